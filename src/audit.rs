@@ -0,0 +1,445 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config;
+use crate::history::{self, ModeChange};
+
+/// Size in bytes at which the audit log is rotated
+pub const DEFAULT_AUDIT_MAX_SIZE: u64 = 1024 * 1024;
+
+/// Rotated logs kept, from `audit.jsonl.1` (newest) to `audit.jsonl.3`
+pub const AUDIT_ROTATIONS: u32 = 3;
+
+// How often `log tail --follow` checks for new records
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What changed the display mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    /// A switch to one or more specifications
+    Switch,
+    /// A switch to a profile
+    Profile,
+    Undo,
+    /// Putting back modes that were not confirmed
+    Revert,
+    SunshineDo,
+    SunshineUndo,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Action::Switch => "switch",
+            Action::Profile => "profile",
+            Action::Undo => "undo",
+            Action::Revert => "revert",
+            Action::SunshineDo => "sunshine-do",
+            Action::SunshineUndo => "sunshine-undo",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    /// Empty if it could not be read
+    pub command_line: Vec<String>,
+}
+
+/// One line of the audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub action: Action,
+    pub user: Option<String>,
+    pub process: ProcessInfo,
+    /// The process that ran display-switch, e.g. a shell or Sunshine
+    pub parent: Option<ProcessInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requested: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub applied: Vec<ModeChange>,
+    /// `SUNSHINE_*` variables, present when run as a Sunshine prep command
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sunshine: BTreeMap<String, String>,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    /// A record of `action` by this process, with the invoking user, processes
+    /// and Sunshine environment filled in
+    pub fn new(action: Action, applied: Vec<ModeChange>, error: Option<String>) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            action,
+            user: ["USER", "USERNAME", "LOGNAME"]
+                .iter()
+                .find_map(|name| std::env::var(name).ok().filter(|v| !v.is_empty())),
+            process: ProcessInfo {
+                pid: std::process::id(),
+                command_line: std::env::args().collect(),
+            },
+            parent: parent_process(),
+            profile: None,
+            requested: Vec::new(),
+            applied,
+            sunshine: sunshine_variables(std::env::vars()),
+            success: error.is_none(),
+            error,
+        }
+    }
+}
+
+/// `2024-05-01 18:30:00 UTC alice profile 'tv': MOCK-1 3840x2160@60hz (from bash)`
+impl fmt::Display for AuditRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            history::format_timestamp(self.timestamp),
+            self.user.as_deref().unwrap_or("?"),
+            self.action
+        )?;
+        if let Some(profile) = &self.profile {
+            write!(f, " '{profile}'")?;
+        } else if !self.requested.is_empty() {
+            write!(f, " {}", self.requested.join(", "))?;
+        }
+        match &self.error {
+            Some(error) => write!(f, ": failed: {error}")?,
+            None => {
+                let applied: Vec<String> = self
+                    .applied
+                    .iter()
+                    .map(|change| match &change.output {
+                        Some(output) => format!("{output} {}", change.mode),
+                        None => change.mode.to_string(),
+                    })
+                    .collect();
+                write!(f, ": {}", applied.join(", "))?;
+            }
+        }
+        let parent = self
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.command_line.first());
+        if let Some(parent) = parent {
+            write!(f, " (from {parent})")?;
+        }
+        Ok(())
+    }
+}
+
+// The `SUNSHINE_*` variables among `vars`
+fn sunshine_variables(
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> BTreeMap<String, String> {
+    vars.into_iter()
+        .filter(|(name, _)| name.starts_with("SUNSHINE_"))
+        .collect()
+}
+
+#[cfg(unix)]
+fn parent_process() -> Option<ProcessInfo> {
+    let pid = std::os::unix::process::parent_id();
+    let command_line = match fs::read(format!("/proc/{pid}/cmdline")) {
+        Ok(cmdline) => cmdline
+            .split(|b| *b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect(),
+        // No procfs, e.g. on macOS
+        Err(_) => std::process::Command::new("ps")
+            .args(["-o", "args=", "-p", &pid.to_string()])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .filter(|args| !args.is_empty())
+            .into_iter()
+            .collect(),
+    };
+    Some(ProcessInfo { pid, command_line })
+}
+
+#[cfg(not(unix))]
+fn parent_process() -> Option<ProcessInfo> {
+    None
+}
+
+/// The append-only JSON Lines log of every display change
+pub struct AuditLog {
+    path: PathBuf,
+    max_size: u64,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf, max_size: u64) -> Self {
+        Self { path, max_size }
+    }
+
+    /// `audit.jsonl` next to the profiles file
+    pub fn default_path(config_file: &Path) -> PathBuf {
+        config_file
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join("audit.jsonl")
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `record` as one line, first rotating the log if it has reached
+    /// its maximum size
+    pub fn append(&self, record: &AuditRecord) -> Result<()> {
+        let _lock = config::lock(&self.path)?;
+        let size = fs::metadata(&self.path).map_or(0, |m| m.len());
+        if size > 0 && size >= self.max_size {
+            self.rotate()?;
+        }
+
+        let mut file = File::options()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open audit log {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    // audit.jsonl.2 -> audit.jsonl.3, audit.jsonl.1 -> audit.jsonl.2, audit.jsonl -> audit.jsonl.1
+    fn rotate(&self) -> Result<()> {
+        for n in (1..AUDIT_ROTATIONS).rev() {
+            let from = config::sibling_path(&self.path, &format!(".{n}"));
+            if from.exists() {
+                fs::rename(
+                    &from,
+                    config::sibling_path(&self.path, &format!(".{}", n + 1)),
+                )?;
+            }
+        }
+        fs::rename(&self.path, config::sibling_path(&self.path, ".1"))?;
+        Ok(())
+    }
+
+    /// The last `count` lines of the log, reaching into rotated logs if the
+    /// current one is shorter, and the offset where the current log ends
+    pub fn tail(&self, count: usize) -> Result<(Vec<String>, u64)> {
+        let mut lines = VecDeque::new();
+        let mut end = 0;
+        for n in 0..=AUDIT_ROTATIONS {
+            let path = match n {
+                0 => self.path.clone(),
+                n => config::sibling_path(&self.path, &format!(".{n}")),
+            };
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            if n == 0 {
+                end = content.len() as u64;
+            }
+            for line in content.lines().rev() {
+                if lines.len() == count {
+                    return Ok((lines.into(), end));
+                }
+                lines.push_front(line.to_string());
+            }
+        }
+        Ok((lines.into(), end))
+    }
+
+    /// Calls `print` for every line appended after `offset`, until an error.
+    /// When the log is rotated, the rest of the old file is read before the new one.
+    pub async fn follow(&self, offset: u64, mut print: impl FnMut(&str)) -> Result<()> {
+        let mut reader = self.open_at(offset)?;
+        let mut pending = String::new();
+        loop {
+            if let Some(reader) = &mut reader {
+                while reader.read_line(&mut pending)? > 0 {
+                    // A line without its newline is still being written
+                    if pending.ends_with('\n') {
+                        print(pending.trim_end());
+                        pending.clear();
+                    }
+                }
+            }
+
+            let rotated = match (&reader, fs::metadata(&self.path)) {
+                (Some(reader), Ok(current)) => !same_file(&reader.get_ref().metadata()?, &current),
+                (None, Ok(_)) => true,
+                (_, Err(_)) => false,
+            };
+            if rotated {
+                reader = self.open_at(0)?;
+                pending.clear();
+            } else {
+                tokio::time::sleep(FOLLOW_INTERVAL).await;
+            }
+        }
+    }
+
+    // `None` until the log is first written
+    fn open_at(&self, offset: u64) -> Result<Option<BufReader<File>>> {
+        match File::open(&self.path) {
+            Ok(mut file) => {
+                file.seek(SeekFrom::Start(offset))?;
+                Ok(Some(BufReader::new(file)))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn same_file(open: &fs::Metadata, current: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    open.dev() == current.dev() && open.ino() == current.ino()
+}
+
+// Without inodes, a file shorter than the one held open must be a new one
+#[cfg(not(unix))]
+fn same_file(open: &fs::Metadata, current: &fs::Metadata) -> bool {
+    current.len() >= open.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::DisplayMode;
+
+    fn record(profile: &str) -> AuditRecord {
+        AuditRecord {
+            profile: Some(profile.to_string()),
+            ..AuditRecord::new(
+                Action::Profile,
+                vec![ModeChange {
+                    output: Some("HDMI-1".to_string()),
+                    previous: None,
+                    mode: DisplayMode {
+                        width: 3840,
+                        height: 2160,
+                        refresh_rate: 60.0,
+                    },
+                }],
+                None,
+            )
+        }
+    }
+
+    #[test]
+    fn test_audit_log_rotation() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let record = record("tv");
+        let line_size = serde_json::to_string(&record)?.len() as u64 + 1;
+        let log = AuditLog::new(dir.path().join("audit.jsonl"), line_size * 2);
+
+        for _ in 0..7 {
+            log.append(&record)?;
+        }
+        // Two records per file, and only AUDIT_ROTATIONS old files are kept
+        assert_eq!(fs::metadata(log.path())?.len(), line_size);
+        assert!(config::sibling_path(log.path(), ".3").exists());
+        assert!(!config::sibling_path(log.path(), ".4").exists());
+
+        let (lines, end) = log.tail(4)?;
+        assert_eq!(lines.len(), 4);
+        assert_eq!(end, line_size);
+        let parsed: AuditRecord = serde_json::from_str(&lines[3])?;
+        assert_eq!(parsed, record);
+        assert!(record
+            .to_string()
+            .contains("profile 'tv': HDMI-1 3840x2160@60hz"));
+        assert_eq!(log.tail(100)?.0.len(), 7);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_follow_reads_through_rotation() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let line_size = serde_json::to_string(&record("a"))?.len() as u64 + 1;
+        let log = AuditLog::new(dir.path().join("audit.jsonl"), line_size * 2);
+        log.append(&record("a"))?;
+        let (_, end) = log.tail(10)?;
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let follow = log.follow(end, |line| {
+            let record: AuditRecord = serde_json::from_str(line).unwrap();
+            sender.send(record.profile.unwrap()).unwrap();
+        });
+        // "c" rotates the log after "b" has been written to the followed file
+        let append = async {
+            for profile in ["b", "c", "d"] {
+                log.append(&record(profile))?;
+            }
+            let mut followed = Vec::new();
+            while followed.len() < 3 {
+                followed.extend(receiver.recv().await);
+            }
+            Ok::<_, anyhow::Error>(followed)
+        };
+        let followed = tokio::select! {
+            // Start following before anything is appended
+            biased;
+            result = follow => return result,
+            followed = tokio::time::timeout(Duration::from_secs(10), append) => followed??,
+        };
+        assert_eq!(followed, ["b", "c", "d"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_captures_environment() {
+        let vars = [
+            ("SUNSHINE_CLIENT_WIDTH", "1920"),
+            ("SUNSHINE_CLIENT_FPS", "120"),
+            ("PATH", "/usr/bin"),
+            ("NOT_SUNSHINE_X", "1"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        assert_eq!(
+            sunshine_variables(vars),
+            BTreeMap::from([
+                ("SUNSHINE_CLIENT_FPS".to_string(), "120".to_string()),
+                ("SUNSHINE_CLIENT_WIDTH".to_string(), "1920".to_string()),
+            ])
+        );
+
+        std::env::set_var("SUNSHINE_AUDIT_TEST", "1");
+        let record = AuditRecord::new(Action::Undo, Vec::new(), None);
+        std::env::remove_var("SUNSHINE_AUDIT_TEST");
+        assert_eq!(
+            record
+                .sunshine
+                .get("SUNSHINE_AUDIT_TEST")
+                .map(String::as_str),
+            Some("1")
+        );
+        assert_eq!(record.process.pid, std::process::id());
+
+        // The test binary is started by cargo, or whatever else runs it
+        #[cfg(unix)]
+        {
+            let parent = record.parent.expect("parent process");
+            assert_eq!(parent.pid, std::os::unix::process::parent_id());
+            assert!(!parent.command_line.is_empty());
+        }
+    }
+}
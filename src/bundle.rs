@@ -0,0 +1,124 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::config::{self, Format};
+use crate::profile::Profile;
+
+/// Identifies a file as a display-switch profile bundle
+pub const BUNDLE_KIND: &str = "display-switch-profiles";
+
+/// Bundle format version written by `profile export`
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// A set of profiles for sharing between machines, written by `profile export`
+/// and read by `profile import`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Bundle {
+    pub kind: String,
+    pub version: u32,
+    /// Version of display-switch that wrote the bundle, for troubleshooting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Bundle {
+    pub fn new(profiles: BTreeMap<String, Profile>) -> Self {
+        Self {
+            kind: BUNDLE_KIND.to_string(),
+            version: BUNDLE_VERSION,
+            generator: Some(format!("display-switch {}", env!("CARGO_PKG_VERSION"))),
+            profiles,
+        }
+    }
+
+    /// Reads a bundle from `path`, or stdin for `-`. TOML is used for `.toml`
+    /// files, JSON otherwise.
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = if path == Path::new("-") {
+            let mut content = String::new();
+            io::stdin()
+                .read_to_string(&mut content)
+                .context("Failed to read bundle from stdin")?;
+            content
+        } else {
+            fs::read_to_string(path)
+                .with_context(|| format!("Failed to read bundle {}", path.display()))?
+        };
+
+        Self::parse(Format::from_path(path), &content)
+            .with_context(|| format!("Invalid profile bundle {}", path.display()))
+    }
+
+    fn parse(format: Format, content: &str) -> Result<Self> {
+        let value = format.parse(content)?;
+        // Check what the file is before complaining about its contents
+        match value.get("kind").and_then(Value::as_str) {
+            Some(BUNDLE_KIND) => {}
+            Some(kind) => return Err(anyhow!("expected kind '{BUNDLE_KIND}', found '{kind}'")),
+            None => {
+                return Err(anyhow!(
+                    "missing kind '{BUNDLE_KIND}'; is this a profiles file rather than an export?"
+                ))
+            }
+        }
+        let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+        if version == 0 || version > u64::from(BUNDLE_VERSION) {
+            return Err(anyhow!(
+                "unsupported bundle version {} (this display-switch reads version {})",
+                value.get("version").unwrap_or(&Value::Null),
+                BUNDLE_VERSION
+            ));
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Writes the bundle to `path` in the format its extension calls for, or
+    /// as JSON to stdout without one
+    pub fn write(&self, path: Option<&Path>) -> Result<()> {
+        match path {
+            Some(path) => {
+                let content = Format::from_path(path).serialize(self)?;
+                config::write_atomically(path, &content)
+                    .with_context(|| format!("Failed to write bundle {}", path.display()))
+            }
+            None => {
+                println!("{}", Format::Json.serialize(self)?);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::StoredSpec;
+
+    #[test]
+    fn test_bundle_round_trip() -> Result<()> {
+        let profiles = BTreeMap::from([(
+            "tv".to_string(),
+            Profile::single(vec![StoredSpec::new("4k@60hz")?]),
+        )]);
+        let bundle = Bundle::new(profiles.clone());
+
+        for format in [Format::Json, Format::Toml] {
+            let read = Bundle::parse(format, &format.serialize(&bundle)?)?;
+            assert_eq!(read.profiles, profiles);
+        }
+
+        let profiles_file = r#"{"version": 2, "profiles": {"tv": ["4k"]}}"#;
+        let error = Bundle::parse(Format::Json, profiles_file).unwrap_err();
+        assert!(error.to_string().contains("missing kind"), "{error}");
+        let newer = format!(r#"{{"kind": "{BUNDLE_KIND}", "version": 99, "profiles": {{}}}}"#);
+        assert!(Bundle::parse(Format::Json, &newer).is_err());
+        Ok(())
+    }
+}
@@ -1,22 +1,67 @@
-use clap::Parser;
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::display::MatchPolicy;
+use crate::export::ExportFormat;
+use crate::parser::{self, Variables};
+use crate::platform::BackendKind;
+use crate::profile::ConflictMode;
+use crate::settings::{OutputFormat, Settings};
 
 #[derive(Parser)]
 #[command(name = "display-switch")]
 #[command(about = "A cross-platform CLI tool for switching and listing display specifications")]
 #[command(version = "0.1.0")]
 pub struct Args {
-    /// Optional spec or profile name (checks profiles first, then treats as spec)
+    /// Optional spec or profile name (checks profiles first, then treats as spec).
+    /// Profile names may be abbreviated; prefix with `profile:` or `spec:` to
+    /// choose explicitly
     #[arg(value_name = "SPEC_OR_PROFILE")]
     pub spec_or_profile: Option<String>,
 
-    /// Display specifications to try (in order of preference)
+    /// Display specifications to try (in order of preference); accepts comma- or `|`-separated lists
     #[arg(short, long, value_name = "SPEC", action = clap::ArgAction::Append)]
     pub spec: Vec<String>,
 
+    /// Read display specifications from a file (one per line, `#` comments), or `-` for stdin
+    #[arg(long, value_name = "PATH")]
+    pub spec_file: Option<PathBuf>,
+
     /// Force exact match instead of closest match
-    #[arg(short, long)]
+    #[arg(short, long, global = true, overrides_with = "no_exact")]
     pub exact: bool,
 
+    /// Allow the closest match even if the `exact` setting is enabled
+    #[arg(long, global = true, overrides_with = "exact")]
+    pub no_exact: bool,
+
+    /// How to pick a refresh rate when the requested one is unavailable
+    #[arg(long, global = true, value_enum)]
+    pub policy: Option<MatchPolicy>,
+
+    /// Treat refresh rates closer than this many Hz as equal
+    #[arg(long, global = true, value_name = "HZ")]
+    pub refresh_tolerance: Option<f64>,
+
+    /// Output to act on (defaults to the primary output)
+    #[arg(short, long, global = true, value_name = "NAME")]
+    pub output: Option<String>,
+
+    /// Output format for commands that support JSON
+    #[arg(long, global = true, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Ask for confirmation after switching and revert after this many seconds without it (0 disables)
+    #[arg(long, global = true, value_name = "SECS")]
+    pub confirm_timeout: Option<u64>,
+
+    /// Display backend to use
+    #[arg(long, global = true, value_enum)]
+    pub backend: Option<BackendKind>,
+
     /// List available display specifications
     #[arg(short, long)]
     pub list: bool,
@@ -29,6 +74,10 @@ pub struct Args {
     #[arg(long, value_name = "NAME")]
     pub create_profile: Option<String>,
 
+    /// Overwrite an existing profile (used with --create-profile)
+    #[arg(long)]
+    pub force: bool,
+
     /// Switch to a named profile
     #[arg(short = 'p', long, value_name = "NAME")]
     pub profile: Option<String>,
@@ -37,33 +86,668 @@ pub struct Args {
     #[arg(long)]
     pub list_profiles: bool,
 
+    /// With --list-profiles, include profiles whose `when` conditions do not hold here
+    #[arg(long, requires = "list_profiles")]
+    pub all: bool,
+
     /// Display current display specification
     #[arg(long)]
     pub current: bool,
+
+    /// Use this profiles file instead of the default (overrides DISPLAY_SWITCH_CONFIG)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Value for `${NAME}` placeholders in profile specifications (repeatable)
+    #[arg(long = "var", global = true, value_name = "NAME=VALUE", value_parser = parse_variable, action = clap::ArgAction::Append)]
+    pub vars: Vec<(String, String)>,
+
+    /// Print extra detail, such as how placeholders were expanded
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Manage settings and inspect the configuration file format
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Manage named profiles
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+    /// Sunshine prep-command integration
+    Sunshine {
+        #[command(subcommand)]
+        action: SunshineAction,
+    },
+    /// Read the audit log of display changes
+    Log {
+        #[command(subcommand)]
+        action: LogAction,
+    },
+    /// List recent switches, newest first
+    History {
+        /// Number of entries to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+
+        /// Output in JSON format
+        #[arg(short, long)]
+        json: bool,
+    },
+    /// Step back through the switch history, restoring the modes from before
+    Undo {
+        /// Number of switches to step back
+        #[arg(default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        steps: usize,
+    },
+    /// Record the connected outputs and their modes to a file, for
+    /// `profile validate --snapshot` or the mock backend
+    Snapshot {
+        /// File to write; defaults to stdout
+        #[arg(short, long, value_name = "PATH")]
+        file: Option<PathBuf>,
+    },
+    /// Convert another display tool's configuration into profiles
+    Import {
+        #[command(subcommand)]
+        source: ImportSource,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum LogAction {
+    /// Print the latest audit records
+    Tail {
+        /// Number of records to print
+        #[arg(short = 'n', long, default_value_t = 10)]
+        lines: usize,
+
+        /// Keep printing records as they are written
+        #[arg(short, long)]
+        follow: bool,
+
+        /// Print the records as JSON Lines
+        #[arg(short, long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ImportSource {
+    /// Import autorandr profiles (`<dir>/<profile>/config` and `setup`)
+    Autorandr {
+        /// autorandr directory (defaults to ~/.config/autorandr)
+        #[arg(long, value_name = "PATH")]
+        dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        conflicts: ConflictArgs,
+    },
+    /// Import the profiles of a kanshi configuration
+    Kanshi {
+        /// kanshi config file (defaults to ~/.config/kanshi/config)
+        #[arg(long, value_name = "PATH")]
+        file: Option<PathBuf>,
+
+        #[command(flatten)]
+        conflicts: ConflictArgs,
+    },
+}
+
+/// What to do with imported profiles whose names are taken
+#[derive(clap::Args)]
+pub struct ConflictArgs {
+    /// Import conflicting profiles as NAME-2, NAME-3, ...
+    #[arg(long, conflicts_with_all = ["overwrite", "skip"])]
+    rename_on_conflict: bool,
+
+    /// Replace existing profiles with the imported ones
+    #[arg(long, conflicts_with = "skip")]
+    overwrite: bool,
+
+    /// Keep existing profiles and import only the new ones
+    #[arg(long)]
+    skip: bool,
+}
+
+impl ConflictArgs {
+    fn mode(&self) -> ConflictMode {
+        if self.rename_on_conflict {
+            ConflictMode::Rename
+        } else if self.overwrite {
+            ConflictMode::Overwrite
+        } else if self.skip {
+            ConflictMode::Skip
+        } else {
+            ConflictMode::Fail
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Print a JSON Schema for profiles.json / profiles.toml, for editor validation
+    Schema,
+    /// Print the effective value of a setting
+    Get { key: String },
+    /// Change a setting in the user profiles file
+    Set { key: String, value: String },
+    /// Remove a setting from the user profiles file, restoring the default
+    Unset { key: String },
+    /// Show every setting with its effective value and where it comes from
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum ProfileAction {
+    /// Create a profile from one or more specifications
+    Create {
+        name: String,
+
+        /// Display specifications to try (in order of preference); accepts comma- or `|`-separated lists
+        #[arg(short, long, value_name = "SPEC", action = clap::ArgAction::Append)]
+        spec: Vec<String>,
+
+        /// Read display specifications from a file (one per line, `#` comments), or `-` for stdin
+        #[arg(long, value_name = "PATH")]
+        spec_file: Option<PathBuf>,
+
+        /// Specifications for a named output or monitor identity (as printed by
+        /// `--list`), e.g. `HDMI-1=4k@60hz,1080p@60hz` or `DEL:A0B2=4k@60hz` (repeatable)
+        #[arg(long, value_name = "OUTPUT=SPECS", value_parser = parse_output_specs, action = clap::ArgAction::Append)]
+        output_spec: Vec<OutputSpecs>,
+
+        /// Like --output-spec, but the output is skipped if it is not connected (repeatable)
+        #[arg(long, value_name = "OUTPUT=SPECS", value_parser = parse_output_specs, action = clap::ArgAction::Append)]
+        optional_output: Vec<OutputSpecs>,
+
+        /// Record the currently connected outputs so `profile auto` picks this profile for them
+        #[arg(long)]
+        match_current: bool,
+
+        /// Overwrite the profile if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Delete a profile
+    Delete { name: String },
+    /// Rename a profile
+    Rename {
+        from: String,
+        to: String,
+
+        /// Overwrite the target profile if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Copy a profile under a new name
+    Copy {
+        from: String,
+        to: String,
+
+        /// Overwrite the target profile if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show a profile's specifications
+    Show {
+        name: String,
+
+        /// Show the profile with `extends` and `includes` applied
+        #[arg(long)]
+        resolved: bool,
+
+        /// Output in JSON format
+        #[arg(short, long)]
+        json: bool,
+    },
+    /// Edit a profile in $EDITOR
+    Edit { name: String },
+    /// Apply the profile whose `match` block best fits the connected outputs
+    Auto,
+    /// Create a profile from the current mode of every connected output
+    Capture {
+        name: String,
+
+        /// Also try the same resolution at any refresh rate, then the same aspect ratio
+        #[arg(long)]
+        with_fallbacks: bool,
+
+        /// Overwrite the profile if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check which specifications of a profile the connected outputs can show
+    Validate {
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        name: Option<String>,
+
+        /// Check every profile that applies here
+        #[arg(long)]
+        all: bool,
+
+        /// Check against outputs recorded by `display-switch snapshot` instead
+        #[arg(long, value_name = "PATH")]
+        snapshot: Option<PathBuf>,
+
+        /// Output in JSON format
+        #[arg(short, long)]
+        json: bool,
+    },
+    /// Write profiles, and the profiles they extend or include, to a bundle file
+    Export {
+        #[arg(required = true)]
+        names: Vec<String>,
+
+        /// File to write (`.toml` for TOML, JSON otherwise); defaults to stdout
+        #[arg(short, long, value_name = "PATH")]
+        file: Option<PathBuf>,
+
+        /// Render a single profile in another tool's configuration syntax instead
+        #[arg(long = "as", value_name = "FORMAT")]
+        render_as: Option<ExportFormat>,
+
+        /// With --as, write the specifications as stored instead of resolving
+        /// them against the connected outputs' modes
+        #[arg(long, requires = "render_as")]
+        no_resolve: bool,
+    },
+    /// Add the profiles from a bundle written by `profile export`
+    Import {
+        /// Bundle file, or `-` for stdin
+        file: PathBuf,
+
+        #[command(flatten)]
+        conflicts: ConflictArgs,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SunshineAction {
+    /// Switch to the connecting client's resolution and frame rate
+    Do {
+        /// Apply this profile instead of the client's resolution and frame rate
+        #[arg(short, long, value_name = "NAME")]
+        profile: Option<String>,
+
+        /// Specifications to try if the client's mode cannot be applied; accepts comma- or `|`-separated lists
+        #[arg(short, long, value_name = "SPEC", action = clap::ArgAction::Append)]
+        fallback: Vec<String>,
+
+        /// Path to sunshine.conf
+        #[arg(long, value_name = "PATH")]
+        sunshine_config: Option<PathBuf>,
+    },
+    /// Restore the display mode saved by `sunshine do`
+    Undo,
+    /// Add display-switch prep commands to apps in Sunshine's apps.json, keeping the previous file as apps.json.bak
+    Install {
+        /// Path to apps.json
+        #[arg(long, value_name = "PATH")]
+        apps_file: Option<PathBuf>,
+
+        /// App to configure (repeatable)
+        #[arg(long, value_name = "NAME", action = clap::ArgAction::Append, required_unless_present = "all")]
+        app: Vec<String>,
+
+        /// Configure every app
+        #[arg(long, conflicts_with = "app")]
+        all: bool,
+
+        /// Apply this profile instead of the client's resolution and frame rate
+        #[arg(short, long, value_name = "NAME", conflicts_with = "uninstall")]
+        profile: Option<String>,
+
+        /// Fallback specifications passed to `sunshine do`
+        #[arg(short, long, value_name = "SPEC", action = clap::ArgAction::Append, conflicts_with = "uninstall")]
+        fallback: Vec<String>,
+
+        /// Remove display-switch prep commands instead of adding them
+        #[arg(long)]
+        uninstall: bool,
+    },
+}
+
+/// Specifications for one named output of a profile
+#[derive(Debug, Clone)]
+pub struct OutputSpecs {
+    pub output: String,
+    pub specs: Vec<String>,
+    pub optional: bool,
+}
+
+fn parse_variable(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected NAME=VALUE, got '{value}'")),
+    }
+}
+
+fn parse_output_specs(value: &str) -> Result<OutputSpecs, String> {
+    match value.split_once('=') {
+        Some((output, specs)) if !output.trim().is_empty() => Ok(OutputSpecs {
+            output: output.trim().to_string(),
+            specs: parser::split_spec_list(specs),
+            optional: false,
+        }),
+        _ => Err(format!("expected OUTPUT=SPECS, got '{value}'")),
+    }
 }
 
 // Convert the flat args structure to the enum used by main
 pub enum ParsedArgs {
-    Switch { spec: Vec<String>, exact: bool },
-    List { spec: Option<String>, json: bool },
-    CreateProfile { name: String, spec: Vec<String> },
-    Profile { name: String },
-    ListProfiles,
-    Current { json: bool },
+    Switch {
+        spec: Vec<String>,
+    },
+    List {
+        spec: Option<String>,
+        json: bool,
+    },
+    CreateProfile {
+        name: String,
+        spec: Vec<String>,
+        outputs: Vec<OutputSpecs>,
+        match_current: bool,
+        force: bool,
+    },
+    DeleteProfile {
+        name: String,
+    },
+    RenameProfile {
+        from: String,
+        to: String,
+        force: bool,
+    },
+    CopyProfile {
+        from: String,
+        to: String,
+        force: bool,
+    },
+    ShowProfile {
+        name: String,
+        resolved: bool,
+        json: bool,
+    },
+    EditProfile {
+        name: String,
+    },
+    Profile {
+        name: String,
+    },
+    AutoProfile,
+    CaptureProfile {
+        name: String,
+        with_fallbacks: bool,
+        force: bool,
+    },
+    ExportProfiles {
+        names: Vec<String>,
+        file: Option<PathBuf>,
+        render_as: Option<ExportFormat>,
+        no_resolve: bool,
+    },
+    ImportProfiles {
+        file: PathBuf,
+        conflicts: ConflictMode,
+    },
+    ValidateProfiles {
+        /// `None` checks every profile
+        name: Option<String>,
+        snapshot: Option<PathBuf>,
+        json: bool,
+    },
+    Snapshot {
+        file: Option<PathBuf>,
+    },
+    History {
+        limit: usize,
+        json: bool,
+    },
+    LogTail {
+        lines: usize,
+        follow: bool,
+        json: bool,
+    },
+    Undo {
+        steps: usize,
+    },
+    ImportAutorandr {
+        dir: Option<PathBuf>,
+        conflicts: ConflictMode,
+    },
+    ImportKanshi {
+        file: Option<PathBuf>,
+        conflicts: ConflictMode,
+    },
+    ListProfiles {
+        all: bool,
+    },
+    Current {
+        json: bool,
+    },
     // New variant for handling the positional argument that could be either
-    SpecOrProfile { value: String, exact: bool },
+    SpecOrProfile {
+        value: String,
+    },
+    SunshineDo {
+        profile: Option<String>,
+        fallback: Vec<String>,
+        sunshine_config: Option<PathBuf>,
+    },
+    SunshineUndo,
+    ConfigSchema,
+    ConfigGet {
+        key: String,
+    },
+    ConfigSet {
+        key: String,
+        value: String,
+    },
+    ConfigUnset {
+        key: String,
+    },
+    ConfigList,
+    SunshineInstall {
+        apps_file: Option<PathBuf>,
+        apps: Option<Vec<String>>,
+        profile: Option<String>,
+        fallback: Vec<String>,
+        uninstall: bool,
+    },
 }
 
 impl Args {
-    pub fn into_parsed_args(self) -> ParsedArgs {
-        if self.current {
+    /// Settings given on the command line, which take precedence over the config files
+    pub fn settings_overrides(&self) -> Settings {
+        Settings {
+            exact: match (self.exact, self.no_exact) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            policy: self.policy,
+            refresh_tolerance: self.refresh_tolerance,
+            output: self.output.clone(),
+            format: self.format,
+            confirm_timeout: self.confirm_timeout,
+            backend: self.backend,
+            ..Default::default()
+        }
+    }
+
+    /// Placeholder values given with `--var`
+    pub fn variables(&self) -> Variables {
+        Variables::new(self.vars.iter().cloned().collect())
+    }
+
+    pub fn into_parsed_args(mut self) -> Result<ParsedArgs> {
+        if let Some(command) = self.command {
+            return Ok(match command {
+                Command::Config { action } => match action {
+                    ConfigAction::Schema => ParsedArgs::ConfigSchema,
+                    ConfigAction::Get { key } => ParsedArgs::ConfigGet { key },
+                    ConfigAction::Set { key, value } => ParsedArgs::ConfigSet { key, value },
+                    ConfigAction::Unset { key } => ParsedArgs::ConfigUnset { key },
+                    ConfigAction::List => ParsedArgs::ConfigList,
+                },
+                Command::Profile { action } => match action {
+                    ProfileAction::Create {
+                        name,
+                        spec,
+                        spec_file,
+                        output_spec,
+                        optional_output,
+                        match_current,
+                        force,
+                    } => ParsedArgs::CreateProfile {
+                        name,
+                        spec: expand_specs(&spec, spec_file.as_deref())?,
+                        outputs: output_spec
+                            .into_iter()
+                            .chain(optional_output.into_iter().map(|output| OutputSpecs {
+                                optional: true,
+                                ..output
+                            }))
+                            .collect(),
+                        match_current,
+                        force,
+                    },
+                    ProfileAction::Delete { name } => ParsedArgs::DeleteProfile { name },
+                    ProfileAction::Rename { from, to, force } => {
+                        ParsedArgs::RenameProfile { from, to, force }
+                    }
+                    ProfileAction::Copy { from, to, force } => {
+                        ParsedArgs::CopyProfile { from, to, force }
+                    }
+                    ProfileAction::Show {
+                        name,
+                        resolved,
+                        json,
+                    } => ParsedArgs::ShowProfile {
+                        name,
+                        resolved,
+                        json,
+                    },
+                    ProfileAction::Edit { name } => ParsedArgs::EditProfile { name },
+                    ProfileAction::Auto => ParsedArgs::AutoProfile,
+                    ProfileAction::Capture {
+                        name,
+                        with_fallbacks,
+                        force,
+                    } => ParsedArgs::CaptureProfile {
+                        name,
+                        with_fallbacks,
+                        force,
+                    },
+                    ProfileAction::Export {
+                        names,
+                        file,
+                        render_as,
+                        no_resolve,
+                    } => ParsedArgs::ExportProfiles {
+                        names,
+                        file,
+                        render_as,
+                        no_resolve,
+                    },
+                    ProfileAction::Validate {
+                        name,
+                        all: _,
+                        snapshot,
+                        json,
+                    } => ParsedArgs::ValidateProfiles {
+                        name,
+                        snapshot,
+                        json,
+                    },
+                    ProfileAction::Import { file, conflicts } => ParsedArgs::ImportProfiles {
+                        file,
+                        conflicts: conflicts.mode(),
+                    },
+                },
+                Command::Sunshine { action } => match action {
+                    SunshineAction::Do {
+                        profile,
+                        fallback,
+                        sunshine_config,
+                    } => ParsedArgs::SunshineDo {
+                        profile,
+                        fallback: fallback
+                            .iter()
+                            .flat_map(|s| parser::split_spec_list(s))
+                            .collect(),
+                        sunshine_config,
+                    },
+                    SunshineAction::Undo => ParsedArgs::SunshineUndo,
+                    SunshineAction::Install {
+                        apps_file,
+                        app,
+                        all,
+                        profile,
+                        fallback,
+                        uninstall,
+                    } => ParsedArgs::SunshineInstall {
+                        apps_file,
+                        apps: if all { None } else { Some(app) },
+                        profile,
+                        fallback: fallback
+                            .iter()
+                            .flat_map(|s| parser::split_spec_list(s))
+                            .collect(),
+                        uninstall,
+                    },
+                },
+                Command::Snapshot { file } => ParsedArgs::Snapshot { file },
+                Command::History { limit, json } => ParsedArgs::History { limit, json },
+                Command::Log {
+                    action:
+                        LogAction::Tail {
+                            lines,
+                            follow,
+                            json,
+                        },
+                } => ParsedArgs::LogTail {
+                    lines,
+                    follow,
+                    json,
+                },
+                Command::Undo { steps } => ParsedArgs::Undo { steps },
+                Command::Import { source } => match source {
+                    ImportSource::Autorandr { dir, conflicts } => ParsedArgs::ImportAutorandr {
+                        dir,
+                        conflicts: conflicts.mode(),
+                    },
+                    ImportSource::Kanshi { file, conflicts } => ParsedArgs::ImportKanshi {
+                        file,
+                        conflicts: conflicts.mode(),
+                    },
+                },
+            });
+        }
+
+        self.spec = expand_specs(&self.spec, self.spec_file.as_deref())?;
+
+        Ok(if self.current {
             ParsedArgs::Current { json: self.json }
         } else if self.list_profiles {
-            ParsedArgs::ListProfiles
+            ParsedArgs::ListProfiles { all: self.all }
         } else if let Some(name) = self.create_profile {
             ParsedArgs::CreateProfile {
                 name,
                 spec: self.spec,
+                outputs: Vec::new(),
+                match_current: false,
+                force: self.force,
             }
         } else if let Some(name) = self.profile {
             ParsedArgs::Profile { name }
@@ -75,24 +759,45 @@ impl Args {
         } else if let Some(value) = self.spec_or_profile {
             // If we have a positional argument and no explicit specs, treat it as spec_or_profile
             if self.spec.is_empty() {
-                ParsedArgs::SpecOrProfile {
-                    value,
-                    exact: self.exact,
-                }
+                ParsedArgs::SpecOrProfile { value }
+            } else if value.starts_with("profile:") {
+                return Err(anyhow!("{value} cannot be combined with --spec"));
             } else {
                 // If we have both positional and --spec args, combine them
-                let mut all_specs = vec![value];
+                let value = value.strip_prefix("spec:").unwrap_or(&value);
+                let mut all_specs = parser::split_spec_list(value);
                 all_specs.extend(self.spec);
-                ParsedArgs::Switch {
-                    spec: all_specs,
-                    exact: self.exact,
-                }
+                ParsedArgs::Switch { spec: all_specs }
             }
         } else {
-            ParsedArgs::Switch {
-                spec: self.spec,
-                exact: self.exact,
-            }
-        }
+            ParsedArgs::Switch { spec: self.spec }
+        })
     }
 }
+
+// Expand inline fallback lists and append any specs read from --spec-file
+fn expand_specs(specs: &[String], spec_file: Option<&Path>) -> Result<Vec<String>> {
+    let mut expanded: Vec<String> = specs
+        .iter()
+        .flat_map(|s| parser::split_spec_list(s))
+        .collect();
+    if let Some(path) = spec_file {
+        expanded.extend(read_spec_file(path)?);
+    }
+    Ok(expanded)
+}
+
+fn read_spec_file(path: &Path) -> Result<Vec<String>> {
+    let content = if path == Path::new("-") {
+        let mut content = String::new();
+        io::stdin()
+            .read_to_string(&mut content)
+            .context("Failed to read display specifications from stdin")?;
+        content
+    } else {
+        fs::read_to_string(path)
+            .with_context(|| format!("Failed to read spec file {}", path.display()))?
+    };
+
+    Ok(parser::parse_spec_file(&content))
+}
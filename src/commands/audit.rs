@@ -0,0 +1,33 @@
+use anyhow::Result;
+
+use crate::audit::{AuditLog, AuditRecord};
+
+pub async fn handle_log_tail(
+    audit: &AuditLog,
+    lines: usize,
+    follow: bool,
+    json: bool,
+) -> Result<()> {
+    let print = |line: &str| {
+        if json {
+            println!("{line}");
+        } else {
+            // Lines from other versions are shown as written
+            match serde_json::from_str::<AuditRecord>(line) {
+                Ok(record) => println!("{record}"),
+                Err(_) => println!("{line}"),
+            }
+        }
+    };
+
+    let (tail, end) = audit.tail(lines)?;
+    if tail.is_empty() && !follow {
+        eprintln!("No audit records in {}", audit.path().display());
+    }
+    tail.iter().for_each(|line| print(line));
+
+    if follow {
+        audit.follow(end, print).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+
+use crate::config;
+use crate::profile::{Layer, ProfileManager};
+use crate::settings;
+
+pub fn handle_config_schema() -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&config::json_schema())?);
+    Ok(())
+}
+
+pub fn handle_config_get(profile_manager: &ProfileManager, key: &str) -> Result<()> {
+    if let Some(value) = profile_manager.settings().get(key)? {
+        println!("{value}");
+    }
+    Ok(())
+}
+
+pub fn handle_config_set(
+    profile_manager: &mut ProfileManager,
+    key: &str,
+    value: String,
+) -> Result<()> {
+    let mut stored = None;
+    profile_manager.update_settings(|settings| {
+        settings.set(key, &value)?;
+        stored = settings.get(key)?;
+        Ok(())
+    })?;
+    println!("Set {key} = {}", stored.unwrap_or(value));
+    Ok(())
+}
+
+pub fn handle_config_unset(profile_manager: &mut ProfileManager, key: &str) -> Result<()> {
+    profile_manager.update_settings(|settings| settings.unset(key))?;
+    println!("Unset {key}");
+    Ok(())
+}
+
+pub fn handle_config_list(profile_manager: &ProfileManager, json: bool) -> Result<()> {
+    let effective = profile_manager.settings();
+    let user = profile_manager.layer_settings(Layer::User);
+    let system = profile_manager.layer_settings(Layer::System);
+
+    let mut entries = Vec::new();
+    for key in settings::KEYS {
+        let source = if user.get(key)?.is_some() {
+            "user"
+        } else if system.get(key)?.is_some() {
+            "system"
+        } else {
+            "default"
+        };
+        entries.push((key, effective.get(key)?, source));
+    }
+
+    if json {
+        let value: serde_json::Map<_, _> = entries
+            .into_iter()
+            .map(|(key, value, source)| {
+                (
+                    key.to_string(),
+                    serde_json::json!({ "value": value, "source": source }),
+                )
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&value)?);
+    } else {
+        for (key, value, source) in entries {
+            match value {
+                Some(value) => println!("{key} = {value} ({source})"),
+                None => println!("{key} is not set"),
+            }
+        }
+    }
+
+    Ok(())
+}
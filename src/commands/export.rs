@@ -0,0 +1,148 @@
+use anyhow::Result;
+use std::path::Path;
+
+use super::Expansion;
+use crate::bundle::Bundle;
+use crate::config;
+use crate::display::DisplayManager;
+use crate::error::Error;
+use crate::export::{self, ExportFormat, ExportedOutput};
+use crate::profile::ProfileManager;
+use crate::settings::Settings;
+
+pub fn handle_export_profiles(
+    profile_manager: &ProfileManager,
+    names: &[String],
+    file: Option<&Path>,
+) -> Result<()> {
+    let (profiles, referenced) = profile_manager.export_profiles(names)?;
+    for name in &referenced {
+        eprintln!("Also exporting '{name}', which the selected profiles extend or include");
+    }
+
+    let count = profiles.len();
+    Bundle::new(profiles).write(file)?;
+    if let Some(file) = file {
+        println!("Exported {} profile(s) to {}", count, file.display());
+    }
+    Ok(())
+}
+
+/// Renders one profile for another tool, to `file` or standard output
+pub async fn handle_render_profile(
+    display_manager: Option<&DisplayManager>,
+    profile_manager: &ProfileManager,
+    settings: &Settings,
+    expansion: &Expansion,
+    names: &[String],
+    format: ExportFormat,
+    file: Option<&Path>,
+) -> Result<()> {
+    let [name] = names else {
+        return Err(anyhow::anyhow!("--as renders exactly one profile"));
+    };
+    let rendered = render_profile(
+        display_manager,
+        profile_manager,
+        settings,
+        expansion,
+        name,
+        format,
+    )
+    .await?;
+    match file {
+        Some(file) => {
+            config::write_atomically(file, &rendered)?;
+            println!("Exported profile '{}' to {}", name, file.display());
+        }
+        None => print!("{rendered}"),
+    }
+    Ok(())
+}
+
+// Renders a resolved profile for another tool. With a display manager each
+// entry's specs are resolved to the mode they would switch to; without one
+// the first spec is used as written and must name a resolution.
+async fn render_profile(
+    display_manager: Option<&DisplayManager>,
+    profile_manager: &ProfileManager,
+    settings: &Settings,
+    expansion: &Expansion,
+    name: &str,
+    format: ExportFormat,
+) -> Result<String> {
+    let profile = profile_manager.resolved_profile(name)?;
+    let connected = match display_manager {
+        Some(display_manager) => display_manager.list_outputs().await?,
+        None => Vec::new(),
+    };
+
+    let mut outputs = Vec::new();
+    for entry in &profile.outputs {
+        let specs = expansion.expand_specs(&entry.specs)?;
+        let Some(display_manager) = display_manager else {
+            if entry.output.is_none() && entry.monitor.is_some() {
+                return Err(anyhow::anyhow!(
+                    "Monitor '{}' has no connector name to export; drop --no-resolve to look it up",
+                    entry
+                ));
+            }
+            let spec = &specs[0];
+            let (Some(width), Some(height)) = (spec.width, spec.height) else {
+                return Err(anyhow::anyhow!(
+                    "'{}' for {} has no fixed resolution; drop --no-resolve to resolve it",
+                    spec,
+                    entry
+                ));
+            };
+            outputs.push(ExportedOutput {
+                name: entry.output.clone(),
+                width,
+                height,
+                refresh_rate: spec.refresh_rate,
+            });
+            continue;
+        };
+
+        let target = if entry.is_default_output() {
+            connected.iter().find(|o| o.primary)
+        } else {
+            entry.find_output(&connected)
+        };
+        let Some(target) = target else {
+            if entry.optional {
+                eprintln!("Warning: skipping {entry}, which is not connected");
+                continue;
+            }
+            return Err(
+                Error::OutputNotFound(format!("Output '{}' is not connected", entry)).into(),
+            );
+        };
+
+        let mut resolved = None;
+        for spec in &specs {
+            if let Ok(mode) = display_manager
+                .resolve_mode(Some(&target.name), spec, &settings.match_options())
+                .await
+            {
+                resolved = Some(mode);
+                break;
+            }
+        }
+        let mode = resolved.ok_or_else(|| {
+            anyhow::anyhow!(
+                "None of the specifications for {} match a mode of {}",
+                entry,
+                target.name
+            )
+        })?;
+        outputs.push(ExportedOutput {
+            name: Some(target.name.clone()),
+            width: mode.width,
+            height: mode.height,
+            refresh_rate: Some(mode.refresh_rate),
+        });
+    }
+
+    export::render(format, name, &outputs)
+}
@@ -0,0 +1,105 @@
+use anyhow::Result;
+
+use super::Journal;
+use crate::audit::{Action, AuditRecord};
+use crate::display::DisplayManager;
+use crate::history::{self, History, HistoryEntry, ModeChange, Request};
+
+pub fn handle_history(history: &History, limit: usize, json: bool) -> Result<()> {
+    let mut entries = history.entries()?;
+    entries.reverse();
+    entries.truncate(limit);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("No switches recorded yet.");
+        return Ok(());
+    }
+
+    for entry in &entries {
+        let outcome = match (&entry.error, entry.undone) {
+            (Some(error), _) => format!("failed: {error}"),
+            (None, true) => "undone".to_string(),
+            (None, false) => "ok".to_string(),
+        };
+        println!(
+            "#{} {} {} ({outcome})",
+            entry.id,
+            history::format_timestamp(entry.timestamp),
+            entry.request
+        );
+        for change in &entry.changes {
+            let output = change.output.as_deref().unwrap_or("default");
+            match &change.previous {
+                Some(previous) => println!("  {output}: {previous} -> {}", change.mode),
+                None => println!("  {output}: {}", change.mode),
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn handle_undo(
+    display_manager: &DisplayManager,
+    journal: &Journal,
+    steps: usize,
+) -> Result<()> {
+    let history = &journal.history;
+    let undone = history.undo_steps(steps)?;
+    let targets = history::undo_targets(&undone);
+    if targets.is_empty() {
+        anyhow::bail!("The previous display modes of these switches were not recorded");
+    }
+
+    let mut changes = Vec::new();
+    let mut result = Ok(());
+    for (output, mode) in targets {
+        let previous = display_manager
+            .get_current_display_mode(output.as_deref())
+            .await
+            .ok();
+        if let Err(e) = display_manager
+            .set_display_mode(output.as_deref(), &mode)
+            .await
+        {
+            result = Err(e);
+            break;
+        }
+        changes.push(ModeChange {
+            output,
+            previous,
+            mode,
+        });
+    }
+
+    let error = result.as_ref().err().map(|e| format!("{e:#}"));
+    journal.record_audit(AuditRecord {
+        requested: undone
+            .iter()
+            .map(|entry| format!("#{} {}", entry.id, entry.request))
+            .collect(),
+        ..AuditRecord::new(Action::Undo, changes.clone(), error.clone())
+    });
+    journal.record_history(HistoryEntry::new(
+        Request::Undo(steps),
+        changes.clone(),
+        error,
+    ));
+    result?;
+    let ids: Vec<u64> = undone.iter().map(|entry| entry.id).collect();
+    journal.update_history(history.mark_undone(&ids));
+
+    for change in &changes {
+        match &change.output {
+            Some(output) => println!(
+                "Restored {output} to display specification: {}",
+                change.mode
+            ),
+            None => println!("Restored display specification: {}", change.mode),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::bundle::Bundle;
+use crate::import;
+use crate::profile::{ConflictMode, ImportOutcome, ProfileManager};
+
+pub fn handle_import_profiles(
+    profile_manager: &mut ProfileManager,
+    file: &Path,
+    conflicts: ConflictMode,
+) -> Result<()> {
+    let bundle = Bundle::read(file)?;
+    let outcomes = profile_manager.import_profiles(bundle.profiles, conflicts)?;
+    print_import_outcomes(outcomes);
+    Ok(())
+}
+
+pub fn handle_import_autorandr(
+    profile_manager: &mut ProfileManager,
+    dir: Option<PathBuf>,
+    conflicts: ConflictMode,
+) -> Result<()> {
+    let dir = match dir {
+        Some(dir) => dir,
+        None => import::default_autorandr_dir()?,
+    };
+    let imported = import::autorandr(&dir)?;
+    handle_import_foreign(profile_manager, imported, conflicts)
+}
+
+pub fn handle_import_kanshi(
+    profile_manager: &mut ProfileManager,
+    file: Option<PathBuf>,
+    conflicts: ConflictMode,
+) -> Result<()> {
+    let file = match file {
+        Some(file) => file,
+        None => import::default_kanshi_config()?,
+    };
+    let content = std::fs::read_to_string(&file)
+        .with_context(|| format!("Failed to read kanshi config {}", file.display()))?;
+    let imported = import::kanshi(&content)?;
+    handle_import_foreign(profile_manager, imported, conflicts)
+}
+
+fn handle_import_foreign(
+    profile_manager: &mut ProfileManager,
+    imported: import::Imported,
+    conflicts: ConflictMode,
+) -> Result<()> {
+    // Reported up front so they are seen even if the import is refused
+    for note in &imported.notes {
+        eprintln!("Warning: {note}");
+    }
+    let outcomes = profile_manager.import_profiles(imported.profiles, conflicts)?;
+    if outcomes.is_empty() {
+        println!("No profiles could be imported.");
+    }
+    print_import_outcomes(outcomes);
+    Ok(())
+}
+
+fn print_import_outcomes(outcomes: Vec<ImportOutcome>) {
+    for outcome in outcomes {
+        match outcome {
+            ImportOutcome::Added(name) => println!("Imported profile: {name}"),
+            ImportOutcome::Renamed { from, to } => {
+                println!("Imported profile: {from} as {to} ('{from}' already exists)")
+            }
+            ImportOutcome::Overwritten(name) => println!("Replaced profile: {name}"),
+            ImportOutcome::Skipped(name) => println!("Skipped profile: {name} (already exists)"),
+        }
+    }
+}
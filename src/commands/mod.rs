@@ -0,0 +1,269 @@
+//! Command handlers, one module per command group. `main` parses the
+//! arguments, loads the profiles and settings and dispatches here.
+
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::audit::{Action, AuditLog, AuditRecord};
+use crate::cli::ParsedArgs;
+use crate::display::{AppliedMode, DisplayManager, DisplaySpec, OutputTarget};
+use crate::history::{History, HistoryEntry, ModeChange, Request};
+use crate::hooks::SwitchHooks;
+use crate::parser::{self, Variables};
+use crate::profile::StoredSpec;
+use crate::settings::Settings;
+
+pub mod audit;
+pub mod config;
+pub mod export;
+pub mod history;
+pub mod import;
+pub mod profile;
+pub mod sunshine;
+pub mod switch;
+pub mod validate;
+
+/// Values for placeholders in profile specs, and whether to show how they were filled in
+pub struct Expansion {
+    pub variables: Variables,
+    pub verbose: bool,
+}
+
+impl Expansion {
+    pub fn expand_specs(&self, specs: &[StoredSpec]) -> Result<Vec<DisplaySpec>> {
+        specs
+            .iter()
+            .map(|spec| {
+                let expanded = spec.expand(&self.variables)?;
+                if self.verbose && spec.has_variables() {
+                    eprintln!("Expanded '{spec}' to '{expanded}'");
+                }
+                parser::parse_display_spec(&expanded)
+            })
+            .collect()
+    }
+}
+
+// Where display changes are recorded: the history `undo` steps through, the
+// audit log, and the mode `sunshine do` saves for `sunshine undo`
+pub struct Journal {
+    pub history: History,
+    pub audit: AuditLog,
+    pub sunshine_state: PathBuf,
+    // The switch being attempted, until its outcome is audited
+    attempt: RefCell<Option<AuditRecord>>,
+}
+
+// Records are a convenience; failing to write them must not fail a switch
+impl Journal {
+    /// The journal kept beside `config_file`, with the audit log where `settings` put it
+    pub fn beside(config_file: &Path, settings: &Settings) -> Self {
+        Self {
+            history: History::beside(config_file),
+            audit: settings.audit_log(config_file),
+            sunshine_state: crate::sunshine::state_file_beside(config_file),
+            attempt: RefCell::new(None),
+        }
+    }
+
+    /// Notes the switch about to be attempted, so that `record_failed_attempt`
+    /// can audit it if it fails before its outcome is recorded
+    pub fn attempt(&self, record: AuditRecord) {
+        self.attempt.replace(Some(record));
+    }
+
+    /// Audits the attempted switch as failed with `error`, unless its outcome
+    /// has already been recorded
+    pub fn record_failed_attempt(&self, error: &anyhow::Error) {
+        if let Some(record) = self.attempt.take() {
+            self.record_audit(AuditRecord {
+                success: false,
+                error: Some(format!("{error:#}")),
+                ..record
+            });
+        }
+    }
+
+    pub fn record_history(&self, entry: HistoryEntry) {
+        self.update_history(self.history.record(entry));
+    }
+
+    // For the result of any other change to the history
+    pub fn update_history(&self, result: Result<()>) {
+        if let Err(e) = result {
+            eprintln!("Warning: failed to record switch history: {e:#}");
+        }
+    }
+
+    pub fn record_audit(&self, record: AuditRecord) {
+        self.attempt.take();
+        if let Err(e) = self.audit.append(&record) {
+            eprintln!(
+                "Warning: failed to write audit log {}: {e:#}",
+                self.audit.path().display()
+            );
+        }
+    }
+}
+
+/// The audit record for the switch `args` asks for, before anything is
+/// known about its outcome; `None` for commands that do not switch
+pub fn attempted_switch(args: &ParsedArgs) -> Option<AuditRecord> {
+    let attempt = |action| AuditRecord::new(action, Vec::new(), None);
+    Some(match args {
+        ParsedArgs::Switch { spec } => AuditRecord {
+            requested: spec.clone(),
+            ..attempt(Action::Switch)
+        },
+        // Until it is known whether `value` names a profile
+        ParsedArgs::SpecOrProfile { value } => AuditRecord {
+            requested: vec![value.clone()],
+            ..attempt(Action::Switch)
+        },
+        ParsedArgs::Profile { name } => AuditRecord {
+            profile: Some(name.clone()),
+            ..attempt(Action::Profile)
+        },
+        ParsedArgs::AutoProfile => attempt(Action::Profile),
+        ParsedArgs::Undo { .. } => attempt(Action::Undo),
+        ParsedArgs::SunshineDo { profile, .. } => AuditRecord {
+            profile: profile.clone(),
+            ..attempt(Action::SunshineDo)
+        },
+        ParsedArgs::SunshineUndo => attempt(Action::SunshineUndo),
+        _ => return None,
+    })
+}
+
+// Runs the switch between the pre_switch and post_switch hooks, or runs
+// on_failure if it does not happen, and records it in the journal
+pub async fn switch_with_hooks(
+    display_manager: &DisplayManager,
+    settings: &Settings,
+    hooks: &SwitchHooks,
+    journal: &Journal,
+    request: Request,
+    targets: &[OutputTarget],
+) -> Result<Vec<AppliedMode>> {
+    let options = settings.match_options();
+    let result = match hooks.pre_switch(display_manager, targets, &options).await {
+        Ok(()) => {
+            let switched = display_manager.switch_outputs(targets, &options).await;
+            match hooks.profile() {
+                Some(name) => switched.with_context(|| {
+                    format!(
+                        "No suitable display specification in profile '{name}' could be applied"
+                    )
+                }),
+                None => switched,
+            }
+        }
+        Err(e) => Err(e),
+    };
+
+    let (changes, error) = match &result {
+        Ok(applied) => (applied.iter().map(ModeChange::from).collect(), None),
+        Err(e) => (Vec::new(), Some(format!("{e:#}"))),
+    };
+    let action = match &request {
+        Request::Profile(_) => Action::Profile,
+        _ => Action::Switch,
+    };
+    journal.record_audit(AuditRecord {
+        profile: hooks.profile().map(str::to_string),
+        requested: targets
+            .iter()
+            .flat_map(|target| {
+                target.specs.iter().map(|spec| match &target.output {
+                    Some(output) => format!("{output}={spec}"),
+                    None => spec.to_string(),
+                })
+            })
+            .collect(),
+        ..AuditRecord::new(action, changes.clone(), error.clone())
+    });
+    journal.record_history(HistoryEntry::new(request, changes, error));
+
+    match result {
+        Ok(applied) => {
+            hooks.post_switch(display_manager, &applied).await;
+            Ok(applied)
+        }
+        Err(e) => {
+            hooks.on_failure(&e).await;
+            Err(e)
+        }
+    }
+}
+
+// When the confirm timeout is enabled, keeps the new modes only if the user
+// answers "y" in time, so a mode the monitor cannot show is undone without any input
+pub async fn confirm_or_revert(
+    display_manager: &DisplayManager,
+    settings: &Settings,
+    journal: &Journal,
+    applied: &[AppliedMode],
+) -> Result<()> {
+    let timeout = settings.confirm_timeout();
+    if timeout == 0 {
+        return Ok(());
+    }
+
+    eprint!("Keep this display mode? [y/N] (reverting in {timeout}s) ");
+
+    // A plain thread so a pending read does not keep the runtime alive on timeout
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    std::thread::spawn(move || {
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line).is_ok() {
+            let _ = sender.send(line);
+        }
+    });
+
+    let keep = match tokio::time::timeout(Duration::from_secs(timeout), receiver).await {
+        Ok(Ok(answer)) => matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes"),
+        _ => {
+            eprintln!();
+            false
+        }
+    };
+    if keep {
+        return Ok(());
+    }
+
+    let reverted = display_manager.restore(applied).await;
+    let reverse: Vec<ModeChange> = applied
+        .iter()
+        .filter_map(|change| {
+            Some(ModeChange {
+                output: change.output.clone(),
+                previous: Some(change.mode.clone()),
+                mode: change.previous.clone()?,
+            })
+        })
+        .collect();
+    let error = reverted.as_ref().err().map(|e| format!("{e:#}"));
+    journal.record_audit(AuditRecord::new(
+        Action::Revert,
+        reverse.clone(),
+        error.clone(),
+    ));
+    journal.record_history(HistoryEntry::new(Request::Revert, reverse, error));
+    reverted?;
+    let changes: Vec<_> = applied.iter().map(ModeChange::from).collect();
+    journal.update_history(
+        journal
+            .history
+            .mark_reverted(&changes, "Not confirmed; reverted"),
+    );
+    let restored: Vec<_> = applied
+        .iter()
+        .filter_map(|change| change.previous.as_ref().map(ToString::to_string))
+        .collect();
+    anyhow::bail!(
+        "Not confirmed; reverted to display specification: {}",
+        restored.join(", ")
+    );
+}
@@ -0,0 +1,402 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use super::{confirm_or_revert, switch_with_hooks, Expansion, Journal};
+use crate::audit::{Action, AuditRecord};
+use crate::cli::OutputSpecs;
+use crate::config;
+use crate::display::{DisplayManager, OutputTarget};
+use crate::error::Error;
+use crate::history::Request;
+use crate::hooks::SwitchHooks;
+use crate::parser::Variables;
+use crate::profile::{self, MatchRule, OutputEntry, Profile, ProfileManager, StoredSpec};
+use crate::settings::Settings;
+
+pub fn handle_create_profile(
+    profile_manager: &mut ProfileManager,
+    name: String,
+    specs: Vec<String>,
+    outputs: Vec<OutputSpecs>,
+    match_rule: Option<MatchRule>,
+    force: bool,
+) -> Result<()> {
+    if outputs.is_empty() && match_rule.is_none() {
+        profile_manager.create_profile(name.clone(), &specs, force)?;
+    } else {
+        // --spec entries apply to the default output alongside the named ones
+        let mut entries = Vec::new();
+        if !specs.is_empty() {
+            entries.push(OutputEntry::new(None, &specs, false)?);
+        }
+        for output in outputs {
+            let entry = OutputEntry::new(None, &output.specs, output.optional)?;
+            // Connector names never contain ':', monitor identities always do
+            entries.push(if output.output.contains(':') {
+                OutputEntry {
+                    monitor: Some(output.output),
+                    ..entry
+                }
+            } else {
+                OutputEntry {
+                    output: Some(output.output),
+                    ..entry
+                }
+            });
+        }
+        if let Some(rule) = &match_rule {
+            println!("Matching connected outputs: {}", rule.outputs.join(", "));
+        }
+        let profile = Profile {
+            outputs: entries,
+            match_rule,
+            ..Default::default()
+        };
+        profile_manager.save_profile(name.clone(), profile, force)?;
+    }
+
+    println!("Created profile: {name}");
+    Ok(())
+}
+
+pub async fn handle_capture_profile(
+    display_manager: &DisplayManager,
+    profile_manager: &mut ProfileManager,
+    name: String,
+    with_fallbacks: bool,
+    force: bool,
+) -> Result<()> {
+    let outputs: Vec<_> = display_manager
+        .list_outputs()
+        .await?
+        .into_iter()
+        .filter(|output| output.current_mode.is_some())
+        .collect();
+    if outputs.is_empty() {
+        anyhow::bail!("No active outputs to capture");
+    }
+
+    let mut entries = Vec::new();
+    for output in &outputs {
+        let Some(current) = &output.current_mode else {
+            continue;
+        };
+        let available = if with_fallbacks {
+            display_manager
+                .list_available_modes(Some(&output.name))
+                .await?
+        } else {
+            Vec::new()
+        };
+        let specs: Vec<String> = current
+            .capture_specs(&available)
+            .iter()
+            .map(|spec| spec.to_string())
+            .collect();
+
+        let entry = OutputEntry::new(None, &specs, false)?;
+        // A lone output is stored for the default output so the profile
+        // keeps working when the monitor moves to another connector
+        entries.push(if outputs.len() == 1 {
+            entry
+        } else {
+            OutputEntry {
+                output: Some(output.name.clone()),
+                monitor: output.identity.as_ref().map(|id| id.to_string()),
+                ..entry
+            }
+        });
+    }
+
+    let profile = Profile {
+        outputs: entries,
+        ..Default::default()
+    };
+    profile_manager.save_profile(name.clone(), profile.clone(), force)?;
+
+    println!("Captured profile: {name}");
+    print_profile(&profile, |spec| spec.to_string());
+    Ok(())
+}
+
+pub fn handle_show_profile(
+    profile_manager: &ProfileManager,
+    name: String,
+    resolved: bool,
+    json: bool,
+) -> Result<()> {
+    let profile = if resolved {
+        profile_manager.resolved_profile(&name)?
+    } else {
+        profile_manager.profile(&name)?
+    };
+
+    if json {
+        let mut value = match profile.default_output_specs() {
+            Some(specs) => serde_json::json!({ "name": name, "specs": specs }),
+            None => serde_json::json!({ "name": name, "outputs": profile.outputs }),
+        };
+        if let Some(rule) = &profile.match_rule {
+            value["match"] = serde_json::to_value(rule)?;
+        }
+        if let Some(base) = &profile.extends {
+            value["extends"] = serde_json::json!(base);
+        }
+        if !profile.includes.is_empty() {
+            value["includes"] = serde_json::json!(profile.includes);
+        }
+        if !profile.when.is_empty() {
+            value["when"] = serde_json::to_value(&profile.when)?;
+        }
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        return Ok(());
+    }
+
+    println!("Profile: {name}");
+    print_profile(&profile, |spec| {
+        // Placeholders are only filled in when the profile is applied
+        if spec.has_variables() {
+            return spec.to_string();
+        }
+        match spec.parse(&Variables::default()) {
+            Ok(parsed) if parsed.to_string() != spec.as_str() => format!("{spec} ({parsed})"),
+            Ok(_) => spec.to_string(),
+            Err(e) => format!("{spec} (invalid: {e})"),
+        }
+    });
+
+    Ok(())
+}
+
+pub fn handle_edit_profile(profile_manager: &mut ProfileManager, name: String) -> Result<()> {
+    let profile = profile_manager.profile(&name)?;
+
+    // The profile as it would appear in profiles.toml, so every field can be edited
+    let document = BTreeMap::from([(name.clone(), profile)]);
+    let content = format!(
+        "# Profile '{name}' in the profiles.toml format. Save and quit to apply;\n\
+         # the profile is only updated if it is still valid.\n\n{}",
+        config::Format::Toml.serialize(&document)?
+    );
+
+    let mut temp_file = tempfile::Builder::new()
+        .prefix("display-switch-")
+        .suffix(".toml")
+        .tempfile()?;
+    temp_file.write_all(content.as_bytes())?;
+    temp_file.flush()?;
+    run_editor(temp_file.path())?;
+    // Editors may replace the file rather than write to it, so read it by path
+    let edited = std::fs::read_to_string(temp_file.path())?;
+
+    let mut edited: BTreeMap<String, Profile> =
+        serde_json::from_value(config::Format::Toml.parse(&edited)?)
+            .context("The edited profile is not valid")?;
+    let profile = match (edited.remove(&name), edited.is_empty()) {
+        (Some(profile), true) => profile,
+        _ => anyhow::bail!("The edited file must define exactly the profile '{name}'"),
+    };
+
+    // Validate everything before touching the stored profile
+    for entry in &profile.outputs {
+        for spec in &entry.specs {
+            StoredSpec::new(spec.as_str())?;
+        }
+    }
+    profile_manager.save_profile(name.clone(), profile, true)?;
+    println!("Updated profile: {name}");
+    Ok(())
+}
+
+fn run_editor(path: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| {
+            if cfg!(windows) {
+                "notepad".to_string()
+            } else {
+                "vi".to_string()
+            }
+        });
+
+    // $EDITOR may include arguments, e.g. "code --wait"
+    let mut parts = editor.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("$EDITOR is empty"))?;
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()?;
+
+    if !status.success() {
+        anyhow::bail!("Editor exited with {status}; profile left unchanged");
+    }
+    Ok(())
+}
+
+pub async fn handle_profile(
+    display_manager: &DisplayManager,
+    profile_manager: &ProfileManager,
+    settings: &Settings,
+    expansion: &Expansion,
+    journal: &Journal,
+    name: String,
+) -> Result<()> {
+    journal.attempt(AuditRecord {
+        profile: Some(name.clone()),
+        ..AuditRecord::new(Action::Profile, Vec::new(), None)
+    });
+    let profile = profile_manager.resolved_profile(&name)?;
+
+    // Only per-output profiles need to know which outputs are connected
+    let connected = if profile.outputs.iter().any(|e| !e.is_default_output()) {
+        display_manager.list_outputs().await?
+    } else {
+        Vec::new()
+    };
+
+    let mut targets = Vec::new();
+    for entry in &profile.outputs {
+        let output = if entry.is_default_output() {
+            settings.output.clone()
+        } else if let Some(output) = entry.find_output(&connected) {
+            Some(output.name.clone())
+        } else if entry.optional {
+            eprintln!("Skipping optional output {entry}: not connected");
+            continue;
+        } else {
+            return Err(Error::OutputNotFound(format!(
+                "Output '{entry}' used by profile '{name}' is not connected"
+            ))
+            .into());
+        };
+        targets.push(OutputTarget {
+            output,
+            specs: expansion.expand_specs(&entry.specs)?,
+        });
+    }
+
+    let hooks = SwitchHooks::new(settings.hooks(), Some((&name, &profile.hooks)));
+    let request = Request::Profile(name.clone());
+    let applied = switch_with_hooks(
+        display_manager,
+        settings,
+        &hooks,
+        journal,
+        request,
+        &targets,
+    )
+    .await?;
+    for change in &applied {
+        match &change.output {
+            Some(output) if profile.default_output_specs().is_none() => println!(
+                "Successfully switched {output} for profile '{name}' to specification: {} (requested: {})",
+                change.mode, change.spec
+            ),
+            _ => println!(
+                "Successfully switched to profile '{name}' with specification: {} (requested: {})",
+                change.mode, change.spec
+            ),
+        }
+    }
+
+    confirm_or_revert(display_manager, settings, journal, &applied).await
+}
+
+pub async fn handle_auto_profile(
+    display_manager: &DisplayManager,
+    profile_manager: &ProfileManager,
+    settings: &Settings,
+    expansion: &Expansion,
+    journal: &Journal,
+) -> Result<()> {
+    let connected = display_manager.list_outputs().await?;
+    let fingerprint: Vec<_> = connected.iter().map(profile::fingerprint_of).collect();
+
+    let Some((entry, mode)) = profile_manager.select_profile(&connected)? else {
+        anyhow::bail!(
+            "No profile matches the connected outputs: {}",
+            fingerprint.join(", ")
+        );
+    };
+
+    println!("Detected profile '{}' ({mode} match)", entry.name);
+    handle_profile(
+        display_manager,
+        profile_manager,
+        settings,
+        expansion,
+        journal,
+        entry.name,
+    )
+    .await
+}
+
+pub fn handle_list_profiles(profile_manager: &ProfileManager, all: bool) -> Result<()> {
+    let profiles = profile_manager.list_profiles(all)?;
+
+    if profiles.is_empty() {
+        println!(
+            "No profiles found (user profiles file: {}).",
+            profile_manager.config_file().display()
+        );
+        return Ok(());
+    }
+
+    for entry in profiles {
+        let inactive = if profile_manager.applies(&entry.profile) {
+            ""
+        } else {
+            ", inactive"
+        };
+        println!("Profile: {} ({}{})", entry.name, entry.layer, inactive);
+        print_profile(&entry.profile, ToString::to_string);
+        println!();
+    }
+
+    Ok(())
+}
+
+// Prints a profile's specifications, grouped by output for per-output profiles
+fn print_profile(profile: &Profile, describe: impl Fn(&StoredSpec) -> String) {
+    if let Some(base) = &profile.extends {
+        println!("  Extends: {base}");
+    }
+    if !profile.includes.is_empty() {
+        println!("  Includes: {}", profile.includes.join(", "));
+    }
+    if let Some(rule) = &profile.match_rule {
+        println!("  Matches ({}): {}", rule.mode, rule.outputs.join(", "));
+    }
+    if !profile.when.is_empty() {
+        println!("  When: {}", profile.when);
+    }
+
+    if let Some(specs) = profile.default_output_specs() {
+        for spec in specs {
+            println!("  - {}", describe(spec));
+        }
+        return;
+    }
+
+    for entry in &profile.outputs {
+        let target = if entry.is_default_output() {
+            "default output".to_string()
+        } else {
+            entry.to_string()
+        };
+        let optional = if entry.optional { " (optional)" } else { "" };
+        let when = if entry.when.is_empty() {
+            String::new()
+        } else {
+            format!(" (when {})", entry.when)
+        };
+        println!("  {target}{optional}{when}:");
+        for spec in &entry.specs {
+            println!("    - {}", describe(spec));
+        }
+    }
+}
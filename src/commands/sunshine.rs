@@ -0,0 +1,200 @@
+use anyhow::Result;
+use std::path::PathBuf;
+
+use super::Journal;
+use crate::audit::{Action, AuditRecord};
+use crate::display::{DisplayManager, DisplaySpec};
+use crate::history::{HistoryEntry, ModeChange, Request};
+use crate::parser;
+use crate::profile::ProfileManager;
+use crate::settings::Settings;
+use crate::sunshine;
+
+pub async fn handle_sunshine_do(
+    display_manager: &DisplayManager,
+    settings: &Settings,
+    journal: &Journal,
+    profile_specs: Option<(String, Vec<DisplaySpec>)>,
+    fallback: Vec<String>,
+    output: Option<String>,
+    sunshine_config: Option<PathBuf>,
+) -> Result<()> {
+    let (profile, mut specs) = match profile_specs {
+        Some((name, specs)) => (Some(name), specs),
+        None => {
+            let client = sunshine::ClientEnv::from_env()?;
+            if client.hdr {
+                eprintln!("Note: the client requested HDR, which display-switch does not change");
+            }
+            (None, vec![client.to_spec()])
+        }
+    };
+    for s in &fallback {
+        specs.push(parser::parse_display_spec(s)?);
+    }
+
+    // An explicit --output wins over sunshine.conf, which wins over the output setting
+    let output_name = match output {
+        Some(output) => Some(output),
+        None => match sunshine_config.or_else(sunshine::default_config_path) {
+            Some(path) if path.exists() => sunshine::read_output_name(&path)?,
+            _ => None,
+        },
+    }
+    .or_else(|| settings.output.clone());
+    let output = match output_name {
+        Some(name) => Some(sunshine::resolve_output(display_manager, &name).await?),
+        None => None,
+    };
+
+    let state_file = &journal.sunshine_state;
+    let previous = display_manager
+        .get_current_display_mode(output.as_deref())
+        .await
+        .ok();
+    let applied = sunshine::apply(
+        display_manager,
+        output.as_deref(),
+        &specs,
+        &settings.match_options(),
+        state_file,
+    )
+    .await;
+    let (changes, error) = match &applied {
+        Ok((_, mode)) => {
+            let change = ModeChange {
+                output: output.clone(),
+                previous,
+                mode: mode.clone(),
+            };
+            (vec![change], None)
+        }
+        Err(e) => (Vec::new(), Some(format!("{e:#}"))),
+    };
+    let requested: Vec<String> = specs.iter().map(ToString::to_string).collect();
+    journal.record_audit(AuditRecord {
+        profile,
+        requested: requested.clone(),
+        ..AuditRecord::new(Action::SunshineDo, changes.clone(), error.clone())
+    });
+    journal.record_history(HistoryEntry::new(
+        Request::SunshineDo(requested),
+        changes,
+        error,
+    ));
+    let (spec, actual_mode) = applied?;
+
+    println!("Successfully switched to display specification: {actual_mode} (requested: {spec})");
+    Ok(())
+}
+
+pub async fn handle_sunshine_undo(
+    display_manager: &DisplayManager,
+    journal: &Journal,
+) -> Result<()> {
+    let state_file = &journal.sunshine_state;
+
+    // The mode of the output the saved state is for, before it is restored
+    let before = match sunshine::saved_state(state_file) {
+        Ok(Some(state)) => display_manager
+            .get_current_display_mode(state.output.as_deref())
+            .await
+            .ok(),
+        _ => None,
+    };
+    let restored = sunshine::restore(display_manager, state_file).await;
+    let (changes, error) = match &restored {
+        Ok(Some(state)) => {
+            let change = ModeChange {
+                output: state.output.clone(),
+                previous: before,
+                mode: state.mode.clone(),
+            };
+            (vec![change], None)
+        }
+        Ok(None) => (Vec::new(), None),
+        Err(e) => (Vec::new(), Some(format!("{e:#}"))),
+    };
+    // Nothing to restore is not a display change
+    if !(changes.is_empty() && error.is_none()) {
+        journal.record_audit(AuditRecord::new(
+            Action::SunshineUndo,
+            changes.clone(),
+            error.clone(),
+        ));
+        let success = error.is_none();
+        journal.record_history(HistoryEntry::new(Request::SunshineUndo, changes, error));
+        if success {
+            journal.update_history(
+                journal
+                    .history
+                    .mark_latest_undone(|request| matches!(request, Request::SunshineDo(_))),
+            );
+        }
+    }
+    match restored? {
+        Some(state) => println!("Restored display specification: {}", state.mode),
+        None => println!("No saved display state to restore."),
+    }
+
+    Ok(())
+}
+
+pub fn handle_sunshine_install(
+    profile_manager: &ProfileManager,
+    apps_file: Option<PathBuf>,
+    apps: Option<Vec<String>>,
+    profile: Option<String>,
+    fallback: Vec<String>,
+    uninstall: bool,
+) -> Result<()> {
+    let apps_file = apps_file
+        .or_else(sunshine::default_apps_path)
+        .ok_or_else(|| anyhow::anyhow!("Unable to locate apps.json; pass --apps-file"))?;
+    let selection = match apps {
+        Some(names) => sunshine::AppSelection::Named(names),
+        None => sunshine::AppSelection::All,
+    };
+
+    let command = if uninstall {
+        None
+    } else {
+        if let Some(name) = &profile {
+            profile_manager.get_profile(name)?;
+        }
+        for s in &fallback {
+            parser::parse_display_spec(s)?;
+        }
+
+        // Sunshine runs prep commands with a minimal environment, so use an absolute path
+        let exe = std::env::current_exe()?.to_string_lossy().into_owned();
+        let mut do_args = vec![exe.clone(), "sunshine".to_string(), "do".to_string()];
+        if let Some(name) = profile {
+            do_args.extend(["--profile".to_string(), name]);
+        }
+        if !fallback.is_empty() {
+            do_args.extend(["--fallback".to_string(), fallback.join(",")]);
+        }
+
+        Some(sunshine::PrepCommand {
+            do_cmd: sunshine::join_command(&do_args),
+            undo_cmd: sunshine::join_command(&[exe, "sunshine".to_string(), "undo".to_string()]),
+        })
+    };
+
+    let changed = sunshine::update_apps_file(&apps_file, &selection, command.as_ref())?;
+    if changed.is_empty() {
+        println!("{} is already up to date.", apps_file.display());
+    } else {
+        let action = if uninstall { "Removed" } else { "Installed" };
+        for name in changed {
+            println!("{action} display-switch prep commands for app: {name}");
+        }
+        println!(
+            "The previous file was kept as {}",
+            sunshine::apps_backup_path(&apps_file).display()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use anyhow::Result;
+
+use super::{confirm_or_revert, switch_with_hooks, Journal};
+use crate::audit::{Action, AuditRecord};
+use crate::display::{DisplayManager, DisplaySpec, OutputInfo, OutputTarget};
+use crate::error::Error;
+use crate::history::Request;
+use crate::hooks::SwitchHooks;
+use crate::parser;
+use crate::profile::{ProfileLookup, ProfileManager};
+use crate::settings::Settings;
+
+pub enum SpecOrProfile {
+    Profile(String),
+    /// An inline fallback list of specs
+    Specs(Vec<String>),
+}
+
+// Decides what the positional argument names. Profiles win over specs, but
+// only an exact profile name wins over a valid spec.
+pub fn spec_or_profile(profile_manager: &ProfileManager, value: &str) -> Result<SpecOrProfile> {
+    if let Some(specs) = value.strip_prefix("spec:") {
+        return Ok(SpecOrProfile::Specs(parser::split_spec_list(specs)));
+    }
+    if let Some(name) = value.strip_prefix("profile:") {
+        return lookup_profile(profile_manager, name, None);
+    }
+
+    let specs = parser::split_spec_list(value);
+    let spec_error = specs
+        .iter()
+        .find_map(|spec| parser::parse_display_spec(spec).err());
+    match spec_error {
+        Some(error) => lookup_profile(profile_manager, value, Some(error)),
+        None => {
+            if profile_manager.profile_exists(value) {
+                eprintln!(
+                    "Warning: '{value}' is both a profile and a display specification; \
+                     using the profile (write spec:{value} for the specification)"
+                );
+                Ok(SpecOrProfile::Profile(value.to_string()))
+            } else {
+                Ok(SpecOrProfile::Specs(specs))
+            }
+        }
+    }
+}
+
+// `spec_error` is why the name is not a spec either, if it could have been one
+fn lookup_profile(
+    profile_manager: &ProfileManager,
+    name: &str,
+    spec_error: Option<anyhow::Error>,
+) -> Result<SpecOrProfile> {
+    match profile_manager.lookup_profile(name)? {
+        ProfileLookup::Exact(name) => Ok(SpecOrProfile::Profile(name)),
+        ProfileLookup::Fuzzy(found) => {
+            eprintln!("Using profile '{found}' for '{name}'");
+            Ok(SpecOrProfile::Profile(found))
+        }
+        ProfileLookup::Ambiguous(names) => Err(Error::ProfileNotFound(format!(
+            "'{}' matches several profiles: {}",
+            name,
+            names.join(", ")
+        ))
+        .into()),
+        ProfileLookup::NotFound(similar) => {
+            // Without a close profile name, a broken spec is the likelier mistake
+            let as_spec = spec_error.is_some() && similar.is_empty();
+            let mut message = match spec_error {
+                Some(error) => format!(
+                    "'{name}' is neither a profile nor a valid display specification ({error})"
+                ),
+                None => format!("Profile '{name}' not found"),
+            };
+            if !similar.is_empty() {
+                let similar: Vec<String> = similar.iter().map(|s| format!("'{s}'")).collect();
+                message.push_str(&format!("; did you mean {}?", similar.join(" or ")));
+            }
+            Err(if as_spec {
+                Error::Parse(message).into()
+            } else {
+                Error::ProfileNotFound(message).into()
+            })
+        }
+    }
+}
+
+pub async fn handle_switch(
+    display_manager: &DisplayManager,
+    settings: &Settings,
+    journal: &Journal,
+    specs: Vec<String>,
+) -> Result<()> {
+    journal.attempt(AuditRecord {
+        requested: specs.clone(),
+        ..AuditRecord::new(Action::Switch, Vec::new(), None)
+    });
+    let parsed_specs: Result<Vec<DisplaySpec>, _> = specs
+        .iter()
+        .map(|s| parser::parse_display_spec(s))
+        .collect();
+    let target = OutputTarget {
+        output: settings.output.clone(),
+        specs: parsed_specs?,
+    };
+
+    let hooks = SwitchHooks::new(settings.hooks(), None);
+    let request = Request::Specs(specs);
+    let applied = switch_with_hooks(
+        display_manager,
+        settings,
+        &hooks,
+        journal,
+        request,
+        &[target],
+    )
+    .await?;
+    for change in &applied {
+        println!(
+            "Successfully switched to display specification: {} (requested: {})",
+            change.mode, change.spec
+        );
+    }
+
+    confirm_or_revert(display_manager, settings, journal, &applied).await
+}
+
+pub async fn handle_list(
+    display_manager: &DisplayManager,
+    settings: &Settings,
+    filter_spec: Option<String>,
+    json: bool,
+) -> Result<()> {
+    let available_modes = display_manager
+        .list_available_modes(settings.output.as_deref())
+        .await?;
+
+    let tolerance = settings.match_options().refresh_tolerance;
+    let filtered_modes = if let Some(filter) = filter_spec {
+        let filter_spec = parser::parse_display_spec(&filter)?;
+        available_modes
+            .into_iter()
+            .filter(|mode| mode.matches_filter(&filter_spec, tolerance))
+            .collect()
+    } else {
+        available_modes
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&filtered_modes)?);
+    } else {
+        // Identities are listed first so they can be copied into profiles
+        for output in display_manager.list_outputs().await? {
+            print_output(&output);
+        }
+        println!();
+        for mode in filtered_modes {
+            println!("{mode}");
+        }
+    }
+
+    Ok(())
+}
+
+fn print_output(output: &OutputInfo) {
+    let primary = if output.primary { " (primary)" } else { "" };
+    match &output.identity {
+        Some(identity) => {
+            let mut details = vec![identity.to_string()];
+            details.extend(
+                identity
+                    .edid_hash
+                    .as_ref()
+                    .map(|hash| format!("edid:{hash}")),
+            );
+            details.extend(identity.name.as_ref().map(|name| format!("\"{name}\"")));
+            println!("Output {}{primary}: {}", output.name, details.join(" "));
+        }
+        None => println!("Output {}{primary}: unknown monitor", output.name),
+    }
+}
+
+pub async fn handle_current(
+    display_manager: &DisplayManager,
+    settings: &Settings,
+    json: bool,
+) -> Result<()> {
+    let current_mode = display_manager
+        .get_current_display_mode(settings.output.as_deref())
+        .await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&current_mode)?);
+    } else {
+        println!("Current display specification: {current_mode}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use anyhow::Result;
+use std::path::Path;
+
+use super::Expansion;
+use crate::config;
+use crate::display::DisplayManager;
+use crate::profile::ProfileManager;
+use crate::settings::Settings;
+use crate::validate;
+
+pub async fn handle_validate_profiles(
+    display_manager: &DisplayManager,
+    profile_manager: &ProfileManager,
+    settings: &Settings,
+    expansion: &Expansion,
+    name: Option<String>,
+    json: bool,
+) -> Result<()> {
+    let names = match name {
+        Some(name) => vec![name],
+        None => profile_manager
+            .list_profiles(false)?
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect(),
+    };
+
+    if names.is_empty() && !json {
+        println!("No profiles to validate.");
+        return Ok(());
+    }
+
+    let mut reports = Vec::new();
+    for name in &names {
+        reports.push(
+            validate::validate_profile(
+                display_manager,
+                profile_manager,
+                name,
+                &expansion.variables,
+                &settings.match_options(),
+            )
+            .await?,
+        );
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        for report in &reports {
+            print_validation(report);
+        }
+    }
+
+    let failed: Vec<&str> = reports
+        .iter()
+        .filter(|report| !report.ok)
+        .map(|report| report.name.as_str())
+        .collect();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "{} of {} profile(s) cannot be applied here: {}",
+            failed.len(),
+            reports.len(),
+            failed.join(", ")
+        ))
+    }
+}
+
+fn print_validation(report: &validate::ProfileReport) {
+    let verdict = if report.ok { "ok" } else { "FAILED" };
+    println!("Profile '{}': {}", report.name, verdict);
+    if let Some(error) = &report.error {
+        println!("  {error}");
+    }
+    for entry in &report.outputs {
+        let target = match &entry.output {
+            Some(output) if *output != entry.entry => format!("{} ({output})", entry.entry),
+            Some(_) => entry.entry.clone(),
+            None if entry.optional => {
+                println!("  {}: not connected (optional)", entry.entry);
+                continue;
+            }
+            None => {
+                println!("  {}: not connected", entry.entry);
+                continue;
+            }
+        };
+        println!("  {target}:");
+        for spec in &entry.specs {
+            let status = match &spec.status {
+                validate::SpecStatus::Exact { mode } => format!("exact, {mode}"),
+                validate::SpecStatus::Approximate { mode } => format!("approximate, {mode}"),
+                validate::SpecStatus::Unsatisfiable => "unsatisfiable".to_string(),
+                validate::SpecStatus::Invalid { error } => format!("invalid: {error}"),
+            };
+            println!("    {}: {status}", spec.spec);
+        }
+    }
+}
+
+/// Records the connected outputs and their modes for `validate --snapshot`
+pub async fn handle_snapshot(display_manager: &DisplayManager, file: Option<&Path>) -> Result<()> {
+    let snapshot = display_manager.snapshot().await?;
+    let content = serde_json::to_string_pretty(&snapshot)?;
+    match file {
+        Some(file) => {
+            config::write_atomically(file, &content)?;
+            println!(
+                "Recorded {} output(s) to {}",
+                snapshot.len(),
+                file.display()
+            );
+        }
+        None => println!("{content}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::process::Command;
+
+use crate::platform::BackendKind;
+
+/// Conditions under which a profile or output entry applies. Every condition
+/// that is set must hold; an empty `when` always applies.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct When {
+    /// Host name of the machine, compared case-insensitively
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Display backend in use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<BackendKind>,
+    /// Graphical session type, e.g. `x11` or `wayland`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_type: Option<String>,
+    /// Environment variables and the values they must have; `*` only requires
+    /// the variable to be set
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl When {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn matches(&self, environment: &Environment) -> bool {
+        let same = |expected: &Option<String>, actual: &Option<String>| match expected {
+            Some(expected) => actual
+                .as_deref()
+                .is_some_and(|actual| actual.eq_ignore_ascii_case(expected)),
+            None => true,
+        };
+
+        same(&self.hostname, &environment.hostname)
+            && self.backend.is_none_or(|b| b == environment.backend)
+            && same(&self.session_type, &environment.session_type)
+            && self.env.iter().all(|(name, expected)| {
+                environment
+                    .vars
+                    .get(name)
+                    .is_some_and(|value| expected == "*" || value == expected)
+            })
+    }
+}
+
+/// `hostname=htpc, session_type=wayland`, for listings
+impl fmt::Display for When {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut conditions = Vec::new();
+        if let Some(hostname) = &self.hostname {
+            conditions.push(format!("hostname={hostname}"));
+        }
+        if let Some(backend) = self.backend {
+            conditions.push(format!("backend={}", backend_name(backend)));
+        }
+        if let Some(session_type) = &self.session_type {
+            conditions.push(format!("session_type={session_type}"));
+        }
+        for (name, value) in &self.env {
+            conditions.push(format!("env.{name}={value}"));
+        }
+        write!(f, "{}", conditions.join(", "))
+    }
+}
+
+/// What `when` conditions are checked against
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    pub hostname: Option<String>,
+    pub backend: BackendKind,
+    pub session_type: Option<String>,
+    pub vars: HashMap<String, String>,
+}
+
+impl Environment {
+    /// Describes the machine and session this process runs in
+    pub fn detect(backend: BackendKind) -> Self {
+        let vars: HashMap<String, String> = std::env::vars().collect();
+        let session_type = vars
+            .get("XDG_SESSION_TYPE")
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .or_else(|| {
+                if vars.contains_key("WAYLAND_DISPLAY") {
+                    Some("wayland".to_string())
+                } else if vars.contains_key("DISPLAY") {
+                    Some("x11".to_string())
+                } else {
+                    None
+                }
+            });
+
+        Self {
+            hostname: hostname(&vars),
+            backend,
+            session_type,
+            vars,
+        }
+    }
+}
+
+fn hostname(vars: &HashMap<String, String>) -> Option<String> {
+    if cfg!(windows) {
+        return vars.get("COMPUTERNAME").cloned();
+    }
+
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| {
+            let output = Command::new("hostname").output().ok()?;
+            output
+                .status
+                .success()
+                .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+        })
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+fn backend_name(backend: BackendKind) -> String {
+    serde_json::to_value(backend)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_matches() {
+        let environment = Environment {
+            hostname: Some("HTPC".to_string()),
+            backend: BackendKind::Native,
+            session_type: Some("wayland".to_string()),
+            vars: HashMap::from([("DOCKED".to_string(), "1".to_string())]),
+        };
+        let when = |json: &str| serde_json::from_str::<When>(json).unwrap();
+
+        assert!(when("{}").matches(&environment));
+        assert!(when(r#"{"hostname": "htpc", "session_type": "Wayland"}"#).matches(&environment));
+        assert!(when(r#"{"env": {"DOCKED": "1"}}"#).matches(&environment));
+        assert!(when(r#"{"env": {"DOCKED": "*"}}"#).matches(&environment));
+        assert!(!when(r#"{"hostname": "laptop"}"#).matches(&environment));
+        assert!(!when(r#"{"backend": "mock"}"#).matches(&environment));
+        assert!(!when(r#"{"env": {"DOCKED": "0"}}"#).matches(&environment));
+        assert!(!when(r#"{"env": {"UNDOCKED": "*"}}"#).matches(&environment));
+
+        assert_eq!(
+            when(r#"{"hostname": "htpc", "backend": "mock", "env": {"A": "1"}}"#).to_string(),
+            "hostname=htpc, backend=mock, env.A=1"
+        );
+    }
+}
@@ -0,0 +1,376 @@
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::display::DisplaySpec;
+use crate::error::Error;
+use crate::platform::BackendKind;
+
+/// Schema version written to new configuration files
+pub const CURRENT_VERSION: u32 = 2;
+
+// Upgrades a document from version `i + 1` to version `i + 2`
+const MIGRATIONS: [fn(Value) -> Result<Value>; 1] = [migrate_v1_to_v2];
+
+/// On-disk format of a configuration file, chosen by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => Format::Toml,
+            _ => Format::Json,
+        }
+    }
+
+    pub fn parse(self, content: &str) -> Result<Value> {
+        Ok(match self {
+            Format::Json => serde_json::from_str(content)?,
+            Format::Toml => toml::from_str(content)?,
+        })
+    }
+
+    pub fn serialize<T: Serialize>(self, value: &T) -> Result<String> {
+        Ok(match self {
+            Format::Json => serde_json::to_string_pretty(value)?,
+            Format::Toml => toml::to_string_pretty(value)?,
+        })
+    }
+}
+
+/// Returns the display-switch config directory, creating it if needed
+pub fn config_dir() -> Result<PathBuf> {
+    let config_dir = dirs::config_dir()
+        .ok_or_else(|| anyhow!("Unable to determine config directory"))?
+        .join("display-switch");
+
+    if !config_dir.exists() {
+        fs::create_dir_all(&config_dir)?;
+    }
+
+    Ok(config_dir)
+}
+
+/// Directory holding system-wide defaults shipped with the machine image
+pub fn system_config_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        Some(PathBuf::from(std::env::var_os("ProgramData")?).join("display-switch"))
+    } else {
+        Some(PathBuf::from("/etc/display-switch"))
+    }
+}
+
+// profiles.toml takes precedence over profiles.json within a directory
+fn profiles_file_in(dir: &Path) -> PathBuf {
+    let toml_file = dir.join("profiles.toml");
+    if toml_file.exists() {
+        toml_file
+    } else {
+        dir.join("profiles.json")
+    }
+}
+
+/// Resolves the user profiles file: an explicit `--config` path, then
+/// `DISPLAY_SWITCH_CONFIG`, then the default file in `config_dir()`.
+pub fn user_profiles_path(explicit: Option<PathBuf>) -> Result<PathBuf> {
+    match explicit.or_else(|| std::env::var_os("DISPLAY_SWITCH_CONFIG").map(PathBuf::from)) {
+        Some(path) => Ok(path),
+        None => Ok(profiles_file_in(&config_dir()?)),
+    }
+}
+
+/// The system profiles file, if one exists
+pub fn system_profiles_path() -> Option<PathBuf> {
+    let path = profiles_file_in(&system_config_dir()?);
+    path.exists().then_some(path)
+}
+
+/// Appends `suffix` to the file name of `path` (e.g. `profiles.json` -> `profiles.json.lock`)
+pub fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(suffix);
+    PathBuf::from(sibling)
+}
+
+/// Takes an advisory lock on a sidecar file; config files are replaced by
+/// rename on every write, so they cannot hold the lock themselves
+pub fn lock(path: &Path) -> Result<File> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+
+    let lock = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sibling_path(path, ".lock"))?;
+    lock.lock()?;
+    Ok(lock)
+}
+
+/// Writes to a temporary file next to `path` and renames it into place
+pub fn write_atomically(path: &Path, content: &str) -> Result<()> {
+    let temp_path = sibling_path(path, ".tmp");
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// Upgrades a parsed configuration document to `CURRENT_VERSION`. Files
+/// without a `version` field are version 1. Returns the original version if
+/// any migration was applied.
+pub fn migrate(mut value: Value) -> Result<(Value, Option<u32>)> {
+    let original = value
+        .get("version")
+        .map(|v| {
+            v.as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| Error::InvalidConfig(format!("Invalid config version: {}", v)))
+        })
+        .transpose()?
+        .unwrap_or(1);
+
+    if original > CURRENT_VERSION {
+        return Err(Error::InvalidConfig(format!(
+            "Config version {} is newer than this display-switch supports ({}); please upgrade",
+            original, CURRENT_VERSION
+        ))
+        .into());
+    }
+
+    for version in original..CURRENT_VERSION {
+        value = MIGRATIONS[version as usize - 1](value)
+            .with_context(|| format!("Failed to migrate config from version {version}"))?;
+        value["version"] = json!(version + 1);
+    }
+
+    Ok((value, (original < CURRENT_VERSION).then_some(original)))
+}
+
+// Version 1 files had no version field and could store specs as DisplaySpec structs
+fn migrate_v1_to_v2(mut value: Value) -> Result<Value> {
+    if let Some(profiles) = value.get_mut("profiles").and_then(Value::as_object_mut) {
+        for specs in profiles.values_mut().filter_map(Value::as_array_mut) {
+            for spec in specs.iter_mut().filter(|spec| spec.is_object()) {
+                let parsed: DisplaySpec = serde_json::from_value(spec.take())?;
+                *spec = Value::String(parsed.to_string());
+            }
+        }
+    }
+
+    Ok(value)
+}
+
+/// JSON Schema describing the current configuration file format
+pub fn json_schema() -> Value {
+    // Only the backends built into this binary
+    let backends: Vec<Value> = BackendKind::value_variants()
+        .iter()
+        .filter_map(|backend| serde_json::to_value(backend).ok())
+        .collect();
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "display-switch configuration",
+        "type": "object",
+        "properties": {
+            "version": {
+                "description": "Configuration schema version",
+                "type": "integer",
+                "const": CURRENT_VERSION
+            },
+            "settings": {
+                "description": "Defaults for options that can also be given on the command line",
+                "type": "object",
+                "properties": {
+                    "exact": {"type": "boolean"},
+                    "policy": {"enum": ["prefer-higher", "prefer-lower", "nearest"]},
+                    "refresh_tolerance": {"type": "number", "minimum": 0},
+                    "output": {"type": "string"},
+                    "format": {"enum": ["text", "json"]},
+                    "confirm_timeout": {"type": "integer", "minimum": 0},
+                    "backend": {"enum": backends},
+                    "pre_switch": {"type": "string"},
+                    "post_switch": {"type": "string"},
+                    "on_failure": {"type": "string"},
+                    "hook_timeout": {"type": "integer", "minimum": 0},
+                    "audit_log": {"type": "string"},
+                    "audit_max_size": {"type": "integer", "minimum": 1}
+                },
+                "additionalProperties": false
+            },
+            "profiles": {
+                "description": "Named profiles, each a list of display specifications in order of preference",
+                "type": "object",
+                "additionalProperties": {
+                    "anyOf": [
+                        {
+                            "description": "Specifications for the default output",
+                            "$ref": "#/$defs/specs"
+                        },
+                        {
+                            "type": "object",
+                            "properties": {
+                                "outputs": {
+                                    "type": "array",
+                                    "items": {
+                                        "type": "object",
+                                        "properties": {
+                                            "output": {
+                                                "description": "Output name; omit for the default output",
+                                                "type": "string"
+                                            },
+                                            "monitor": {
+                                                "description": "Monitor identity, MFG:PRODUCT[:SERIAL] or edid:<hash>, as printed by --list",
+                                                "type": "string"
+                                            },
+                                            "specs": {"$ref": "#/$defs/specs"},
+                                            "optional": {
+                                                "description": "Skip this output if it is not connected",
+                                                "type": "boolean"
+                                            },
+                                            "when": {
+                                                "description": "Only use this entry when these conditions hold",
+                                                "$ref": "#/$defs/when"
+                                            }
+                                        },
+                                        "required": ["specs"]
+                                    },
+                                    "minItems": 1
+                                },
+                                "match": {
+                                    "description": "Connected outputs this profile is for, used by `profile auto`",
+                                    "type": "object",
+                                    "properties": {
+                                        "outputs": {
+                                            "description": "Monitor identities or connector names",
+                                            "type": "array",
+                                            "items": {"type": "string"},
+                                            "minItems": 1
+                                        },
+                                        "mode": {"enum": ["exact", "subset", "any-of"]}
+                                    },
+                                    "required": ["outputs"]
+                                },
+                                "extends": {
+                                    "description": "Profile to inherit outputs and match block from",
+                                    "type": "string"
+                                },
+                                "includes": {
+                                    "description": "Profiles whose outputs are merged in, later ones winning",
+                                    "type": "array",
+                                    "items": {"type": "string"}
+                                },
+                                "hooks": {
+                                    "description": "Commands run around applying the profile, after the global ones",
+                                    "type": "object",
+                                    "properties": {
+                                        "pre_switch": {"type": "string"},
+                                        "post_switch": {"type": "string"},
+                                        "on_failure": {"type": "string"},
+                                        "timeout": {"type": "integer", "minimum": 0}
+                                    },
+                                    "additionalProperties": false
+                                },
+                                "when": {
+                                    "description": "Where this profile applies; elsewhere it is hidden from --list-profiles",
+                                    "$ref": "#/$defs/when"
+                                }
+                            },
+                            "minProperties": 1
+                        }
+                    ]
+                }
+            }
+        },
+        "required": ["profiles"],
+        "$defs": {
+            "specs": {
+                "type": "array",
+                "items": {
+                    "type": "string",
+                    "description": "Display specification, e.g. \"4k@60hz\", \"2560x1440@144hz\" or \"16:9\""
+                },
+                "minItems": 1
+            },
+            "when": {
+                "type": "object",
+                "properties": {
+                    "hostname": {"type": "string"},
+                    "backend": {"enum": backends},
+                    "session_type": {
+                        "description": "XDG_SESSION_TYPE, e.g. \"x11\" or \"wayland\"",
+                        "type": "string"
+                    },
+                    "env": {
+                        "description": "Required environment variable values; \"*\" only requires the variable to be set",
+                        "type": "object",
+                        "additionalProperties": {"type": "string"}
+                    }
+                },
+                "additionalProperties": false
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_v1() -> Result<()> {
+        let v1 = json!({
+            "profiles": {
+                "tv": [
+                    "4k@60hz",
+                    {"width": 1920, "height": 1080, "refresh_rate": 60.0, "aspect_ratio": null}
+                ]
+            }
+        });
+
+        let (migrated, original) = migrate(v1)?;
+        assert_eq!(original, Some(1));
+        assert_eq!(migrated["version"], json!(CURRENT_VERSION));
+        assert_eq!(
+            migrated["profiles"]["tv"],
+            json!(["4k@60hz", "1920x1080@60hz"])
+        );
+
+        let (unchanged, original) = migrate(migrated.clone())?;
+        assert_eq!(original, None);
+        assert_eq!(unchanged, migrated);
+
+        assert!(migrate(json!({"version": CURRENT_VERSION + 1, "profiles": {}})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_format() -> Result<()> {
+        assert_eq!(Format::from_path(Path::new("profiles.toml")), Format::Toml);
+        assert_eq!(Format::from_path(Path::new("profiles.json")), Format::Json);
+
+        let value = Format::Toml.parse("version = 2\n\n[profiles]\ntv = [\"4k@60hz\"]\n")?;
+        assert_eq!(
+            value,
+            json!({"version": 2, "profiles": {"tv": ["4k@60hz"]}})
+        );
+        Ok(())
+    }
+}
@@ -1,8 +1,13 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
 
-use crate::platform::PlatformDisplayManager;
+use crate::edid::MonitorIdentity;
+use crate::error::Error;
+use crate::platform::mock::MockOutput;
+use crate::platform::{BackendKind, PlatformDisplayManager};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DisplaySpec {
@@ -19,13 +24,74 @@ pub struct DisplayMode {
     pub refresh_rate: f64,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputInfo {
+    pub name: String,
+    pub primary: bool,
+    pub current_mode: Option<DisplayMode>,
+    /// The connected monitor, if the backend can read its EDID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<MonitorIdentity>,
+}
+
+/// How to choose between refresh rates when the requested one is unavailable
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchPolicy {
+    /// Prefer the closest higher refresh rate, then the closest lower one
+    #[default]
+    PreferHigher,
+    /// Prefer the closest lower refresh rate, then the closest higher one
+    PreferLower,
+    /// Use whichever refresh rate is closest
+    Nearest,
+}
+
+/// Refresh rates closer than this (in Hz) are treated as equal unless configured otherwise
+pub const DEFAULT_REFRESH_TOLERANCE: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MatchOptions {
+    pub exact: bool,
+    pub policy: MatchPolicy,
+    pub refresh_tolerance: f64,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self {
+            exact: false,
+            policy: MatchPolicy::default(),
+            refresh_tolerance: DEFAULT_REFRESH_TOLERANCE,
+        }
+    }
+}
+
+/// One output's part of a switch: the specifications to try on it, in order
+#[derive(Debug, Clone)]
+pub struct OutputTarget {
+    /// `None` means the primary output
+    pub output: Option<String>,
+    pub specs: Vec<DisplaySpec>,
+}
+
+/// The mode `switch_outputs` applied to one output
+#[derive(Debug, Clone)]
+pub struct AppliedMode {
+    pub output: Option<String>,
+    pub spec: DisplaySpec,
+    pub mode: DisplayMode,
+    /// Mode before the switch, if the backend could report it
+    pub previous: Option<DisplayMode>,
+}
+
 pub struct DisplayManager {
     platform_manager: PlatformDisplayManager,
 }
 
 impl DisplaySpec {
     #[allow(dead_code)]
-    pub fn matches_filter(&self, filter: &DisplaySpec) -> bool {
+    pub fn matches_filter(&self, filter: &DisplaySpec, tolerance: f64) -> bool {
         // Check width and height
         if let (Some(filter_width), Some(filter_height)) = (filter.width, filter.height) {
             if let (Some(width), Some(height)) = (self.width, self.height) {
@@ -50,7 +116,7 @@ impl DisplaySpec {
         // Check refresh rate
         if let Some(filter_rate) = filter.refresh_rate {
             if let Some(rate) = self.refresh_rate {
-                if (rate - filter_rate).abs() > 0.1 {
+                if (rate - filter_rate).abs() > tolerance {
                     return false;
                 }
             }
@@ -59,37 +125,46 @@ impl DisplaySpec {
         true
     }
 
-    pub fn to_concrete_spec(&self, available_modes: &[DisplayMode]) -> Option<DisplayMode> {
+    pub fn to_concrete_spec(
+        &self,
+        available_modes: &[DisplayMode],
+        options: &MatchOptions,
+    ) -> Option<DisplayMode> {
         // If we have concrete width and height, find exact or closest match
         if let (Some(target_width), Some(target_height)) = (self.width, self.height) {
             return self.find_best_mode_for_resolution(
                 available_modes,
                 target_width,
                 target_height,
+                options,
             );
         }
 
-        // If we have aspect ratio, find modes matching that aspect ratio
+        // Otherwise keep the modes with the requested aspect ratio that are
+        // closest to whichever dimension is given (`2560x*`, `*x1440`)
+        let mut candidates = available_modes.to_vec();
         if let Some((w_ratio, h_ratio)) = self.aspect_ratio {
-            let matching_modes: Vec<_> = available_modes
-                .iter()
-                .filter(|mode| {
-                    let gcd = gcd(mode.width, mode.height);
-                    let actual_w_ratio = mode.width / gcd;
-                    let actual_h_ratio = mode.height / gcd;
-                    actual_w_ratio == w_ratio && actual_h_ratio == h_ratio
-                })
-                .cloned()
-                .collect();
-
-            if matching_modes.is_empty() {
-                return None;
-            }
-
-            return self.find_best_mode_by_refresh_rate(&matching_modes);
+            candidates.retain(|mode| {
+                let gcd = gcd(mode.width, mode.height);
+                mode.width / gcd == w_ratio && mode.height / gcd == h_ratio
+            });
+        }
+        if let Some(width) = self.width {
+            keep_closest(&mut candidates, |mode| mode.width.abs_diff(width));
+        }
+        if let Some(height) = self.height {
+            keep_closest(&mut candidates, |mode| mode.height.abs_diff(height));
         }
 
-        None
+        // The policy picks the refresh rate; the largest mode at that rate wins
+        let best = self.find_best_mode_by_refresh_rate(&candidates, options)?;
+        candidates
+            .into_iter()
+            .filter(|mode| {
+                (mode.refresh_rate - best.refresh_rate).abs() <= options.refresh_tolerance
+            })
+            .max_by_key(|mode| u64::from(mode.width) * u64::from(mode.height))
+            .or(Some(best))
     }
 
     fn find_best_mode_for_resolution(
@@ -97,6 +172,7 @@ impl DisplaySpec {
         available_modes: &[DisplayMode],
         target_width: u32,
         target_height: u32,
+        options: &MatchOptions,
     ) -> Option<DisplayMode> {
         // First, try to find exact resolution match
         let resolution_matches: Vec<_> = available_modes
@@ -106,7 +182,7 @@ impl DisplaySpec {
             .collect();
 
         if !resolution_matches.is_empty() {
-            return self.find_best_mode_by_refresh_rate(&resolution_matches);
+            return self.find_best_mode_by_refresh_rate(&resolution_matches, options);
         }
 
         // If no exact resolution match, find the closest resolution
@@ -127,7 +203,11 @@ impl DisplaySpec {
         closest_mode
     }
 
-    fn find_best_mode_by_refresh_rate(&self, modes: &[DisplayMode]) -> Option<DisplayMode> {
+    fn find_best_mode_by_refresh_rate(
+        &self,
+        modes: &[DisplayMode],
+        options: &MatchOptions,
+    ) -> Option<DisplayMode> {
         if modes.is_empty() {
             return None;
         }
@@ -135,11 +215,45 @@ impl DisplaySpec {
         if let Some(target_rate) = self.refresh_rate {
             // First try to find exact refresh rate match
             for mode in modes {
-                if (mode.refresh_rate - target_rate).abs() < 0.1 {
+                if (mode.refresh_rate - target_rate).abs() <= options.refresh_tolerance {
                     return Some(mode.clone());
                 }
             }
 
+            let closest_higher = modes
+                .iter()
+                .filter(|mode| mode.refresh_rate > target_rate)
+                .min_by(|a, b| a.refresh_rate.partial_cmp(&b.refresh_rate).unwrap());
+            let closest_lower = modes
+                .iter()
+                .filter(|mode| mode.refresh_rate < target_rate)
+                .max_by(|a, b| a.refresh_rate.partial_cmp(&b.refresh_rate).unwrap());
+
+            match options.policy {
+                MatchPolicy::PreferLower => {
+                    if let Some(mode) = closest_lower.or(closest_higher) {
+                        return Some(mode.clone());
+                    }
+                }
+                MatchPolicy::Nearest => {
+                    let nearest = match (closest_higher, closest_lower) {
+                        (Some(higher), Some(lower)) => {
+                            if higher.refresh_rate - target_rate <= target_rate - lower.refresh_rate
+                            {
+                                Some(higher)
+                            } else {
+                                Some(lower)
+                            }
+                        }
+                        (higher, lower) => higher.or(lower),
+                    };
+                    if let Some(mode) = nearest {
+                        return Some(mode.clone());
+                    }
+                }
+                MatchPolicy::PreferHigher => {}
+            }
+
             // If no exact match, prefer higher refresh rates first
             // Find all modes with higher refresh rates than target
             let higher_rates: Vec<_> = modes
@@ -181,47 +295,203 @@ impl DisplaySpec {
 }
 
 impl DisplayManager {
-    pub fn new() -> Result<Self> {
+    pub fn new(backend: BackendKind) -> Result<Self> {
         Ok(Self {
-            platform_manager: PlatformDisplayManager::new()?,
+            platform_manager: PlatformDisplayManager::new(backend)?,
         })
     }
 
-    pub async fn switch_display(&self, spec: &DisplaySpec, exact: bool) -> Result<DisplayMode> {
-        let available_modes = self.platform_manager.get_available_modes().await?;
+    /// Reads outputs and modes from a file written by `display-switch snapshot`
+    pub fn from_snapshot(path: &Path) -> Result<Self> {
+        Ok(Self {
+            platform_manager: PlatformDisplayManager::from_snapshot(path)?,
+        })
+    }
+
+    /// Records every connected output with its modes, in the format
+    /// `from_snapshot` and the mock backend read
+    pub async fn snapshot(&self) -> Result<Vec<MockOutput>> {
+        let mut snapshot = Vec::new();
+        for output in self.list_outputs().await? {
+            let modes = self.list_available_modes(Some(&output.name)).await?;
+            let Some(current) = output.current_mode.or_else(|| modes.first().cloned()) else {
+                continue;
+            };
+            snapshot.push(MockOutput {
+                name: output.name,
+                primary: output.primary,
+                modes,
+                current,
+                identity: output.identity,
+            });
+        }
+        Ok(snapshot)
+    }
+
+    #[cfg(test)]
+    pub fn with_platform(platform_manager: PlatformDisplayManager) -> Self {
+        Self { platform_manager }
+    }
+
+    /// Resolves `spec` against the modes of `output` (the primary output when
+    /// `None`) and applies the chosen mode. Fails if the output then reports a
+    /// different mode, e.g. because the driver silently picked another one.
+    pub async fn switch_display(
+        &self,
+        output: Option<&str>,
+        spec: &DisplaySpec,
+        options: &MatchOptions,
+    ) -> Result<DisplayMode> {
+        let mode = self.resolve_mode(output, spec, options).await?;
+        self.platform_manager
+            .set_display_mode(output, &mode)
+            .await?;
+
+        // Backends that cannot read the mode back are trusted
+        if let Ok(current) = self.get_current_display_mode(output).await {
+            let differs = current.width != mode.width
+                || current.height != mode.height
+                || (current.refresh_rate - mode.refresh_rate).abs() > options.refresh_tolerance;
+            if differs {
+                return Err(Error::VerificationFailed(format!(
+                    "Switched to {} but the display reports {}",
+                    mode, current
+                ))
+                .into());
+            }
+        }
+        Ok(mode)
+    }
+
+    /// The mode `switch_display` would choose for `spec`, without applying it
+    pub async fn resolve_mode(
+        &self,
+        output: Option<&str>,
+        spec: &DisplaySpec,
+        options: &MatchOptions,
+    ) -> Result<DisplayMode> {
+        let available_modes = self.platform_manager.get_available_modes(output).await?;
 
-        let target_mode = if exact {
+        let target_mode = if options.exact {
             // For exact match, find a mode that exactly matches the specification
-            self.find_exact_match(spec, &available_modes)
+            self.find_exact_match(spec, &available_modes, options.refresh_tolerance)
         } else {
             // For closest match, use the spec's logic to find the best mode
-            spec.to_concrete_spec(&available_modes)
+            spec.to_concrete_spec(&available_modes, options)
         };
 
-        match target_mode {
-            Some(mode) => {
-                self.platform_manager.set_display_mode(&mode).await?;
-                Ok(mode)
-            }
-            None => Err(anyhow!(
+        target_mode.ok_or_else(|| {
+            Error::NoSuitableMode(format!(
                 "No suitable display mode found for specification: {}",
                 spec
-            )),
+            ))
+            .into()
+        })
+    }
+
+    /// Switches every target to the first of its specifications that can be
+    /// applied. If any target cannot be switched, the outputs already changed
+    /// are put back into their previous modes before the error is returned.
+    pub async fn switch_outputs(
+        &self,
+        targets: &[OutputTarget],
+        options: &MatchOptions,
+    ) -> Result<Vec<AppliedMode>> {
+        let mut previous_modes = Vec::with_capacity(targets.len());
+        for target in targets {
+            let output = target.output.as_deref();
+            previous_modes.push(self.get_current_display_mode(output).await.ok());
+        }
+
+        let mut applied = Vec::with_capacity(targets.len());
+        for (target, previous) in targets.iter().zip(previous_modes) {
+            let output = target.output.as_deref();
+            let mut switched = None;
+            let mut last_error = None;
+            for spec in &target.specs {
+                match self.switch_display(output, spec, options).await {
+                    Ok(mode) => {
+                        switched = Some((spec.clone(), mode));
+                        break;
+                    }
+                    Err(e) => {
+                        match output {
+                            Some(output) => eprintln!("Failed to switch {output} to {spec}: {e}"),
+                            None => eprintln!("Failed to switch to {spec}: {e}"),
+                        }
+                        last_error = Some(e);
+                    }
+                }
+            }
+
+            match switched {
+                Some((spec, mode)) => applied.push(AppliedMode {
+                    output: target.output.clone(),
+                    spec,
+                    mode,
+                    previous,
+                }),
+                None => {
+                    if let Err(e) = self.restore(&applied).await {
+                        eprintln!("Failed to restore previous display modes: {e}");
+                    }
+                    let message = match output {
+                        Some(output) => format!(
+                            "No suitable display specification could be applied to {}",
+                            output
+                        ),
+                        None => "No suitable display specification could be applied".to_string(),
+                    };
+                    // Anything but a missing mode keeps its cause, and its exit code
+                    return Err(match last_error {
+                        Some(e) if !matches!(Error::find(&e), Some(Error::NoSuitableMode(_))) => {
+                            e.context(message)
+                        }
+                        _ => Error::NoSuitableMode(message).into(),
+                    });
+                }
+            }
         }
+
+        Ok(applied)
     }
 
-    pub async fn list_available_modes(&self) -> Result<Vec<DisplayMode>> {
-        self.platform_manager.get_available_modes().await
+    /// Puts outputs changed by `switch_outputs` back into their previous
+    /// modes, most recent first
+    pub async fn restore(&self, applied: &[AppliedMode]) -> Result<()> {
+        let mut result = Ok(());
+        for change in applied.iter().rev() {
+            if let Some(previous) = &change.previous {
+                let restored = self
+                    .set_display_mode(change.output.as_deref(), previous)
+                    .await;
+                result = result.and(restored);
+            }
+        }
+        result
     }
 
-    pub async fn get_current_display_mode(&self) -> Result<DisplayMode> {
-        self.platform_manager.get_current_display_mode().await
+    pub async fn set_display_mode(&self, output: Option<&str>, mode: &DisplayMode) -> Result<()> {
+        self.platform_manager.set_display_mode(output, mode).await
+    }
+
+    pub async fn list_outputs(&self) -> Result<Vec<OutputInfo>> {
+        self.platform_manager.get_outputs().await
+    }
+
+    pub async fn list_available_modes(&self, output: Option<&str>) -> Result<Vec<DisplayMode>> {
+        self.platform_manager.get_available_modes(output).await
+    }
+
+    pub async fn get_current_display_mode(&self, output: Option<&str>) -> Result<DisplayMode> {
+        self.platform_manager.get_current_display_mode(output).await
     }
 
     fn find_exact_match(
         &self,
         spec: &DisplaySpec,
         available_modes: &[DisplayMode],
+        tolerance: f64,
     ) -> Option<DisplayMode> {
         for mode in available_modes {
             let mode_spec = DisplaySpec {
@@ -231,7 +501,7 @@ impl DisplayManager {
                 aspect_ratio: None,
             };
 
-            if spec.matches_exact(&mode_spec) {
+            if spec.matches_exact(&mode_spec, tolerance) {
                 return Some(mode.clone());
             }
         }
@@ -240,13 +510,16 @@ impl DisplayManager {
 }
 
 impl DisplaySpec {
-    fn matches_exact(&self, other: &DisplaySpec) -> bool {
-        // Check width and height
-        if let (Some(self_width), Some(self_height)) = (self.width, self.height) {
-            if let (Some(other_width), Some(other_height)) = (other.width, other.height) {
-                if self_width != other_width || self_height != other_height {
-                    return false;
-                }
+    fn matches_exact(&self, other: &DisplaySpec, tolerance: f64) -> bool {
+        // Check each dimension that is constrained, so `2560x*` still needs a 2560 width
+        if let (Some(self_width), Some(other_width)) = (self.width, other.width) {
+            if self_width != other_width {
+                return false;
+            }
+        }
+        if let (Some(self_height), Some(other_height)) = (self.height, other.height) {
+            if self_height != other_height {
+                return false;
             }
         }
 
@@ -265,7 +538,7 @@ impl DisplaySpec {
         // Check refresh rate
         if let Some(self_rate) = self.refresh_rate {
             if let Some(other_rate) = other.refresh_rate {
-                if (self_rate - other_rate).abs() > 0.1 {
+                if (self_rate - other_rate).abs() > tolerance {
                     return false;
                 }
             }
@@ -276,29 +549,43 @@ impl DisplaySpec {
 }
 
 impl fmt::Display for DisplaySpec {
+    /// Writes the canonical form of the specification, which always parses back
+    /// to an equal `DisplaySpec` (e.g. `1920x1080/16:9@59.94hz`, `21:9`, `*@60hz`).
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut parts = Vec::new();
+        let resolution = match (self.width, self.height) {
+            (Some(width), Some(height)) => Some(format!("{width}x{height}")),
+            (Some(width), None) => Some(format!("{width}x*")),
+            (None, Some(height)) => Some(format!("*x{height}")),
+            (None, None) => None,
+        };
 
-        if let (Some(width), Some(height)) = (self.width, self.height) {
-            parts.push(format!("{width}x{height}"));
-        } else if let Some((w_ratio, h_ratio)) = self.aspect_ratio {
-            parts.push(format!("{w_ratio}:{h_ratio}"));
+        match (resolution, self.aspect_ratio) {
+            (Some(resolution), Some((w_ratio, h_ratio))) => {
+                write!(f, "{resolution}/{w_ratio}:{h_ratio}")?
+            }
+            (Some(resolution), None) => write!(f, "{resolution}")?,
+            (None, Some((w_ratio, h_ratio))) => write!(f, "{w_ratio}:{h_ratio}")?,
+            (None, None) => write!(f, "*")?,
         }
 
         if let Some(rate) = self.refresh_rate {
-            if parts.is_empty() {
-                parts.push(format!("{rate}hz"));
-            } else {
-                parts[0] = format!("{}@{rate}hz", parts[0]);
-            }
+            write!(f, "@{rate}hz")?;
         }
 
-        write!(f, "{}", parts.join(" "))
+        Ok(())
+    }
+}
+
+impl FromStr for DisplaySpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        crate::parser::parse_display_spec(s)
     }
 }
 
 impl DisplayMode {
-    pub fn matches_filter(&self, filter: &DisplaySpec) -> bool {
+    pub fn matches_filter(&self, filter: &DisplaySpec, tolerance: f64) -> bool {
         // Check width and height
         if let (Some(filter_width), Some(filter_height)) = (filter.width, filter.height) {
             if self.width != filter_width || self.height != filter_height {
@@ -318,13 +605,56 @@ impl DisplayMode {
 
         // Check refresh rate
         if let Some(filter_rate) = filter.refresh_rate {
-            if (self.refresh_rate - filter_rate).abs() > 0.1 {
+            if (self.refresh_rate - filter_rate).abs() > tolerance {
                 return false;
             }
         }
 
         true
     }
+
+    /// Specifications that reproduce this mode, most specific first. With
+    /// `available` modes given, adds fallbacks for when the exact mode is
+    /// missing: the same resolution at any refresh rate, then the same aspect
+    /// ratio, each only if another available mode would satisfy it.
+    pub fn capture_specs(&self, available: &[DisplayMode]) -> Vec<DisplaySpec> {
+        let resolution = DisplaySpec {
+            width: Some(self.width),
+            height: Some(self.height),
+            refresh_rate: None,
+            aspect_ratio: None,
+        };
+        let mut specs = vec![DisplaySpec {
+            refresh_rate: Some(self.refresh_rate),
+            ..resolution.clone()
+        }];
+
+        let other_refresh = available.iter().any(|mode| {
+            mode.width == self.width
+                && mode.height == self.height
+                && mode.refresh_rate != self.refresh_rate
+        });
+        if other_refresh {
+            specs.push(resolution);
+        }
+
+        let gcd = gcd(self.width, self.height);
+        let aspect = DisplaySpec {
+            width: None,
+            height: None,
+            refresh_rate: None,
+            aspect_ratio: Some((self.width / gcd, self.height / gcd)),
+        };
+        let other_resolution = available.iter().any(|mode| {
+            (mode.width, mode.height) != (self.width, self.height)
+                && mode.matches_filter(&aspect, 0.0)
+        });
+        if other_resolution {
+            specs.push(aspect);
+        }
+
+        specs
+    }
 }
 
 impl fmt::Display for DisplayMode {
@@ -333,6 +663,13 @@ impl fmt::Display for DisplayMode {
     }
 }
 
+// Drops all but the modes at the smallest `distance`
+fn keep_closest(modes: &mut Vec<DisplayMode>, distance: impl Fn(&DisplayMode) -> u32) {
+    if let Some(min) = modes.iter().map(&distance).min() {
+        modes.retain(|mode| distance(mode) == min);
+    }
+}
+
 // Helper function to calculate greatest common divisor
 fn gcd(mut a: u32, mut b: u32) -> u32 {
     while b != 0 {
@@ -362,7 +699,7 @@ mod tests {
             refresh_rate: None,
             aspect_ratio: None,
         };
-        assert!(spec.matches_filter(&filter1));
+        assert!(spec.matches_filter(&filter1, DEFAULT_REFRESH_TOLERANCE));
 
         let filter2 = DisplaySpec {
             width: None,
@@ -370,7 +707,265 @@ mod tests {
             refresh_rate: Some(60.0),
             aspect_ratio: Some((16, 9)),
         };
-        assert!(spec.matches_filter(&filter2));
+        assert!(spec.matches_filter(&filter2, DEFAULT_REFRESH_TOLERANCE));
+    }
+
+    #[test]
+    fn test_display_spec_round_trip() {
+        let specs = [
+            DisplaySpec {
+                width: Some(1920),
+                height: Some(1080),
+                refresh_rate: Some(59.94),
+                aspect_ratio: Some((16, 9)),
+            },
+            DisplaySpec {
+                width: None,
+                height: None,
+                refresh_rate: Some(120.0),
+                aspect_ratio: Some((21, 9)),
+            },
+            DisplaySpec {
+                width: None,
+                height: None,
+                refresh_rate: Some(144.0),
+                aspect_ratio: None,
+            },
+            DisplaySpec {
+                width: Some(2560),
+                height: None,
+                refresh_rate: None,
+                aspect_ratio: None,
+            },
+            DisplaySpec {
+                width: None,
+                height: None,
+                refresh_rate: None,
+                aspect_ratio: None,
+            },
+        ];
+
+        for spec in specs {
+            let canonical = spec.to_string();
+            let reparsed: DisplaySpec = canonical.parse().unwrap();
+            assert_eq!(spec, reparsed, "{canonical} did not round-trip");
+        }
+
+        assert_eq!(
+            "4K@60FPS".parse::<DisplaySpec>().unwrap().to_string(),
+            "3840x2160@60hz"
+        );
+    }
+
+    #[test]
+    fn test_match_policy() {
+        let modes = vec![
+            DisplayMode {
+                width: 1920,
+                height: 1080,
+                refresh_rate: 50.0,
+            },
+            DisplayMode {
+                width: 1920,
+                height: 1080,
+                refresh_rate: 75.0,
+            },
+        ];
+        let spec = DisplaySpec {
+            width: Some(1920),
+            height: Some(1080),
+            refresh_rate: Some(60.0),
+            aspect_ratio: None,
+        };
+
+        let rate = |policy| {
+            let options = MatchOptions {
+                policy,
+                ..Default::default()
+            };
+            spec.to_concrete_spec(&modes, &options)
+                .unwrap()
+                .refresh_rate
+        };
+        assert_eq!(rate(MatchPolicy::PreferHigher), 75.0);
+        assert_eq!(rate(MatchPolicy::PreferLower), 50.0);
+        assert_eq!(rate(MatchPolicy::Nearest), 50.0);
+    }
+
+    #[test]
+    fn test_refresh_tolerance() {
+        let mode = DisplayMode {
+            width: 1920,
+            height: 1080,
+            refresh_rate: 59.5,
+        };
+        let filter: DisplaySpec = "1920x1080@60hz".parse().unwrap();
+
+        assert!(!mode.matches_filter(&filter, DEFAULT_REFRESH_TOLERANCE));
+        assert!(mode.matches_filter(&filter, 1.0));
+
+        // With no tolerance an exact rate still matches instead of a higher one
+        let modes = [60.0, 120.0, 144.0].map(|refresh_rate| DisplayMode {
+            refresh_rate,
+            ..mode.clone()
+        });
+        let no_tolerance = MatchOptions {
+            refresh_tolerance: 0.0,
+            ..Default::default()
+        };
+        let chosen = filter.to_concrete_spec(&modes, &no_tolerance).unwrap();
+        assert_eq!(chosen.refresh_rate, 60.0);
+    }
+
+    #[tokio::test]
+    async fn test_switch_outputs_rolls_back_on_failure() -> Result<()> {
+        use crate::platform::mock::MockOutput;
+
+        let mode = |width, height, refresh_rate| DisplayMode {
+            width,
+            height,
+            refresh_rate,
+        };
+        let output = |name: &str, primary| MockOutput {
+            name: name.to_string(),
+            primary,
+            modes: vec![mode(3840, 2160, 60.0), mode(1920, 1080, 60.0)],
+            current: mode(3840, 2160, 60.0),
+            identity: None,
+        };
+        let manager = DisplayManager::with_platform(PlatformDisplayManager::mock(vec![
+            output("DP-1", true),
+            output("HDMI-1", false),
+        ]));
+        let target = |output: &str, spec: &str| OutputTarget {
+            output: Some(output.to_string()),
+            specs: vec![spec.parse().unwrap()],
+        };
+        let exact = MatchOptions {
+            exact: true,
+            ..Default::default()
+        };
+
+        let failed = manager
+            .switch_outputs(
+                &[target("DP-1", "1080p@60hz"), target("HDMI-1", "1440p")],
+                &exact,
+            )
+            .await;
+        assert!(failed.is_err());
+        assert_eq!(
+            manager.get_current_display_mode(Some("DP-1")).await?,
+            mode(3840, 2160, 60.0)
+        );
+
+        let applied = manager
+            .switch_outputs(
+                &[target("DP-1", "1080p@60hz"), target("HDMI-1", "1080p")],
+                &exact,
+            )
+            .await?;
+        assert_eq!(applied.len(), 2);
+        assert_eq!(
+            manager.get_current_display_mode(Some("HDMI-1")).await?,
+            mode(1920, 1080, 60.0)
+        );
+
+        manager.restore(&applied).await?;
+        assert_eq!(
+            manager.get_current_display_mode(Some("HDMI-1")).await?,
+            mode(3840, 2160, 60.0)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_switch_with_partial_specs() -> Result<()> {
+        use crate::platform::mock::MockOutput;
+
+        let mode = |width, height, refresh_rate| DisplayMode {
+            width,
+            height,
+            refresh_rate,
+        };
+        let manager =
+            DisplayManager::with_platform(PlatformDisplayManager::mock(vec![MockOutput {
+                name: "DP-1".to_string(),
+                primary: true,
+                modes: vec![
+                    mode(3840, 2160, 60.0),
+                    mode(3440, 1440, 100.0),
+                    mode(2560, 1440, 144.0),
+                    mode(2560, 1440, 120.0),
+                    mode(2560, 1080, 60.0),
+                    mode(1920, 1080, 120.0),
+                ],
+                current: mode(3840, 2160, 60.0),
+                identity: None,
+            }]));
+        let switch = |spec: &str, exact| {
+            let spec: DisplaySpec = spec.parse().unwrap();
+            let options = MatchOptions {
+                exact,
+                ..Default::default()
+            };
+            let manager = &manager;
+            async move { manager.switch_display(None, &spec, &options).await }
+        };
+
+        // The largest mode at the chosen refresh rate
+        assert_eq!(switch("*@120hz", false).await?, mode(2560, 1440, 120.0));
+        assert_eq!(switch("*", false).await?, mode(2560, 1440, 144.0));
+        assert_eq!(switch("2560x*", false).await?, mode(2560, 1440, 144.0));
+        assert_eq!(switch("2560x*@60hz", false).await?, mode(2560, 1080, 60.0));
+        assert_eq!(
+            switch("*x1440@100hz", false).await?,
+            mode(3440, 1440, 100.0)
+        );
+        assert_eq!(switch("*x2160", false).await?, mode(3840, 2160, 60.0));
+        // The closest width when none matches, then the policy's refresh rate
+        assert_eq!(
+            switch("2500x*@130hz", false).await?,
+            mode(2560, 1440, 144.0)
+        );
+        assert_eq!(
+            manager.get_current_display_mode(None).await?,
+            mode(2560, 1440, 144.0)
+        );
+
+        assert_eq!(switch("*x1440@120hz", true).await?, mode(2560, 1440, 120.0));
+        assert!(switch("2500x*", true).await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_capture_specs() {
+        let mode = |width, height, refresh_rate| DisplayMode {
+            width,
+            height,
+            refresh_rate,
+        };
+        let current = mode(2560, 1440, 144.0);
+        let specs = |available: &[DisplayMode]| -> Vec<String> {
+            current
+                .capture_specs(available)
+                .iter()
+                .map(|spec| spec.to_string())
+                .collect()
+        };
+
+        assert_eq!(specs(&[]), ["2560x1440@144hz"]);
+        assert_eq!(
+            specs(&[
+                current.clone(),
+                mode(2560, 1440, 60.0),
+                mode(1920, 1080, 60.0)
+            ]),
+            ["2560x1440@144hz", "2560x1440", "16:9"]
+        );
+        assert_eq!(
+            specs(&[current.clone(), mode(1920, 1200, 60.0)]),
+            ["2560x1440@144hz"]
+        );
     }
 
     #[test]
@@ -0,0 +1,174 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+const BLOCK_LEN: usize = 128;
+
+/// Identifies a physical monitor independently of the connector it is plugged into
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonitorIdentity {
+    /// Three-letter PNP manufacturer ID, e.g. `DEL`
+    pub manufacturer: String,
+    /// Manufacturer-assigned product code
+    pub product: u16,
+    /// Serial number string, or the numeric serial if the monitor has none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+    /// Model name reported by the monitor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Hash of the raw EDID, for monitors that share a manufacturer, product and serial
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edid_hash: Option<String>,
+}
+
+impl MonitorIdentity {
+    /// Parses the base block of an EDID blob
+    pub fn from_edid(edid: &[u8]) -> Result<Self> {
+        if edid.len() < BLOCK_LEN || edid[..8] != HEADER {
+            return Err(anyhow!("Invalid EDID: missing header"));
+        }
+
+        let mut serial = None;
+        let mut name = None;
+        for descriptor in edid[54..126].chunks_exact(18) {
+            // Display descriptors start with a zero pixel clock
+            if descriptor[..3] != [0, 0, 0] {
+                continue;
+            }
+            match descriptor[3] {
+                0xff => serial = descriptor_text(descriptor),
+                0xfc => name = descriptor_text(descriptor),
+                _ => {}
+            }
+        }
+
+        let numeric_serial = u32::from_le_bytes([edid[12], edid[13], edid[14], edid[15]]);
+        Ok(Self {
+            manufacturer: manufacturer_id(u16::from_be_bytes([edid[8], edid[9]])),
+            product: u16::from_le_bytes([edid[10], edid[11]]),
+            serial: serial.or((numeric_serial != 0).then(|| numeric_serial.to_string())),
+            name,
+            edid_hash: Some(format!("{:016x}", fnv1a(edid))),
+        })
+    }
+
+    /// Builds an identity from the vendor number used in EDIDs and by
+    /// platform APIs (three 5-bit letters)
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn from_vendor(vendor: u16, product: u16, serial: u32) -> Self {
+        Self {
+            manufacturer: manufacturer_id(vendor),
+            product,
+            serial: (serial != 0).then(|| serial.to_string()),
+            name: None,
+            edid_hash: None,
+        }
+    }
+
+    /// Whether this monitor matches a profile's `monitor` value: either
+    /// `MFG:PRODUCT[:SERIAL]` (case-insensitive, product in hex) or `edid:<hash>`
+    pub fn matches(&self, pattern: &str) -> bool {
+        let pattern = pattern.trim();
+        if let Some(hash) = pattern.strip_prefix("edid:") {
+            return self
+                .edid_hash
+                .as_deref()
+                .is_some_and(|own| own.eq_ignore_ascii_case(hash));
+        }
+
+        let mut parts = pattern.splitn(3, ':');
+        let manufacturer = parts.next().unwrap_or_default();
+        let product = parts.next().and_then(|p| u16::from_str_radix(p, 16).ok());
+        let serial = parts.next();
+
+        manufacturer.eq_ignore_ascii_case(&self.manufacturer)
+            && product == Some(self.product)
+            && serial.is_none_or(|serial| self.serial.as_deref() == Some(serial))
+    }
+}
+
+/// `MFG:PRODUCT[:SERIAL]`, the form profiles use to refer to a monitor
+impl fmt::Display for MonitorIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{:04X}", self.manufacturer, self.product)?;
+        if let Some(serial) = &self.serial {
+            write!(f, ":{serial}")?;
+        }
+        Ok(())
+    }
+}
+
+fn manufacturer_id(vendor: u16) -> String {
+    [10, 5, 0]
+        .iter()
+        .map(|shift| (b'A' - 1 + ((vendor >> shift) & 0x1f) as u8) as char)
+        .collect()
+}
+
+fn descriptor_text(descriptor: &[u8]) -> Option<String> {
+    let text = &descriptor[5..];
+    let end = text.iter().position(|&b| b == b'\n').unwrap_or(text.len());
+    let text = String::from_utf8_lossy(&text[..end]).trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+// 64-bit FNV-1a: stable across platforms and releases, unlike std's hasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_edid() -> Vec<u8> {
+        let mut edid = vec![0u8; BLOCK_LEN];
+        edid[..8].copy_from_slice(&HEADER);
+        // "DEL" = 00100 00101 01100
+        edid[8..10].copy_from_slice(&0x10acu16.to_be_bytes());
+        edid[10..12].copy_from_slice(&0xa0b2u16.to_le_bytes());
+        edid[12..16].copy_from_slice(&1234u32.to_le_bytes());
+
+        let mut descriptor = |offset: usize, tag: u8, text: &str| {
+            edid[offset + 3] = tag;
+            let mut bytes = text.as_bytes().to_vec();
+            bytes.push(b'\n');
+            bytes.resize(13, b' ');
+            edid[offset + 5..offset + 18].copy_from_slice(&bytes);
+        };
+        descriptor(72, 0xfc, "DELL U2720Q");
+        descriptor(90, 0xff, "CFV9N13");
+        edid
+    }
+
+    #[test]
+    fn test_parse_edid() -> Result<()> {
+        let identity = MonitorIdentity::from_edid(&sample_edid())?;
+        assert_eq!(identity.manufacturer, "DEL");
+        assert_eq!(identity.product, 0xa0b2);
+        assert_eq!(identity.serial.as_deref(), Some("CFV9N13"));
+        assert_eq!(identity.name.as_deref(), Some("DELL U2720Q"));
+        assert_eq!(identity.to_string(), "DEL:A0B2:CFV9N13");
+
+        assert!(MonitorIdentity::from_edid(&[0u8; 16]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_identity_matching() -> Result<()> {
+        let identity = MonitorIdentity::from_edid(&sample_edid())?;
+        let hash = identity.edid_hash.clone().unwrap();
+
+        assert!(identity.matches("DEL:A0B2:CFV9N13"));
+        assert!(identity.matches("del:a0b2"));
+        assert!(identity.matches(&format!("edid:{hash}")));
+        assert!(!identity.matches("DEL:A0B2:OTHER"));
+        assert!(!identity.matches("SAM:A0B2"));
+        assert!(!identity.matches("edid:0000000000000000"));
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Exit code for failures that are not one of the kinds below
+pub const EXIT_FAILURE: u8 = 1;
+
+/// Failures scripts can tell apart, by exit code or by the `code` in JSON
+/// error output. They travel inside `anyhow` errors, so context added on the
+/// way up is kept; `Error::find` digs them out again. Each variant holds the
+/// message shown to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A display specification or placeholder could not be parsed
+    Parse(String),
+    /// A profile name that is not defined
+    ProfileNotFound(String),
+    /// An output that is not connected
+    OutputNotFound(String),
+    /// No available mode satisfies the specification
+    NoSuitableMode(String),
+    /// The display backend cannot be reached or cannot switch modes here
+    BackendUnavailable(String),
+    /// The backend refused to apply a mode
+    ApplyFailed(String),
+    /// The mode reported after a switch is not the one that was applied
+    VerificationFailed(String),
+    /// A configuration file is unreadable or inconsistent
+    InvalidConfig(String),
+}
+
+impl Error {
+    /// Stable identifier for JSON error output
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Parse(_) => "parse-error",
+            Error::ProfileNotFound(_) => "profile-not-found",
+            Error::OutputNotFound(_) => "output-not-found",
+            Error::NoSuitableMode(_) => "no-suitable-mode",
+            Error::BackendUnavailable(_) => "backend-unavailable",
+            Error::ApplyFailed(_) => "apply-failed",
+            Error::VerificationFailed(_) => "verification-failed",
+            Error::InvalidConfig(_) => "invalid-config",
+        }
+    }
+
+    /// Process exit code; 1 is any other failure and 2 a usage error
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Parse(_) => 3,
+            Error::ProfileNotFound(_) => 4,
+            Error::OutputNotFound(_) => 5,
+            Error::NoSuitableMode(_) => 6,
+            Error::BackendUnavailable(_) => 7,
+            Error::ApplyFailed(_) => 8,
+            Error::VerificationFailed(_) => 9,
+            Error::InvalidConfig(_) => 10,
+        }
+    }
+
+    /// The outermost typed error in `error`'s chain
+    pub fn find(error: &anyhow::Error) -> Option<&Error> {
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<Error>())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(message)
+            | Error::ProfileNotFound(message)
+            | Error::OutputNotFound(message)
+            | Error::NoSuitableMode(message)
+            | Error::BackendUnavailable(message)
+            | Error::ApplyFailed(message)
+            | Error::VerificationFailed(message)
+            | Error::InvalidConfig(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// How a failed command reports itself with `--format json`
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    pub code: &'static str,
+    pub exit_code: u8,
+    /// The message with every cause, as in text output
+    pub message: String,
+}
+
+impl ErrorReport {
+    pub fn new(error: &anyhow::Error) -> Self {
+        let typed = Error::find(error);
+        Self {
+            code: typed.map_or("error", Error::code),
+            exit_code: typed.map_or(EXIT_FAILURE, Error::exit_code),
+            message: format!("{error:#}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_error_report() {
+        let error = Err::<(), _>(Error::NoSuitableMode("No mode for 8k".to_string()))
+            .context("No suitable display specification in profile 'tv' could be applied")
+            .unwrap_err();
+        let report = ErrorReport::new(&error);
+        assert_eq!(report.code, "no-suitable-mode");
+        assert_eq!(report.exit_code, 6);
+        assert_eq!(
+            report.message,
+            "No suitable display specification in profile 'tv' could be applied: No mode for 8k"
+        );
+
+        let untyped = ErrorReport::new(&anyhow::anyhow!("Something else"));
+        assert_eq!((untyped.code, untyped.exit_code), ("error", EXIT_FAILURE));
+    }
+}
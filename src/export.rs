@@ -0,0 +1,283 @@
+use anyhow::{anyhow, Result};
+use std::fmt::Write;
+
+/// Configuration syntax `profile export --as` renders a profile in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// sway `output` blocks
+    Sway,
+    /// Hyprland `monitor=` lines
+    Hyprland,
+    /// A kanshi profile
+    Kanshi,
+    /// An `xrandr` command line
+    Xrandr,
+    /// xorg.conf `Monitor` sections with CVT modelines
+    XorgConf,
+}
+
+/// One output of a profile, ready to be rendered
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportedOutput {
+    /// Connector name; `None` for the default output when it is not resolved
+    pub name: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate: Option<f64>,
+}
+
+/// Renders `outputs` of profile `profile` in `format`
+pub fn render(format: ExportFormat, profile: &str, outputs: &[ExportedOutput]) -> Result<String> {
+    let mut rendered = String::new();
+    match format {
+        ExportFormat::Sway => {
+            writeln!(rendered, "# display-switch profile '{profile}'")?;
+            for output in outputs {
+                writeln!(rendered, "output {} {{", quoted(output.name.as_deref()))?;
+                writeln!(rendered, "    mode {}", mode_hz(output))?;
+                writeln!(rendered, "}}")?;
+            }
+        }
+        ExportFormat::Hyprland => {
+            writeln!(rendered, "# display-switch profile '{profile}'")?;
+            for output in outputs {
+                let mode = match output.refresh_rate {
+                    Some(rate) => format!("{}x{}@{}", output.width, output.height, rate),
+                    None => format!("{}x{}", output.width, output.height),
+                };
+                // An empty name applies to any monitor
+                writeln!(
+                    rendered,
+                    "monitor={},{},auto,1",
+                    output.name.as_deref().unwrap_or_default(),
+                    mode
+                )?;
+            }
+        }
+        ExportFormat::Kanshi => {
+            writeln!(rendered, "profile {} {{", quoted(Some(profile)))?;
+            for output in outputs {
+                writeln!(
+                    rendered,
+                    "    output {} mode {}",
+                    quoted(output.name.as_deref()),
+                    mode_hz(output)
+                )?;
+            }
+            writeln!(rendered, "}}")?;
+        }
+        ExportFormat::Xrandr => {
+            rendered.push_str("xrandr");
+            for output in outputs {
+                let name = named(output)?;
+                write!(
+                    rendered,
+                    " --output {} --mode {}x{}",
+                    name, output.width, output.height
+                )?;
+                if let Some(rate) = output.refresh_rate {
+                    write!(rendered, " --rate {rate}")?;
+                }
+            }
+            rendered.push('\n');
+        }
+        ExportFormat::XorgConf => {
+            writeln!(rendered, "# display-switch profile '{profile}'")?;
+            for output in outputs {
+                let name = named(output)?;
+                let modeline = cvt_modeline(
+                    output.width,
+                    output.height,
+                    output.refresh_rate.unwrap_or(60.0),
+                );
+                // The server uses the Monitor section named after the output
+                writeln!(rendered, "Section \"Monitor\"")?;
+                writeln!(rendered, "    Identifier \"{name}\"")?;
+                writeln!(rendered, "    Modeline {modeline}")?;
+                writeln!(
+                    rendered,
+                    "    Option \"PreferredMode\" \"{}\"",
+                    modeline.name
+                )?;
+                writeln!(rendered, "EndSection")?;
+            }
+        }
+    }
+    Ok(rendered)
+}
+
+// `*` for the default output, quoted when it contains spaces
+fn quoted(name: Option<&str>) -> String {
+    match name {
+        Some(name) if name.contains(char::is_whitespace) => format!("\"{name}\""),
+        Some(name) => name.to_string(),
+        None => "*".to_string(),
+    }
+}
+
+fn named(output: &ExportedOutput) -> Result<&str> {
+    output.name.as_deref().ok_or_else(|| {
+        anyhow!(
+            "This format needs output names; name the output in the profile or drop --no-resolve"
+        )
+    })
+}
+
+fn mode_hz(output: &ExportedOutput) -> String {
+    match output.refresh_rate {
+        Some(rate) => format!("{}x{}@{}Hz", output.width, output.height, rate),
+        None => format!("{}x{}", output.width, output.height),
+    }
+}
+
+/// Timings for one mode, as written on an xorg.conf `Modeline`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modeline {
+    pub name: String,
+    /// Pixel clock in kHz
+    pub clock: u32,
+    pub horizontal: [u32; 4],
+    pub vertical: [u32; 4],
+}
+
+impl std::fmt::Display for Modeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [hdisplay, hsync_start, hsync_end, htotal] = self.horizontal;
+        let [vdisplay, vsync_start, vsync_end, vtotal] = self.vertical;
+        write!(
+            f,
+            "\"{}\" {:.2} {} {} {} {} {} {} {} {} -hsync +vsync",
+            self.name,
+            f64::from(self.clock) / 1000.0,
+            hdisplay,
+            hsync_start,
+            hsync_end,
+            htotal,
+            vdisplay,
+            vsync_start,
+            vsync_end,
+            vtotal
+        )
+    }
+}
+
+/// Computes a VESA CVT mode with standard blanking, as the `cvt` tool does
+pub fn cvt_modeline(width: u32, height: u32, refresh_rate: f64) -> Modeline {
+    const H_GRANULARITY: u32 = 8;
+    const MIN_V_PORCH: u32 = 3;
+    const MIN_VSYNC_BP: f64 = 550.0;
+    const HSYNC_PERCENTAGE: u32 = 8;
+    const CLOCK_STEP: u32 = 250;
+    // Blanking formula: C' = (C - J) * K / 256 + J and M' = M * K / 256
+    const C_PRIME: f64 = 30.0;
+    const M_PRIME: f64 = 300.0;
+
+    let hdisplay = width - width % H_GRANULARITY;
+    let vdisplay = height;
+
+    // The vsync width encodes the aspect ratio
+    let ratio = |w: u32, h: u32| vdisplay.is_multiple_of(h) && vdisplay * w / h == hdisplay;
+    let vsync = if ratio(4, 3) {
+        4
+    } else if ratio(16, 9) {
+        5
+    } else if ratio(16, 10) {
+        6
+    } else if ratio(5, 4) || ratio(15, 9) {
+        7
+    } else {
+        10
+    };
+
+    let hperiod = (1_000_000.0 / refresh_rate - MIN_VSYNC_BP) / f64::from(vdisplay + MIN_V_PORCH);
+    let vsync_and_back_porch = ((MIN_VSYNC_BP / hperiod) as u32 + 1).max(vsync + MIN_V_PORCH);
+    let vtotal = vdisplay + vsync_and_back_porch + MIN_V_PORCH;
+
+    let blank_percentage = (C_PRIME - M_PRIME * hperiod / 1000.0).max(20.0);
+    let mut hblank = (f64::from(hdisplay) * blank_percentage / (100.0 - blank_percentage)) as u32;
+    hblank -= hblank % (2 * H_GRANULARITY);
+    let htotal = hdisplay + hblank;
+
+    let hsync_end = hdisplay + hblank / 2;
+    let mut hsync_start = hsync_end - htotal * HSYNC_PERCENTAGE / 100;
+    hsync_start += H_GRANULARITY - hsync_start % H_GRANULARITY;
+
+    let vsync_start = vdisplay + MIN_V_PORCH;
+    let mut clock = (f64::from(htotal) * 1000.0 / hperiod) as u32;
+    clock -= clock % CLOCK_STEP;
+
+    Modeline {
+        name: format!("{width}x{height}_{refresh_rate:.2}"),
+        clock,
+        horizontal: [hdisplay, hsync_start, hsync_end, htotal],
+        vertical: [vdisplay, vsync_start, vsync_start + vsync, vtotal],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cvt_modeline() {
+        // As printed by `cvt 1920 1080 60` and `cvt 2560 1440 144`
+        assert_eq!(
+            cvt_modeline(1920, 1080, 60.0).to_string(),
+            "\"1920x1080_60.00\" 173.00 1920 2048 2248 2576 1080 1083 1088 1120 -hsync +vsync"
+        );
+        assert_eq!(
+            cvt_modeline(2560, 1440, 144.0).to_string(),
+            "\"2560x1440_144.00\" 808.75 2560 2792 3072 3584 1440 1443 1448 1568 -hsync +vsync"
+        );
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let outputs = [
+            ExportedOutput {
+                name: Some("HDMI-A-1".to_string()),
+                width: 3840,
+                height: 2160,
+                refresh_rate: Some(60.0),
+            },
+            ExportedOutput {
+                name: Some("DP-1".to_string()),
+                width: 2560,
+                height: 1440,
+                refresh_rate: None,
+            },
+        ];
+
+        assert_eq!(
+            render(ExportFormat::Sway, "tv", &outputs)?,
+            "# display-switch profile 'tv'\noutput HDMI-A-1 {\n    mode 3840x2160@60Hz\n}\noutput DP-1 {\n    mode 2560x1440\n}\n"
+        );
+        assert_eq!(
+            render(ExportFormat::Hyprland, "tv", &outputs[..1])?,
+            "# display-switch profile 'tv'\nmonitor=HDMI-A-1,3840x2160@60,auto,1\n"
+        );
+        assert_eq!(
+            render(ExportFormat::Kanshi, "living room", &outputs)?,
+            "profile \"living room\" {\n    output HDMI-A-1 mode 3840x2160@60Hz\n    output DP-1 mode 2560x1440\n}\n"
+        );
+        assert_eq!(
+            render(ExportFormat::Xrandr, "tv", &outputs)?,
+            "xrandr --output HDMI-A-1 --mode 3840x2160 --rate 60 --output DP-1 --mode 2560x1440\n"
+        );
+        let xorg = render(ExportFormat::XorgConf, "tv", &outputs[1..])?;
+        assert!(xorg.contains("Identifier \"DP-1\""), "{xorg}");
+        assert!(
+            xorg.contains("Option \"PreferredMode\" \"2560x1440_60.00\""),
+            "{xorg}"
+        );
+
+        // Unnamed outputs only work where the syntax has a wildcard
+        let unnamed = [ExportedOutput {
+            name: None,
+            ..outputs[0].clone()
+        }];
+        assert!(render(ExportFormat::Sway, "tv", &unnamed)?.contains("output * {"));
+        assert!(render(ExportFormat::Xrandr, "tv", &unnamed).is_err());
+        Ok(())
+    }
+}
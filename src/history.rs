@@ -0,0 +1,332 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config;
+use crate::display::{AppliedMode, DisplayMode};
+
+/// Number of switches kept; older entries are dropped first
+pub const HISTORY_LIMIT: usize = 100;
+
+/// What a switch was asked to do
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Request {
+    Specs(Vec<String>),
+    Profile(String),
+    /// `undo`, with the number of switches it stepped back
+    Undo(usize),
+    /// Putting back modes that were not confirmed
+    Revert,
+    /// `sunshine do`, with the specifications it tried
+    #[serde(rename = "sunshine-do")]
+    SunshineDo(Vec<String>),
+    /// `sunshine undo`, back to the mode saved by `sunshine do`
+    #[serde(rename = "sunshine-undo")]
+    SunshineUndo,
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Request::Specs(specs) => write!(f, "{}", specs.join(", ")),
+            Request::Profile(name) => write!(f, "profile '{name}'"),
+            Request::Undo(steps) => write!(f, "undo {steps}"),
+            Request::Revert => write!(f, "revert"),
+            Request::SunshineDo(specs) => write!(f, "sunshine do {}", specs.join(", ")),
+            Request::SunshineUndo => write!(f, "sunshine undo"),
+        }
+    }
+}
+
+/// One output's mode before and after a switch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModeChange {
+    pub output: Option<String>,
+    pub previous: Option<DisplayMode>,
+    pub mode: DisplayMode,
+}
+
+impl From<&AppliedMode> for ModeChange {
+    fn from(applied: &AppliedMode) -> Self {
+        Self {
+            output: applied.output.clone(),
+            previous: applied.previous.clone(),
+            mode: applied.mode.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub request: Request,
+    pub changes: Vec<ModeChange>,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Set once `undo` has stepped back over this switch
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub undone: bool,
+}
+
+impl HistoryEntry {
+    pub fn new(request: Request, changes: Vec<ModeChange>, error: Option<String>) -> Self {
+        Self {
+            id: 0,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            request,
+            changes,
+            success: error.is_none(),
+            error,
+            undone: false,
+        }
+    }
+
+    /// Whether `undo` can step back over this entry. Entries that themselves
+    /// put back earlier modes cannot.
+    fn undoable(&self) -> bool {
+        self.success
+            && !self.undone
+            && !matches!(
+                self.request,
+                Request::Undo(_) | Request::Revert | Request::SunshineUndo
+            )
+    }
+}
+
+/// The switch history file, kept next to the profiles file
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn beside(config_file: &Path) -> Self {
+        let dir = config_file.parent().unwrap_or(Path::new(""));
+        Self {
+            path: dir.join("history.json"),
+        }
+    }
+
+    /// All entries, oldest first
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(&self.path)?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", self.path.display()))
+    }
+
+    /// Appends `entry` under the next id, dropping the oldest entries beyond
+    /// `HISTORY_LIMIT`
+    pub fn record(&self, mut entry: HistoryEntry) -> Result<()> {
+        self.update(|entries| {
+            entry.id = entries.last().map_or(1, |last| last.id + 1);
+            entries.push(entry);
+            let excess = entries.len().saturating_sub(HISTORY_LIMIT);
+            entries.drain(..excess);
+        })
+    }
+
+    /// The latest `steps` switches `undo` would step back over, newest first
+    pub fn undo_steps(&self, steps: usize) -> Result<Vec<HistoryEntry>> {
+        let undoable: Vec<_> = self
+            .entries()?
+            .into_iter()
+            .rev()
+            .filter(HistoryEntry::undoable)
+            .take(steps)
+            .collect();
+        if undoable.is_empty() {
+            return Err(anyhow!("There is no switch to undo"));
+        }
+        if undoable.len() < steps {
+            return Err(anyhow!(
+                "Only {} switch(es) in the history can be undone",
+                undoable.len()
+            ));
+        }
+        Ok(undoable)
+    }
+
+    /// Marks the entries with the given ids as undone
+    pub fn mark_undone(&self, ids: &[u64]) -> Result<()> {
+        self.update(|entries| {
+            for entry in entries.iter_mut().filter(|e| ids.contains(&e.id)) {
+                entry.undone = true;
+            }
+        })
+    }
+
+    /// Marks the latest switch `undo` could step back over whose request
+    /// satisfies `is_match` as undone, e.g. the `sunshine do` that a
+    /// `sunshine undo` reverses
+    pub fn mark_latest_undone(&self, is_match: impl Fn(&Request) -> bool) -> Result<()> {
+        self.update(|entries| {
+            let latest = entries
+                .iter_mut()
+                .rev()
+                .find(|e| e.undoable() && is_match(&e.request));
+            if let Some(entry) = latest {
+                entry.undone = true;
+            }
+        })
+    }
+
+    /// Records that the latest switch to `changes` was reverted after all,
+    /// e.g. because it was not confirmed
+    pub fn mark_reverted(&self, changes: &[ModeChange], reason: &str) -> Result<()> {
+        self.update(|entries| {
+            if let Some(entry) = entries.iter_mut().rev().find(|e| e.changes == changes) {
+                entry.success = false;
+                entry.error = Some(reason.to_string());
+            }
+        })
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Vec<HistoryEntry>)) -> Result<()> {
+        let _lock = config::lock(&self.path)?;
+        let mut entries = self.entries()?;
+        f(&mut entries);
+        config::write_atomically(&self.path, &serde_json::to_string_pretty(&entries)?)
+    }
+}
+
+/// The modes that undoing `steps` (newest first) returns to: for each output,
+/// the mode before the oldest of the switches that changed it
+pub fn undo_targets(steps: &[HistoryEntry]) -> Vec<(Option<String>, DisplayMode)> {
+    let mut targets: Vec<(Option<String>, DisplayMode)> = Vec::new();
+    for change in steps.iter().flat_map(|entry| &entry.changes) {
+        let Some(previous) = &change.previous else {
+            continue;
+        };
+        match targets
+            .iter_mut()
+            .find(|(output, _)| *output == change.output)
+        {
+            Some(target) => target.1 = previous.clone(),
+            None => targets.push((change.output.clone(), previous.clone())),
+        }
+    }
+    targets
+}
+
+/// `2024-05-01 18:30:00 UTC`
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mode(width: u32, height: u32) -> DisplayMode {
+        DisplayMode {
+            width,
+            height,
+            refresh_rate: 60.0,
+        }
+    }
+
+    fn switch(output: &str, previous: DisplayMode, new: DisplayMode) -> HistoryEntry {
+        HistoryEntry::new(
+            Request::Specs(vec![new.to_string()]),
+            vec![ModeChange {
+                output: Some(output.to_string()),
+                previous: Some(previous),
+                mode: new,
+            }],
+            None,
+        )
+    }
+
+    #[test]
+    fn test_history_undo() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let history = History::beside(&dir.path().join("profiles.json"));
+        assert!(history.entries()?.is_empty());
+
+        history.record(switch("A", mode(1920, 1080), mode(3840, 2160)))?;
+        history.record(HistoryEntry::new(
+            Request::Profile("tv".to_string()),
+            Vec::new(),
+            Some("failed".to_string()),
+        ))?;
+        history.record(switch("B", mode(1280, 720), mode(1920, 1080)))?;
+        history.record(switch("A", mode(3840, 2160), mode(2560, 1440)))?;
+
+        // Failed switches are skipped; the oldest previous mode per output wins
+        let steps = history.undo_steps(3)?;
+        assert_eq!(steps.iter().map(|e| e.id).collect::<Vec<_>>(), [4, 3, 1]);
+        assert_eq!(
+            undo_targets(&steps),
+            [
+                (Some("A".to_string()), mode(1920, 1080)),
+                (Some("B".to_string()), mode(1280, 720)),
+            ]
+        );
+        assert!(history.undo_steps(4).is_err());
+
+        // Undone switches are not stepped back over twice
+        history.mark_undone(&[4])?;
+        assert_eq!(history.undo_steps(1)?[0].id, 3);
+
+        // Neither is a `sunshine do` once `sunshine undo` has reversed it, nor
+        // the `sunshine undo` itself
+        history.record(HistoryEntry::new(
+            Request::SunshineDo(vec!["1280x720@60hz".to_string()]),
+            vec![ModeChange {
+                output: Some("A".to_string()),
+                previous: Some(mode(1920, 1080)),
+                mode: mode(1280, 720),
+            }],
+            None,
+        ))?;
+        history.record(HistoryEntry::new(Request::SunshineUndo, Vec::new(), None))?;
+        history.mark_latest_undone(|request| matches!(request, Request::SunshineDo(_)))?;
+        assert_eq!(history.undo_steps(1)?[0].id, 3);
+
+        for _ in 0..HISTORY_LIMIT {
+            history.record(switch("A", mode(1920, 1080), mode(1920, 1080)))?;
+        }
+        let entries = history.entries()?;
+        assert_eq!(entries.len(), HISTORY_LIMIT);
+        assert_eq!(entries[0].id, 7);
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_709_210_096), "2024-02-29 12:34:56 UTC");
+    }
+}
@@ -0,0 +1,414 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::display::{AppliedMode, DisplayManager, MatchOptions, OutputTarget};
+
+/// Seconds a hook may run before it is killed, unless configured otherwise
+pub const DEFAULT_HOOK_TIMEOUT: u64 = 30;
+
+/// Shell commands run around a switch
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Hooks {
+    /// Runs before anything changes; a failure aborts the switch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_switch: Option<String>,
+    /// Runs after each output has been switched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_switch: Option<String>,
+    /// Runs when the switch fails or a `pre_switch` hook aborts it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_failure: Option<String>,
+    /// Seconds each hook may run before it is killed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns `self` with every hook set in `other` replaced by `other`'s
+    pub fn overlay(mut self, other: &Hooks) -> Self {
+        macro_rules! overlay {
+            ($($field:ident),*) => {
+                $(if other.$field.is_some() {
+                    self.$field = other.$field.clone();
+                })*
+            };
+        }
+        overlay!(pre_switch, post_switch, on_failure, timeout);
+        self
+    }
+}
+
+/// The hooks that apply to one switch: the global ones from the settings,
+/// then those of the profile being applied
+pub struct SwitchHooks {
+    layers: Vec<Hooks>,
+    profile: Option<String>,
+}
+
+impl SwitchHooks {
+    pub fn new(global: Hooks, profile: Option<(&str, &Hooks)>) -> Self {
+        let mut layers = vec![global];
+        layers.extend(profile.map(|(_, hooks)| hooks.clone()));
+        Self {
+            layers: layers.into_iter().filter(|h| !h.is_empty()).collect(),
+            profile: profile.map(|(name, _)| name.to_string()),
+        }
+    }
+
+    /// Name of the profile being applied, if any
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Runs `pre_switch` for every target before any of them changes. The
+    /// new mode is the one the first specification that can be applied
+    /// resolves to under `options`.
+    pub async fn pre_switch(
+        &self,
+        display_manager: &DisplayManager,
+        targets: &[OutputTarget],
+        options: &MatchOptions,
+    ) -> Result<()> {
+        if self.commands(|h| &h.pre_switch).next().is_none() {
+            return Ok(());
+        }
+
+        for target in targets {
+            let output = target.output.as_deref();
+            let mut env = self.env("pre_switch");
+            env.push(("OUTPUT", output_name(display_manager, output).await));
+            if let Ok(mode) = display_manager.get_current_display_mode(output).await {
+                env.push(("OLD_MODE", mode.to_string()));
+            }
+            let mut new = None;
+            for spec in &target.specs {
+                if let Ok(mode) = display_manager.resolve_mode(output, spec, options).await {
+                    new = Some((spec, Some(mode)));
+                    break;
+                }
+            }
+            // Without a mode to switch to, the switch fails after the hook
+            if let Some((spec, mode)) = new.or_else(|| target.specs.first().map(|s| (s, None))) {
+                env.push(("NEW_SPEC", spec.to_string()));
+                env.extend(mode.map(|mode| ("NEW_MODE", mode.to_string())));
+            }
+
+            for (command, timeout) in self.commands(|h| &h.pre_switch) {
+                run(command, &env, timeout)
+                    .await
+                    .map_err(|e| anyhow!("pre_switch hook failed, switch aborted: {e}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `post_switch` for every output that changed. Failures are only
+    /// reported, as the new modes are already in place.
+    pub async fn post_switch(&self, display_manager: &DisplayManager, applied: &[AppliedMode]) {
+        for change in applied {
+            let mut env = self.env("post_switch");
+            env.push((
+                "OUTPUT",
+                output_name(display_manager, change.output.as_deref()).await,
+            ));
+            if let Some(previous) = &change.previous {
+                env.push(("OLD_MODE", previous.to_string()));
+            }
+            env.push(("NEW_MODE", change.mode.to_string()));
+            env.push(("NEW_SPEC", change.spec.to_string()));
+
+            for (command, timeout) in self.commands(|h| &h.post_switch) {
+                if let Err(e) = run(command, &env, timeout).await {
+                    eprintln!("Warning: post_switch hook failed: {e}");
+                }
+            }
+        }
+    }
+
+    pub async fn on_failure(&self, error: &anyhow::Error) {
+        let mut env = self.env("on_failure");
+        env.push(("ERROR", format!("{error:#}")));
+
+        for (command, timeout) in self.commands(|h| &h.on_failure) {
+            if let Err(e) = run(command, &env, timeout).await {
+                eprintln!("Warning: on_failure hook failed: {e}");
+            }
+        }
+    }
+
+    fn commands<'a>(
+        &'a self,
+        hook: impl Fn(&Hooks) -> &Option<String> + 'a,
+    ) -> impl Iterator<Item = (&'a str, Duration)> + 'a {
+        self.layers.iter().filter_map(move |hooks| {
+            let timeout = hooks.timeout.unwrap_or(DEFAULT_HOOK_TIMEOUT);
+            hook(hooks)
+                .as_deref()
+                .map(|command| (command, Duration::from_secs(timeout)))
+        })
+    }
+
+    fn env(&self, event: &str) -> Vec<(&'static str, String)> {
+        let mut env = vec![("EVENT", event.to_string())];
+        env.extend(self.profile.clone().map(|profile| ("PROFILE", profile)));
+        env
+    }
+}
+
+// The primary output has no name in a target, so look it up for the hook
+async fn output_name(display_manager: &DisplayManager, output: Option<&str>) -> String {
+    if let Some(output) = output {
+        return output.to_string();
+    }
+    display_manager
+        .list_outputs()
+        .await
+        .ok()
+        .and_then(|outputs| outputs.into_iter().find(|o| o.primary))
+        .map(|o| o.name)
+        .unwrap_or_default()
+}
+
+/// Runs `command` through the shell with `DISPLAY_SWITCH_<NAME>` variables set
+async fn run(command: &str, env: &[(&str, String)], timeout: Duration) -> Result<()> {
+    let mut process = if cfg!(windows) {
+        let mut process = Command::new("cmd");
+        process.arg("/C").arg(command);
+        process
+    } else {
+        let mut process = Command::new("sh");
+        process.arg("-c").arg(command);
+        process
+    };
+    for (name, value) in env {
+        process.env(format!("DISPLAY_SWITCH_{name}"), value);
+    }
+
+    let mut child = process
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow!("Unable to run '{}': {}", command, e))?;
+    let status = match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => status?,
+        Err(_) => {
+            let _ = child.kill().await;
+            return Err(anyhow!(
+                "'{}' did not finish within {}s",
+                command,
+                timeout.as_secs()
+            ));
+        }
+    };
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("'{}' exited with {}", command, status))
+    }
+}
+
+// The hooks are run through `sh` in these tests
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::display::{DisplayMode, DisplaySpec};
+    use crate::platform::{mock, PlatformDisplayManager};
+    use std::path::Path;
+
+    fn hooks(pre_switch: Option<String>, timeout: Option<u64>) -> Hooks {
+        Hooks {
+            pre_switch,
+            timeout,
+            ..Hooks::default()
+        }
+    }
+
+    // A command writing the hook's DISPLAY_SWITCH_ variables to `file`, sorted
+    fn dump_env(file: &Path) -> Option<String> {
+        Some(format!(
+            "env | grep '^DISPLAY_SWITCH_' | sort > '{}'",
+            file.display()
+        ))
+    }
+
+    fn read_env(file: &Path) -> Result<Vec<String>> {
+        Ok(std::fs::read_to_string(file)?
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn test_run_hook() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let out = dir.path().join("env.txt");
+        let command = format!(
+            "echo \"$DISPLAY_SWITCH_EVENT $DISPLAY_SWITCH_NEW_MODE\" > '{}'",
+            out.display()
+        );
+
+        let env = [
+            ("EVENT", "post_switch".to_string()),
+            ("NEW_MODE", "1920x1080@60hz".to_string()),
+        ];
+        run(&command, &env, Duration::from_secs(5)).await?;
+        assert_eq!(
+            std::fs::read_to_string(&out)?.trim(),
+            "post_switch 1920x1080@60hz"
+        );
+
+        assert!(run("exit 3", &[], Duration::from_secs(5)).await.is_err());
+        let timed_out = run("sleep 5", &[], Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert!(timed_out.to_string().contains("did not finish"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hook_environment() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (pre, post, failure) = (
+            dir.path().join("pre"),
+            dir.path().join("post"),
+            dir.path().join("failure"),
+        );
+        let global = Hooks {
+            pre_switch: dump_env(&pre),
+            post_switch: dump_env(&post),
+            on_failure: dump_env(&failure),
+            timeout: None,
+        };
+        let switch_hooks = SwitchHooks::new(global, Some(("tv", &Hooks::default())));
+        let display_manager =
+            DisplayManager::with_platform(PlatformDisplayManager::mock(mock::default_outputs()));
+        let spec: DisplaySpec = "1920x1080@60hz".parse()?;
+
+        // The primary output is named even though the target leaves it out,
+        // and the new mode is that of the first spec that can be applied
+        let targets = [OutputTarget {
+            output: None,
+            specs: vec!["7680x4320".parse()?, spec.clone()],
+        }];
+        let exact = MatchOptions {
+            exact: true,
+            ..MatchOptions::default()
+        };
+        switch_hooks
+            .pre_switch(&display_manager, &targets, &exact)
+            .await?;
+        assert_eq!(
+            read_env(&pre)?,
+            [
+                "DISPLAY_SWITCH_EVENT=pre_switch",
+                "DISPLAY_SWITCH_NEW_MODE=1920x1080@60hz",
+                "DISPLAY_SWITCH_NEW_SPEC=1920x1080@60hz",
+                "DISPLAY_SWITCH_OLD_MODE=3840x2160@60hz",
+                "DISPLAY_SWITCH_OUTPUT=MOCK-1",
+                "DISPLAY_SWITCH_PROFILE=tv",
+            ]
+        );
+
+        let applied = [AppliedMode {
+            output: Some("MOCK-2".to_string()),
+            spec,
+            mode: DisplayMode {
+                width: 1920,
+                height: 1080,
+                refresh_rate: 60.0,
+            },
+            previous: Some(DisplayMode {
+                width: 1280,
+                height: 720,
+                refresh_rate: 60.0,
+            }),
+        }];
+        switch_hooks.post_switch(&display_manager, &applied).await;
+        assert_eq!(
+            read_env(&post)?,
+            [
+                "DISPLAY_SWITCH_EVENT=post_switch",
+                "DISPLAY_SWITCH_NEW_MODE=1920x1080@60hz",
+                "DISPLAY_SWITCH_NEW_SPEC=1920x1080@60hz",
+                "DISPLAY_SWITCH_OLD_MODE=1280x720@60hz",
+                "DISPLAY_SWITCH_OUTPUT=MOCK-2",
+                "DISPLAY_SWITCH_PROFILE=tv",
+            ]
+        );
+
+        switch_hooks
+            .on_failure(&anyhow!("No mode for 8k").context("Switch failed"))
+            .await;
+        assert_eq!(
+            read_env(&failure)?,
+            [
+                "DISPLAY_SWITCH_ERROR=Switch failed: No mode for 8k",
+                "DISPLAY_SWITCH_EVENT=on_failure",
+                "DISPLAY_SWITCH_PROFILE=tv",
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failing_pre_switch_aborts() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let marker = dir.path().join("profile-hook-ran");
+        let profile = hooks(Some(format!("touch '{}'", marker.display())), None);
+        let switch_hooks = SwitchHooks::new(
+            hooks(Some("exit 3".to_string()), None),
+            Some(("tv", &profile)),
+        );
+        let display_manager =
+            DisplayManager::with_platform(PlatformDisplayManager::mock(mock::default_outputs()));
+        let targets = [OutputTarget {
+            output: Some("MOCK-2".to_string()),
+            specs: vec!["720p".parse()?],
+        }];
+
+        let error = switch_hooks
+            .pre_switch(&display_manager, &targets, &MatchOptions::default())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("switch aborted"), "{error}");
+        assert!(error.contains("'exit 3' exited with"), "{error}");
+        // The profile's hook comes after the failing global one and never runs
+        assert!(!marker.exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hook_timeout() -> Result<()> {
+        // Each layer's timeout covers only its own hooks
+        let switch_hooks = SwitchHooks::new(
+            hooks(Some("true".to_string()), Some(5)),
+            Some(("tv", &hooks(Some("sleep 5".to_string()), Some(1)))),
+        );
+        let display_manager =
+            DisplayManager::with_platform(PlatformDisplayManager::mock(mock::default_outputs()));
+        let targets = [OutputTarget {
+            output: Some("MOCK-1".to_string()),
+            specs: vec!["1080p".parse()?],
+        }];
+
+        let started = std::time::Instant::now();
+        let error = switch_hooks
+            .pre_switch(&display_manager, &targets, &MatchOptions::default())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("'sleep 5' did not finish within 1s"),
+            "{error}"
+        );
+        assert!(started.elapsed() < Duration::from_secs(4));
+        Ok(())
+    }
+}
@@ -0,0 +1,558 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::edid::MonitorIdentity;
+use crate::parser;
+use crate::profile::{MatchMode, MatchRule, OutputEntry, Profile, StoredSpec};
+
+/// Profiles converted from another tool's configuration, along with a note
+/// for everything that could not be carried over
+#[derive(Debug, Default)]
+pub struct Imported {
+    pub profiles: BTreeMap<String, Profile>,
+    pub notes: Vec<String>,
+}
+
+/// Where autorandr keeps its profiles by default
+pub fn default_autorandr_dir() -> Result<PathBuf> {
+    Ok(dirs::config_dir()
+        .ok_or_else(|| anyhow!("Unable to determine config directory"))?
+        .join("autorandr"))
+}
+
+/// Where kanshi reads its configuration by default
+pub fn default_kanshi_config() -> Result<PathBuf> {
+    Ok(dirs::config_dir()
+        .ok_or_else(|| anyhow!("Unable to determine config directory"))?
+        .join("kanshi")
+        .join("config"))
+}
+
+// An output as another tool configures it, before it becomes an entry
+struct ForeignOutput {
+    connector: Option<String>,
+    monitor: Option<String>,
+    spec: Option<String>,
+    disabled: bool,
+    ignored: Vec<String>,
+}
+
+impl ForeignOutput {
+    fn new(connector: Option<String>) -> Self {
+        Self {
+            connector,
+            monitor: None,
+            spec: None,
+            disabled: false,
+            ignored: Vec::new(),
+        }
+    }
+
+    fn label(&self) -> String {
+        self.connector
+            .clone()
+            .or_else(|| self.monitor.clone())
+            .unwrap_or_default()
+    }
+}
+
+// Builds a profile from the outputs of one foreign profile, noting what is
+// lost on the way. `match_outputs` lists the outputs the profile was for.
+fn build_profile(
+    imported: &mut Imported,
+    tool: &str,
+    name: &str,
+    outputs: Vec<ForeignOutput>,
+    match_outputs: Option<Vec<String>>,
+) {
+    let mut entries = Vec::new();
+    for output in outputs {
+        let label = output.label();
+        if !output.ignored.is_empty() {
+            imported.notes.push(format!(
+                "{tool} profile '{name}': ignored {} for {label}",
+                output.ignored.join(", ")
+            ));
+        }
+        if output.disabled {
+            imported.notes.push(format!(
+                "{tool} profile '{name}': {label} is turned off, which profiles cannot express; it was left out"
+            ));
+            continue;
+        }
+        let Some(spec) = output.spec else {
+            imported.notes.push(format!(
+                "{tool} profile '{name}': {label} has no mode; it was left out"
+            ));
+            continue;
+        };
+        match StoredSpec::new(&spec) {
+            Ok(spec) => entries.push(OutputEntry {
+                output: output.connector,
+                monitor: output.monitor,
+                specs: vec![spec],
+                optional: false,
+                when: Default::default(),
+            }),
+            Err(e) => imported
+                .notes
+                .push(format!("{tool} profile '{name}': {label}: {e}")),
+        }
+    }
+
+    if entries.is_empty() {
+        imported.notes.push(format!(
+            "{tool} profile '{name}' has no outputs that can be switched; it was not imported"
+        ));
+        return;
+    }
+
+    let profile = Profile {
+        outputs: entries,
+        match_rule: match_outputs.map(|outputs| MatchRule {
+            outputs,
+            mode: MatchMode::Exact,
+        }),
+        ..Default::default()
+    };
+    imported.profiles.insert(name.to_string(), profile);
+}
+
+// `1920x1080` and `60.00` or `1920x1080@59.95Hz` as a canonical spec
+fn mode_spec(mode: &str, rate: Option<&str>) -> Result<String> {
+    let mode = mode.to_lowercase();
+    let (resolution, mode_rate) = match mode.split_once('@') {
+        Some((resolution, rate)) => (resolution, Some(rate.trim_end_matches("hz"))),
+        None => (mode.as_str(), None),
+    };
+    let spec = match rate.or(mode_rate) {
+        Some(rate) => format!("{resolution}@{rate}hz"),
+        None => resolution.to_string(),
+    };
+    Ok(parser::parse_display_spec(&spec)?.to_string())
+}
+
+/// Reads every autorandr profile in `dir`: the outputs and modes from each
+/// `<profile>/config`, and the monitors from `<profile>/setup`
+pub fn autorandr(dir: &Path) -> Result<Imported> {
+    let mut profile_dirs: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read autorandr directory {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.join("config").is_file())
+        .collect();
+    profile_dirs.sort();
+
+    let mut imported = Imported::default();
+    for profile_dir in profile_dirs {
+        let name = profile_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let config = fs::read_to_string(profile_dir.join("config"))?;
+        let setup = fs::read_to_string(profile_dir.join("setup")).ok();
+        autorandr_profile(&mut imported, &name, &config, setup.as_deref());
+
+        for script in ["preswitch", "postswitch", "postsave"] {
+            if profile_dir.join(script).exists() || profile_dir.join(format!("{script}.d")).exists()
+            {
+                imported.notes.push(format!(
+                    "autorandr profile '{name}': the {script} script was not imported; see the hooks settings"
+                ));
+            }
+        }
+    }
+
+    if imported.profiles.is_empty() && imported.notes.is_empty() {
+        return Err(anyhow!("No autorandr profiles found in {}", dir.display()));
+    }
+    Ok(imported)
+}
+
+fn autorandr_profile(imported: &mut Imported, name: &str, config: &str, setup: Option<&str>) {
+    // setup: one `OUTPUT EDID-HEX` line per connected output
+    let mut monitors = Vec::new();
+    for line in setup.unwrap_or_default().lines() {
+        if let Some((output, edid)) = line.trim().split_once(char::is_whitespace) {
+            let identity = decode_hex(edid.trim())
+                .and_then(|edid| MonitorIdentity::from_edid(&edid).ok())
+                .map(|identity| identity.to_string());
+            monitors.push((output.to_string(), identity));
+        }
+    }
+    let monitor_of = |output: &str| {
+        monitors
+            .iter()
+            .find(|(name, _)| name == output)
+            .and_then(|(_, identity)| identity.clone())
+    };
+
+    let mut outputs: Vec<ForeignOutput> = Vec::new();
+    let mut rates = Vec::new();
+    for line in config.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(key, value)| (key, value.trim()));
+
+        if key == "output" {
+            let mut output = ForeignOutput::new(Some(value.to_string()));
+            output.monitor = monitor_of(value);
+            outputs.push(output);
+            rates.push(None);
+            continue;
+        }
+        let (Some(output), Some(rate)) = (outputs.last_mut(), rates.last_mut()) else {
+            imported.notes.push(format!(
+                "autorandr profile '{name}': ignored '{line}' before the first output"
+            ));
+            continue;
+        };
+        match key {
+            "off" => output.disabled = true,
+            "mode" => output.spec = Some(value.to_string()),
+            "rate" => *rate = Some(value.to_string()),
+            _ => output.ignored.push(key.to_string()),
+        }
+    }
+
+    // The rate may come before or after the mode
+    for (output, rate) in outputs.iter_mut().zip(rates) {
+        if let Some(mode) = output.spec.take() {
+            match mode_spec(&mode, rate.as_deref()) {
+                Ok(spec) => output.spec = Some(spec),
+                Err(e) => imported.notes.push(format!(
+                    "autorandr profile '{name}': {}: {e}",
+                    output.label()
+                )),
+            }
+        }
+    }
+
+    let match_outputs = (!monitors.is_empty()).then(|| {
+        monitors
+            .iter()
+            .map(|(output, identity)| identity.clone().unwrap_or_else(|| output.clone()))
+            .collect()
+    });
+    build_profile(imported, "autorandr", name, outputs, match_outputs);
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+// A kanshi directive: its words, and the block that follows it, if any
+struct Directive {
+    words: Vec<String>,
+    block: Option<Vec<Directive>>,
+}
+
+/// Converts the profiles of a kanshi configuration file
+pub fn kanshi(content: &str) -> Result<Imported> {
+    let tokens = tokenize(content)?;
+    let mut position = 0;
+    let directives = parse_block(&tokens, &mut position, false)?;
+
+    let mut imported = Imported::default();
+    let mut unnamed = 0;
+    for directive in directives {
+        match (directive.words[0].as_str(), directive.block) {
+            ("profile", Some(block)) => {
+                let name = match directive.words.get(1) {
+                    Some(name) => name.clone(),
+                    None => {
+                        unnamed += 1;
+                        format!("kanshi-{unnamed}")
+                    }
+                };
+                kanshi_profile(&mut imported, &name, block);
+            }
+            _ => imported.notes.push(format!(
+                "kanshi: ignored top-level '{}' directive",
+                directive.words.join(" ")
+            )),
+        }
+    }
+
+    if imported.profiles.is_empty() && imported.notes.is_empty() {
+        return Err(anyhow!("No kanshi profiles found"));
+    }
+    Ok(imported)
+}
+
+fn kanshi_profile(imported: &mut Imported, name: &str, block: Vec<Directive>) {
+    let mut outputs = Vec::new();
+    let mut criteria = Vec::new();
+    let mut matchable = true;
+    for directive in block {
+        match directive.words[0].as_str() {
+            "output" if directive.words.len() > 1 => {
+                let criterion = directive.words[1].clone();
+                // Descriptions ("Make Model Serial") and `*` cannot be
+                // expressed as a connector name or monitor identity
+                if criterion == "*" || criterion.contains(char::is_whitespace) {
+                    imported.notes.push(format!(
+                        "kanshi profile '{name}': output '{criterion}' is matched by description or wildcard, which profiles cannot express; it was left out"
+                    ));
+                    matchable = false;
+                    continue;
+                }
+                criteria.push(criterion.clone());
+
+                let mut options: Vec<String> = directive.words[2..].to_vec();
+                for nested in directive.block.into_iter().flatten() {
+                    options.extend(nested.words);
+                }
+                let mut output = ForeignOutput::new(Some(criterion));
+                let mut options = options.into_iter();
+                while let Some(option) = options.next() {
+                    match option.as_str() {
+                        "enable" => output.disabled = false,
+                        "disable" => output.disabled = true,
+                        "mode" => {
+                            let mut mode = options.next().unwrap_or_default();
+                            if mode == "--custom" {
+                                output.ignored.push("--custom".to_string());
+                                mode = options.next().unwrap_or_default();
+                            }
+                            match mode_spec(&mode, None) {
+                                Ok(spec) => output.spec = Some(spec),
+                                Err(e) => imported.notes.push(format!(
+                                    "kanshi profile '{name}': {}: {e}",
+                                    output.label()
+                                )),
+                            }
+                        }
+                        "position" | "scale" | "transform" | "adaptive_sync" | "alias" => {
+                            options.next();
+                            output.ignored.push(option);
+                        }
+                        _ => output.ignored.push(option),
+                    }
+                }
+                outputs.push(output);
+            }
+            _ => imported.notes.push(format!(
+                "kanshi profile '{name}': ignored '{}'",
+                directive.words.join(" ")
+            )),
+        }
+    }
+
+    // kanshi applies a profile when exactly its outputs are connected
+    let match_outputs = (matchable && !criteria.is_empty()).then_some(criteria);
+    build_profile(imported, "kanshi", name, outputs, match_outputs);
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Open,
+    Close,
+    End,
+}
+
+fn tokenize(content: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' | ';' => tokens.push(Token::End),
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '#' => while chars.next_if(|&c| c != '\n').is_some() {},
+            '"' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(c) => word.push(c),
+                        None => return Err(anyhow!("Unterminated quote in kanshi config")),
+                    }
+                }
+                tokens.push(Token::Word(word));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && !"{};#\"".contains(c))
+                {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_block(tokens: &[Token], position: &mut usize, nested: bool) -> Result<Vec<Directive>> {
+    let mut directives = Vec::new();
+    let mut words = Vec::new();
+    while let Some(token) = tokens.get(*position) {
+        *position += 1;
+        match token {
+            Token::Word(word) => words.push(word.clone()),
+            Token::End => {
+                if !words.is_empty() {
+                    directives.push(Directive {
+                        words: std::mem::take(&mut words),
+                        block: None,
+                    });
+                }
+            }
+            Token::Open => {
+                let block = parse_block(tokens, position, true)?;
+                if words.is_empty() {
+                    return Err(anyhow!("Unexpected '{{' in kanshi config"));
+                }
+                directives.push(Directive {
+                    words: std::mem::take(&mut words),
+                    block: Some(block),
+                });
+            }
+            Token::Close if nested => {
+                if !words.is_empty() {
+                    directives.push(Directive { words, block: None });
+                }
+                return Ok(directives);
+            }
+            Token::Close => return Err(anyhow!("Unexpected '}}' in kanshi config")),
+        }
+    }
+
+    if nested {
+        return Err(anyhow!("Missing '}}' in kanshi config"));
+    }
+    if !words.is_empty() {
+        directives.push(Directive { words, block: None });
+    }
+    Ok(directives)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specs(profile: &Profile) -> Vec<(Option<&str>, Option<&str>, &str)> {
+        profile
+            .outputs
+            .iter()
+            .map(|e| {
+                (
+                    e.output.as_deref(),
+                    e.monitor.as_deref(),
+                    e.specs[0].as_str(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_import_kanshi() -> Result<()> {
+        let config = r#"
+            # Laptop alone
+            profile nomad {
+                output eDP-1 enable mode 1920x1080@60Hz position 0,0 scale 1.5
+            }
+
+            profile docked {
+                output eDP-1 disable
+                output "Dell Inc. DELL U2720Q CFV9N13" mode 3840x2160
+                output DP-2 {
+                    mode --custom 2560x1440@59.951Hz
+                    transform 90
+                }
+                exec notify-send docked
+            }
+
+            profile {
+                output HDMI-A-1 mode 3840x2160@120Hz
+            }
+            include ~/.config/kanshi/extra
+        "#;
+        let imported = kanshi(config)?;
+
+        let names: Vec<_> = imported.profiles.keys().collect();
+        assert_eq!(names, ["docked", "kanshi-1", "nomad"]);
+        let nomad = &imported.profiles["nomad"];
+        assert_eq!(specs(nomad), [(Some("eDP-1"), None, "1920x1080@60hz")]);
+        assert_eq!(nomad.match_rule.as_ref().unwrap().outputs, ["eDP-1"]);
+
+        // Only DP-2 survives; the description keeps the profile from matching
+        let docked = &imported.profiles["docked"];
+        assert_eq!(specs(docked), [(Some("DP-2"), None, "2560x1440@59.951hz")]);
+        assert!(docked.match_rule.is_none());
+
+        let notes = imported.notes.join("\n");
+        for expected in [
+            "ignored position, scale for eDP-1",
+            "eDP-1 is turned off",
+            "'Dell Inc. DELL U2720Q CFV9N13' is matched by description",
+            "ignored --custom, transform for DP-2",
+            "ignored 'exec notify-send docked'",
+            "ignored top-level 'include ~/.config/kanshi/extra'",
+        ] {
+            assert!(
+                notes.contains(expected),
+                "missing '{expected}' in:\n{notes}"
+            );
+        }
+
+        assert!(kanshi("profile x {\n output DP-1 mode 1080p").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_autorandr() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let docked = dir.path().join("docked");
+        fs::create_dir_all(&docked)?;
+        fs::write(
+            docked.join("config"),
+            "output eDP-1\noff\noutput DP-1\ncrtc 0\nmode 2560x1440\npos 0x0\nprimary\nrate 143.91\n",
+        )?;
+        // 128 bytes of EDID for a DEL:A0B2 monitor without a serial string
+        let mut edid = [0u8; 128];
+        edid[..8].copy_from_slice(&[0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00]);
+        edid[8..10].copy_from_slice(&0x10acu16.to_be_bytes());
+        edid[10..12].copy_from_slice(&0xa0b2u16.to_le_bytes());
+        let hex: String = edid.iter().map(|b| format!("{b:02x}")).collect();
+        fs::write(docked.join("setup"), format!("DP-1 {hex}\neDP-1 *\n"))?;
+        fs::write(docked.join("postswitch"), "#!/bin/sh\n")?;
+        fs::create_dir_all(dir.path().join("postswitch.d"))?;
+
+        let imported = autorandr(dir.path())?;
+        let profile = &imported.profiles["docked"];
+        assert_eq!(
+            specs(profile),
+            [(Some("DP-1"), Some("DEL:A0B2"), "2560x1440@143.91hz")]
+        );
+        assert_eq!(
+            profile.match_rule.as_ref().unwrap().outputs,
+            ["DEL:A0B2", "eDP-1"]
+        );
+
+        let notes = imported.notes.join("\n");
+        assert!(notes.contains("eDP-1 is turned off"), "{notes}");
+        assert!(
+            notes.contains("ignored crtc, pos, primary for DP-1"),
+            "{notes}"
+        );
+        assert!(notes.contains("postswitch script"), "{notes}");
+
+        assert!(autorandr(&dir.path().join("missing")).is_err());
+        Ok(())
+    }
+}
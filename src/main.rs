@@ -1,181 +1,640 @@
 use anyhow::Result;
 use clap::Parser;
 
+mod audit;
+mod bundle;
 mod cli;
+mod commands;
+mod conditions;
+mod config;
 mod display;
+mod edid;
+mod error;
+mod export;
+mod history;
+mod hooks;
+mod import;
 mod parser;
 mod platform;
 mod profile;
+mod settings;
+mod sunshine;
+mod validate;
 
 use cli::{Args, ParsedArgs};
-use display::{DisplayManager, DisplaySpec};
-use profile::ProfileManager;
+use commands::switch::SpecOrProfile;
+use commands::{Expansion, Journal};
+use display::DisplayManager;
+use error::ErrorReport;
+use profile::{MatchRule, ProfileManager};
+use settings::{OutputFormat, Settings};
+use std::process::ExitCode;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse().into_parsed_args();
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    let mut format = args.format.unwrap_or_default();
 
-    let display_manager = DisplayManager::new()?;
-    let mut profile_manager = ProfileManager::new()?;
+    let Err(e) = run(args, &mut format).await else {
+        return ExitCode::SUCCESS;
+    };
+    let report = ErrorReport::new(&e);
+    if format == OutputFormat::Json {
+        let error = serde_json::json!({ "error": report });
+        eprintln!(
+            "{}",
+            serde_json::to_string_pretty(&error).unwrap_or_default()
+        );
+    } else {
+        eprintln!("Error: {e:?}");
+    }
+    ExitCode::from(report.exit_code)
+}
+
+// `format` is updated to the configured output format once settings are loaded,
+// so that errors after that point are reported in it
+async fn run(args: Args, format: &mut OutputFormat) -> Result<()> {
+    let config_file = args.config.clone();
+    let overrides = args.settings_overrides();
+    let expansion = Expansion {
+        variables: args.variables(),
+        verbose: args.verbose,
+    };
+    let args = args.into_parsed_args()?;
+
+    let mut profile_manager = ProfileManager::new(config_file)?;
+    let settings = profile_manager.settings().overlay(&overrides);
+    profile_manager.set_backend(settings.backend());
+    *format = settings.format();
+    let format_json = *format == OutputFormat::Json;
+    let journal = Journal::beside(profile_manager.config_file(), &settings);
+
+    // Switches are audited even when they fail before reaching the display
+    if let Some(record) = commands::attempted_switch(&args) {
+        journal.attempt(record);
+    }
+    let result = execute(
+        args,
+        &mut profile_manager,
+        &settings,
+        &expansion,
+        &journal,
+        overrides.output,
+        format_json,
+    )
+    .await;
+    if let Err(e) = &result {
+        journal.record_failed_attempt(e);
+    }
+    result
+}
+
+// `output` is the --output given on the command line, if any
+async fn execute(
+    args: ParsedArgs,
+    profile_manager: &mut ProfileManager,
+    settings: &Settings,
+    expansion: &Expansion,
+    journal: &Journal,
+    output: Option<String>,
+    format_json: bool,
+) -> Result<()> {
+    // Only commands that touch the display need a backend connection
+    let display_manager = || DisplayManager::new(settings.backend());
 
     match args {
-        ParsedArgs::Switch { spec, exact } => {
-            handle_switch(&display_manager, spec, exact).await?;
+        ParsedArgs::Switch { spec } => {
+            commands::switch::handle_switch(&display_manager()?, settings, journal, spec).await?;
         }
         ParsedArgs::List { spec, json } => {
-            handle_list(&display_manager, spec, json).await?;
+            commands::switch::handle_list(&display_manager()?, settings, spec, json || format_json)
+                .await?;
+        }
+        ParsedArgs::CreateProfile {
+            name,
+            spec,
+            outputs,
+            match_current,
+            force,
+        } => {
+            let match_rule = if match_current {
+                let connected = display_manager()?.list_outputs().await?;
+                Some(MatchRule::fingerprint(&connected))
+            } else {
+                None
+            };
+            commands::profile::handle_create_profile(
+                profile_manager,
+                name,
+                spec,
+                outputs,
+                match_rule,
+                force,
+            )?;
         }
-        ParsedArgs::CreateProfile { name, spec } => {
-            handle_create_profile(&mut profile_manager, name, spec)?;
+        ParsedArgs::DeleteProfile { name } => {
+            profile_manager.delete_profile(&name)?;
+            println!("Deleted profile: {name}");
+        }
+        ParsedArgs::RenameProfile { from, to, force } => {
+            profile_manager.rename_profile(&from, &to, force)?;
+            println!("Renamed profile '{from}' to '{to}'");
+        }
+        ParsedArgs::CopyProfile { from, to, force } => {
+            profile_manager.copy_profile(&from, &to, force)?;
+            println!("Copied profile '{from}' to '{to}'");
+        }
+        ParsedArgs::ShowProfile {
+            name,
+            resolved,
+            json,
+        } => {
+            commands::profile::handle_show_profile(
+                profile_manager,
+                name,
+                resolved,
+                json || format_json,
+            )?;
+        }
+        ParsedArgs::EditProfile { name } => {
+            commands::profile::handle_edit_profile(profile_manager, name)?;
         }
         ParsedArgs::Profile { name } => {
-            handle_profile(&display_manager, &profile_manager, name).await?;
+            commands::profile::handle_profile(
+                &display_manager()?,
+                profile_manager,
+                settings,
+                expansion,
+                journal,
+                name,
+            )
+            .await?;
         }
-        ParsedArgs::SpecOrProfile { value, exact } => {
-            // Check if the value is a profile name first
-            if profile_manager.profile_exists(&value) {
-                handle_profile(&display_manager, &profile_manager, value).await?;
-            } else {
-                // Treat it as a spec
-                handle_switch(&display_manager, vec![value], exact).await?;
+        ParsedArgs::AutoProfile => {
+            commands::profile::handle_auto_profile(
+                &display_manager()?,
+                profile_manager,
+                settings,
+                expansion,
+                journal,
+            )
+            .await?;
+        }
+        ParsedArgs::CaptureProfile {
+            name,
+            with_fallbacks,
+            force,
+        } => {
+            commands::profile::handle_capture_profile(
+                &display_manager()?,
+                profile_manager,
+                name,
+                with_fallbacks,
+                force,
+            )
+            .await?;
+        }
+        ParsedArgs::SpecOrProfile { value } => {
+            match commands::switch::spec_or_profile(profile_manager, &value)? {
+                SpecOrProfile::Profile(name) => {
+                    commands::profile::handle_profile(
+                        &display_manager()?,
+                        profile_manager,
+                        settings,
+                        expansion,
+                        journal,
+                        name,
+                    )
+                    .await?;
+                }
+                SpecOrProfile::Specs(specs) => {
+                    commands::switch::handle_switch(&display_manager()?, settings, journal, specs)
+                        .await?;
+                }
             }
         }
-        ParsedArgs::ListProfiles => {
-            handle_list_profiles(&profile_manager)?;
+        ParsedArgs::ExportProfiles {
+            names,
+            file,
+            render_as: Some(format),
+            no_resolve,
+        } => {
+            let display_manager = if no_resolve {
+                None
+            } else {
+                Some(display_manager()?)
+            };
+            commands::export::handle_render_profile(
+                display_manager.as_ref(),
+                profile_manager,
+                settings,
+                expansion,
+                &names,
+                format,
+                file.as_deref(),
+            )
+            .await?;
+        }
+        ParsedArgs::ExportProfiles {
+            names,
+            file,
+            render_as: None,
+            ..
+        } => {
+            commands::export::handle_export_profiles(profile_manager, &names, file.as_deref())?;
+        }
+        ParsedArgs::ImportProfiles { file, conflicts } => {
+            commands::import::handle_import_profiles(profile_manager, &file, conflicts)?;
+        }
+        ParsedArgs::ValidateProfiles {
+            name,
+            snapshot,
+            json,
+        } => {
+            let display_manager = match &snapshot {
+                Some(path) => DisplayManager::from_snapshot(path)?,
+                None => display_manager()?,
+            };
+            commands::validate::handle_validate_profiles(
+                &display_manager,
+                profile_manager,
+                settings,
+                expansion,
+                name,
+                json || format_json,
+            )
+            .await?;
+        }
+        ParsedArgs::LogTail {
+            lines,
+            follow,
+            json,
+        } => {
+            commands::audit::handle_log_tail(&journal.audit, lines, follow, json).await?;
+        }
+        ParsedArgs::History { limit, json } => {
+            commands::history::handle_history(&journal.history, limit, json || format_json)?;
+        }
+        ParsedArgs::Undo { steps } => {
+            commands::history::handle_undo(&display_manager()?, journal, steps).await?;
+        }
+        ParsedArgs::Snapshot { file } => {
+            commands::validate::handle_snapshot(&display_manager()?, file.as_deref()).await?;
+        }
+        ParsedArgs::ImportAutorandr { dir, conflicts } => {
+            commands::import::handle_import_autorandr(profile_manager, dir, conflicts)?;
+        }
+        ParsedArgs::ImportKanshi { file, conflicts } => {
+            commands::import::handle_import_kanshi(profile_manager, file, conflicts)?;
+        }
+        ParsedArgs::ListProfiles { all } => {
+            commands::profile::handle_list_profiles(profile_manager, all)?;
         }
         ParsedArgs::Current { json } => {
-            handle_current(&display_manager, json).await?;
+            commands::switch::handle_current(&display_manager()?, settings, json || format_json)
+                .await?;
+        }
+        ParsedArgs::SunshineDo {
+            profile,
+            fallback,
+            sunshine_config,
+        } => {
+            let profile_specs = match profile {
+                Some(name) => {
+                    let specs = expansion.expand_specs(&profile_manager.get_profile(&name)?)?;
+                    Some((name, specs))
+                }
+                None => None,
+            };
+            commands::sunshine::handle_sunshine_do(
+                &display_manager()?,
+                settings,
+                journal,
+                profile_specs,
+                fallback,
+                output,
+                sunshine_config,
+            )
+            .await?;
+        }
+        ParsedArgs::ConfigSchema => {
+            commands::config::handle_config_schema()?;
+        }
+        ParsedArgs::ConfigGet { key } => {
+            commands::config::handle_config_get(profile_manager, &key)?;
+        }
+        ParsedArgs::ConfigSet { key, value } => {
+            commands::config::handle_config_set(profile_manager, &key, value)?;
+        }
+        ParsedArgs::ConfigUnset { key } => {
+            commands::config::handle_config_unset(profile_manager, &key)?;
+        }
+        ParsedArgs::ConfigList => {
+            commands::config::handle_config_list(profile_manager, format_json)?;
+        }
+        ParsedArgs::SunshineUndo => {
+            commands::sunshine::handle_sunshine_undo(&display_manager()?, journal).await?;
+        }
+        ParsedArgs::SunshineInstall {
+            apps_file,
+            apps,
+            profile,
+            fallback,
+            uninstall,
+        } => {
+            commands::sunshine::handle_sunshine_install(
+                profile_manager,
+                apps_file,
+                apps,
+                profile,
+                fallback,
+                uninstall,
+            )?;
         }
     }
 
     Ok(())
 }
 
-async fn handle_switch(
-    display_manager: &DisplayManager,
-    specs: Vec<String>,
-    exact: bool,
-) -> Result<()> {
-    let parsed_specs: Result<Vec<DisplaySpec>, _> = specs
-        .iter()
-        .map(|s| parser::parse_display_spec(s))
-        .collect();
-    let parsed_specs = parsed_specs?;
-
-    for spec in parsed_specs {
-        match display_manager.switch_display(&spec, exact).await {
-            Ok(actual_mode) => {
-                println!(
-                    "Successfully switched to display specification: {actual_mode} (requested: {spec})"
-                );
-                return Ok(());
-            }
-            Err(e) => {
-                eprintln!("Failed to switch to {spec}: {e}");
-                continue;
-            }
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use platform::mock::{MockOutput, STATE_ENV};
 
-    anyhow::bail!("No suitable display specification could be applied");
-}
+    // The mock backend's state file comes from the environment, which is shared by all tests
+    static MOCK_STATE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
 
-async fn handle_list(
-    display_manager: &DisplayManager,
-    filter_spec: Option<String>,
-    json: bool,
-) -> Result<()> {
-    let available_modes = display_manager.list_available_modes().await?;
+    async fn run_with(config_file: &std::path::Path, args: &[&str]) -> Result<()> {
+        let mut argv = vec!["display-switch", "--config", config_file.to_str().unwrap()];
+        argv.extend(args);
+        run(Args::try_parse_from(argv)?, &mut OutputFormat::default()).await
+    }
 
-    let filtered_modes = if let Some(filter) = filter_spec {
-        let filter_spec = parser::parse_display_spec(&filter)?;
-        available_modes
+    fn current_modes(state_file: &std::path::Path) -> Result<Vec<(String, String)>> {
+        let outputs: Vec<MockOutput> = serde_json::from_str(&std::fs::read_to_string(state_file)?)?;
+        Ok(outputs
             .into_iter()
-            .filter(|mode| mode.matches_filter(&filter_spec))
-            .collect()
-    } else {
-        available_modes
-    };
-
-    if json {
-        println!("{}", serde_json::to_string_pretty(&filtered_modes)?);
-    } else {
-        for mode in filtered_modes {
-            println!("{mode}");
-        }
+            .map(|output| (output.name, output.current.to_string()))
+            .collect())
     }
 
-    Ok(())
-}
+    #[tokio::test]
+    async fn test_settings_drive_switching_end_to_end() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_file = dir.path().join("profiles.toml");
+        let state_file = dir.path().join("mock-state.json");
+        let _guard = MOCK_STATE_LOCK.lock().await;
+        std::env::set_var(STATE_ENV, &state_file);
 
-fn handle_create_profile(
-    profile_manager: &mut ProfileManager,
-    name: String,
-    specs: Vec<String>,
-) -> Result<()> {
-    let parsed_specs: Result<Vec<DisplaySpec>, _> = specs
-        .iter()
-        .map(|s| parser::parse_display_spec(s))
-        .collect();
-    let parsed_specs = parsed_specs?;
-
-    profile_manager.create_profile(name.clone(), parsed_specs)?;
-    println!("Created profile: {name}");
-    Ok(())
-}
+        run_with(&config_file, &["config", "set", "backend", "mock"]).await?;
+        run_with(&config_file, &["config", "set", "exact", "true"]).await?;
 
-async fn handle_profile(
-    display_manager: &DisplayManager,
-    profile_manager: &ProfileManager,
-    name: String,
-) -> Result<()> {
-    let specs = profile_manager.get_profile(&name)?;
-
-    for spec in specs {
-        match display_manager.switch_display(&spec, false).await {
-            Ok(actual_mode) => {
-                println!(
-                    "Successfully switched to profile '{name}' with specification: {actual_mode} (requested: {spec})"
-                );
-                return Ok(());
-            }
-            Err(e) => {
-                eprintln!("Failed to switch to {spec}: {e}");
-                continue;
-            }
-        }
+        // 60.3 Hz is outside the default tolerance, so an exact switch fails...
+        assert!(run_with(&config_file, &["1920x1080@60.3hz"]).await.is_err());
+        assert!(!state_file.exists());
+
+        // ...unless the tolerance is widened on the command line
+        run_with(
+            &config_file,
+            &["--refresh-tolerance", "0.5", "1920x1080@60.3hz"],
+        )
+        .await?;
+        assert_eq!(
+            current_modes(&state_file)?[0],
+            ("MOCK-1".to_string(), "1920x1080@60hz".to_string())
+        );
+
+        // --no-exact overrides the exact setting
+        assert!(run_with(&config_file, &["2560x1400"]).await.is_err());
+        run_with(&config_file, &["--no-exact", "2560x1400"]).await?;
+        assert_eq!(current_modes(&state_file)?[0].1, "2560x1440@144hz");
+
+        // The output setting redirects switches to another output
+        run_with(&config_file, &["config", "set", "output", "MOCK-2"]).await?;
+        run_with(&config_file, &["1280x720@60hz"]).await?;
+        assert_eq!(
+            current_modes(&state_file)?,
+            [
+                ("MOCK-1".to_string(), "2560x1440@144hz".to_string()),
+                ("MOCK-2".to_string(), "1280x720@60hz".to_string()),
+            ]
+        );
+
+        assert!(
+            run_with(&config_file, &["config", "set", "policy", "fastest"])
+                .await
+                .is_err()
+        );
+        let written = std::fs::read_to_string(&config_file)?;
+        assert!(written.contains("backend = \"mock\""));
+        assert!(written.contains("output = \"MOCK-2\""));
+        Ok(())
     }
 
-    anyhow::bail!("No suitable display specification in profile '{name}' could be applied");
-}
+    #[tokio::test]
+    async fn test_per_output_profile_end_to_end() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_file = dir.path().join("profiles.json");
+        let state_file = dir.path().join("mock-state.json");
+        let mock = ["--backend", "mock"];
+        let _guard = MOCK_STATE_LOCK.lock().await;
+        std::env::set_var(STATE_ENV, &state_file);
+
+        run_with(
+            &config_file,
+            &[
+                "profile",
+                "create",
+                "desk",
+                "--output-spec",
+                "MOCK-1=1440p@144hz",
+                "--output-spec",
+                "MOCK-2=720p",
+                "--optional-output",
+                "TV-1=4k",
+            ],
+        )
+        .await?;
+        run_with(&config_file, &[&mock[..], &["--profile", "desk"]].concat()).await?;
+        assert_eq!(
+            current_modes(&state_file)?,
+            [
+                ("MOCK-1".to_string(), "2560x1440@144hz".to_string()),
+                ("MOCK-2".to_string(), "1280x720@60hz".to_string()),
+            ]
+        );
 
-fn handle_list_profiles(profile_manager: &ProfileManager) -> Result<()> {
-    let profiles = profile_manager.list_profiles()?;
+        // A required output that is not connected fails before anything changes
+        run_with(
+            &config_file,
+            &["profile", "create", "tv", "--output-spec", "TV-1=4k"],
+        )
+        .await?;
+        assert!(
+            run_with(&config_file, &[&mock[..], &["--profile", "tv"]].concat())
+                .await
+                .is_err()
+        );
 
-    if profiles.is_empty() {
-        println!("No profiles found.");
-        return Ok(());
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&config_file)?)?;
+        assert_eq!(
+            written["profiles"]["desk"]["outputs"][2],
+            serde_json::json!({"output": "TV-1", "specs": ["4k"], "optional": true})
+        );
+        Ok(())
     }
 
-    for (name, specs) in profiles {
-        println!("Profile: {name}");
-        for spec in specs {
-            println!("  - {spec}");
-        }
-        println!();
+    #[tokio::test]
+    async fn test_capture_restores_captured_modes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_file = dir.path().join("profiles.json");
+        let state_file = dir.path().join("mock-state.json");
+        let mock = ["--backend", "mock"];
+        let _guard = MOCK_STATE_LOCK.lock().await;
+        std::env::set_var(STATE_ENV, &state_file);
+
+        run_with(
+            &config_file,
+            &[&mock[..], &["-o", "MOCK-2", "720p"]].concat(),
+        )
+        .await?;
+        run_with(
+            &config_file,
+            &[
+                &mock[..],
+                &["profile", "capture", "home", "--with-fallbacks"],
+            ]
+            .concat(),
+        )
+        .await?;
+        let captured = current_modes(&state_file)?;
+
+        run_with(
+            &config_file,
+            &[&mock[..], &["-o", "MOCK-1", "1080p"]].concat(),
+        )
+        .await?;
+        run_with(
+            &config_file,
+            &[&mock[..], &["-o", "MOCK-2", "1080p"]].concat(),
+        )
+        .await?;
+        run_with(&config_file, &[&mock[..], &["--profile", "home"]].concat()).await?;
+        assert_eq!(current_modes(&state_file)?, captured);
+
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&config_file)?)?;
+        assert_eq!(
+            written["profiles"]["home"]["outputs"][0],
+            serde_json::json!({
+                "output": "MOCK-1",
+                "monitor": "MCK:0001:M0001",
+                "specs": ["3840x2160@60hz", "3840x2160", "16:9"]
+            })
+        );
+        Ok(())
     }
 
-    Ok(())
-}
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_hooks_run_around_switch() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_file = dir.path().join("profiles.json");
+        let state_file = dir.path().join("mock-state.json");
+        let log = dir.path().join("hooks.log");
+        let mock = ["--backend", "mock"];
+        let _guard = MOCK_STATE_LOCK.lock().await;
+        std::env::set_var(STATE_ENV, &state_file);
 
-async fn handle_current(display_manager: &DisplayManager, json: bool) -> Result<()> {
-    let current_mode = display_manager.get_current_display_mode().await?;
+        let record = |fields: &str| format!("echo \"{fields}\" >> '{}'", log.display());
+        let post = record("$DISPLAY_SWITCH_EVENT $DISPLAY_SWITCH_PROFILE $DISPLAY_SWITCH_OUTPUT $DISPLAY_SWITCH_OLD_MODE $DISPLAY_SWITCH_NEW_MODE");
+        run_with(&config_file, &["config", "set", "post_switch", &post]).await?;
+        run_with(
+            &config_file,
+            &["profile", "create", "tv", "--spec", "1080p@60hz"],
+        )
+        .await?;
+        run_with(&config_file, &[&mock[..], &["--profile", "tv"]].concat()).await?;
+        assert_eq!(
+            std::fs::read_to_string(&log)?,
+            "post_switch tv MOCK-1 3840x2160@60hz 1920x1080@60hz\n"
+        );
 
-    if json {
-        println!("{}", serde_json::to_string_pretty(&current_mode)?);
-    } else {
-        println!("Current display specification: {current_mode}");
+        // A failing pre_switch hook leaves the mode alone and triggers on_failure
+        run_with(&config_file, &["config", "set", "pre_switch", "exit 1"]).await?;
+        let failure = record("$DISPLAY_SWITCH_EVENT");
+        run_with(&config_file, &["config", "set", "on_failure", &failure]).await?;
+        assert!(run_with(&config_file, &[&mock[..], &["720p"]].concat())
+            .await
+            .is_err());
+        assert_eq!(
+            current_modes(&state_file)?[0],
+            ("MOCK-1".to_string(), "1920x1080@60hz".to_string())
+        );
+        assert!(std::fs::read_to_string(&log)?.ends_with("on_failure\n"));
+        Ok(())
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn test_switches_failing_early_are_audited() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_file = dir.path().join("profiles.json");
+        let state_file = dir.path().join("mock-state.json");
+        let mock = ["--backend", "mock"];
+        let _guard = MOCK_STATE_LOCK.lock().await;
+        std::env::set_var(STATE_ENV, &state_file);
+
+        run_with(
+            &config_file,
+            &["profile", "create", "tv", "--output-spec", "TV-1=4k"],
+        )
+        .await?;
+        for args in [
+            &["--profile", "missing"][..],
+            &["--profile", "tv"],
+            &["--spec", "1080p@fast"],
+            &["no-such-thing"],
+        ] {
+            assert!(run_with(&config_file, &[&mock[..], args].concat())
+                .await
+                .is_err());
+        }
+
+        let records: Vec<audit::AuditRecord> =
+            std::fs::read_to_string(dir.path().join("audit.jsonl"))?
+                .lines()
+                .map(serde_json::from_str)
+                .collect::<Result<_, _>>()?;
+        let summary: Vec<_> = records
+            .iter()
+            .map(|record| {
+                assert!(!record.success && record.applied.is_empty());
+                (
+                    record.action.to_string(),
+                    record.profile.clone(),
+                    record.requested.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("profile".to_string(), Some("missing".to_string()), vec![]),
+                ("profile".to_string(), Some("tv".to_string()), vec![]),
+                ("switch".to_string(), None, vec!["1080p@fast".to_string()]),
+                (
+                    "switch".to_string(),
+                    None,
+                    vec!["no-such-thing".to_string()]
+                ),
+            ]
+        );
+        assert!(records[1]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("not connected"));
+        Ok(())
+    }
 }
@@ -1,13 +1,106 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use regex::Regex;
+use std::collections::HashMap;
+use std::str::FromStr;
 
 use crate::display::DisplaySpec;
+use crate::error::Error;
+
+/// Values for `${NAME}` and `${NAME:-default}` placeholders in specifications.
+/// `--var` values take precedence over the environment.
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    values: HashMap<String, String>,
+}
+
+impl Variables {
+    pub fn new(values: HashMap<String, String>) -> Self {
+        Self { values }
+    }
+
+    fn get(&self, name: &str) -> Option<String> {
+        self.values
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+    }
+}
+
+/// Whether `spec` contains `${...}` placeholders
+pub fn has_variables(spec: &str) -> bool {
+    spec.contains("${")
+}
+
+/// Replaces the placeholders in `spec`. Like the shell, `${NAME:-default}`
+/// also uses the default when the variable is set but empty. Fails naming
+/// every variable that has neither a value nor a default.
+pub fn expand_variables(spec: &str, variables: &Variables) -> Result<String> {
+    let (expanded, missing) = substitute(spec, |name| variables.get(name))?;
+    if !missing.is_empty() {
+        return Err(Error::Parse(format!(
+            "Missing variables in '{}': {} (set them with --var NAME=VALUE or in the environment)",
+            spec,
+            missing.join(", ")
+        ))
+        .into());
+    }
+    Ok(expanded)
+}
+
+/// Checks placeholder syntax without looking any variables up
+pub fn check_variables(spec: &str) -> Result<()> {
+    substitute(spec, |_| None).map(|_| ())
+}
+
+fn substitute(
+    spec: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<(String, Vec<String>)> {
+    let mut expanded = String::new();
+    let mut missing = Vec::new();
+    let mut rest = spec;
+
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| Error::Parse(format!("Unterminated placeholder in '{}'", spec)))?;
+
+        let (name, default) = match after[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&after[..end], None),
+        };
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(
+                Error::Parse(format!("Invalid variable name '{}' in '{}'", name, spec)).into(),
+            );
+        }
+
+        let value = lookup(name).filter(|value| default.is_none() || !value.is_empty());
+        match value.or(default.map(str::to_string)) {
+            Some(value) => expanded.push_str(&value),
+            None => missing.push(name.to_string()),
+        }
+        rest = &after[end + 1..];
+    }
+    expanded.push_str(rest);
+
+    Ok((expanded, missing))
+}
 
 pub fn parse_display_spec(spec: &str) -> Result<DisplaySpec> {
     let spec = spec.trim().to_lowercase();
 
     // Split by @ to separate resolution/aspect from refresh rate
     let parts: Vec<&str> = spec.split('@').collect();
+    if parts.len() > 2 {
+        return Err(
+            Error::Parse(format!("Unable to parse display specification: {}", spec)).into(),
+        );
+    }
     let resolution_part = parts[0];
     let refresh_rate = if parts.len() > 1 {
         Some(parse_refresh_rate(parts[1])?)
@@ -15,11 +108,32 @@ pub fn parse_display_spec(spec: &str) -> Result<DisplaySpec> {
         None
     };
 
+    // An empty or `*` resolution part leaves resolution and aspect ratio unconstrained
+    if resolution_part.is_empty() || resolution_part == "*" {
+        return Ok(DisplaySpec {
+            width: None,
+            height: None,
+            refresh_rate,
+            aspect_ratio: None,
+        });
+    }
+
+    // Pattern: {resolution}/{aspect} (canonical form when both are constrained)
+    if let Some((resolution, aspect)) = resolution_part.split_once('/') {
+        let (width, height) = parse_partial_resolution(resolution)?;
+        return Ok(DisplaySpec {
+            width,
+            height,
+            refresh_rate,
+            aspect_ratio: Some(parse_aspect_ratio(aspect)?),
+        });
+    }
+
     // Try to parse as resolution first, then as aspect ratio
-    if let Ok((width, height)) = parse_resolution(resolution_part) {
+    if let Ok((width, height)) = parse_partial_resolution(resolution_part) {
         return Ok(DisplaySpec {
-            width: Some(width),
-            height: Some(height),
+            width,
+            height,
             refresh_rate,
             aspect_ratio: None,
         });
@@ -34,22 +148,62 @@ pub fn parse_display_spec(spec: &str) -> Result<DisplaySpec> {
         });
     }
 
-    Err(anyhow!("Unable to parse display specification: {}", spec))
+    Err(Error::Parse(format!("Unable to parse display specification: {}", spec)).into())
+}
+
+fn parse_partial_resolution(resolution: &str) -> Result<(Option<u32>, Option<u32>)> {
+    // Pattern: {width}x* or *x{height} (only one dimension constrained)
+    let partial_regex = Regex::new(r"^(\d+|\*)x(\d+|\*)$").unwrap();
+    if let Some(captures) = partial_regex.captures(resolution) {
+        let width = match &captures[1] {
+            "*" => None,
+            w => Some(parse_number(w)?),
+        };
+        let height = match &captures[2] {
+            "*" => None,
+            h => Some(parse_number(h)?),
+        };
+        return Ok((width, height));
+    }
+
+    let (width, height) = parse_resolution(resolution)?;
+    Ok((Some(width), Some(height)))
+}
+
+/// Splits an inline fallback list such as `"4k@60hz,1440p@120hz|1080p"` into
+/// its individual specifications, in order of preference.
+pub fn split_spec_list(input: &str) -> Vec<String> {
+    input
+        .split([',', '|'])
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parses the contents of a spec file: one specification (or inline list) per
+/// line, with blank lines and `#` comments ignored.
+pub fn parse_spec_file(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(split_spec_list)
+        .collect()
 }
 
 fn parse_resolution(resolution: &str) -> Result<(u32, u32)> {
     // Pattern: {width}x{height} (e.g., "1920x1080", "2560x1440")
     let width_height_regex = Regex::new(r"^(\d+)x(\d+)$").unwrap();
     if let Some(captures) = width_height_regex.captures(resolution) {
-        let width = captures[1].parse::<u32>()?;
-        let height = captures[2].parse::<u32>()?;
+        let width = parse_number(&captures[1])?;
+        let height = parse_number(&captures[2])?;
         return Ok((width, height));
     }
 
     // Pattern: {height}p (e.g., "1080p", "720p", "4320p")
     let height_p_regex = Regex::new(r"^(\d+)p$").unwrap();
     if let Some(captures) = height_p_regex.captures(resolution) {
-        let height = captures[1].parse::<u32>()?;
+        let height = parse_number(&captures[1])?;
         let width = calculate_width_from_height(height);
         return Ok((width, height));
     }
@@ -57,52 +211,58 @@ fn parse_resolution(resolution: &str) -> Result<(u32, u32)> {
     // Pattern: {int}k (e.g., "4k", "2k", "8k")
     let k_regex = Regex::new(r"^(\d+)k$").unwrap();
     if let Some(captures) = k_regex.captures(resolution) {
-        let k = captures[1].parse::<u32>()?;
+        let k: u32 = parse_number(&captures[1])?;
         return match k {
             2 => Ok((2048, 1080)), // 2K DCI
             4 => Ok((3840, 2160)), // 4K UHD
             8 => Ok((7680, 4320)), // 8K UHD
-            _ => Err(anyhow!("Unsupported K resolution: {}k", k)),
+            _ => Err(Error::Parse(format!("Unsupported K resolution: {}k", k)).into()),
         };
     }
 
     // Pattern: {height} (interlaced, e.g., "1080i", "720i")
     let height_i_regex = Regex::new(r"^(\d+)i?$").unwrap();
     if let Some(captures) = height_i_regex.captures(resolution) {
-        let height = captures[1].parse::<u32>()?;
+        let height = parse_number(&captures[1])?;
         let width = calculate_width_from_height(height);
         return Ok((width, height));
     }
 
-    Err(anyhow!("Unable to parse resolution: {}", resolution))
+    Err(Error::Parse(format!("Unable to parse resolution: {}", resolution)).into())
 }
 
 fn parse_aspect_ratio(aspect: &str) -> Result<(u32, u32)> {
     // Pattern: {width}:{height} (e.g., "16:9", "4:3", "21:9")
     let aspect_regex = Regex::new(r"^(\d+):(\d+)$").unwrap();
     if let Some(captures) = aspect_regex.captures(aspect) {
-        let width_ratio = captures[1].parse::<u32>()?;
-        let height_ratio = captures[2].parse::<u32>()?;
+        let width_ratio = parse_number(&captures[1])?;
+        let height_ratio = parse_number(&captures[2])?;
         return Ok((width_ratio, height_ratio));
     }
 
-    Err(anyhow!("Unable to parse aspect ratio: {}", aspect))
+    Err(Error::Parse(format!("Unable to parse aspect ratio: {}", aspect)).into())
 }
 
 fn parse_refresh_rate(rate: &str) -> Result<f64> {
     // Pattern: {decimal}hz (e.g., "60hz", "144hz", "240hz")
     let hz_regex = Regex::new(r"^([0-9]*\.?[0-9]+)hz$").unwrap();
     if let Some(captures) = hz_regex.captures(rate) {
-        return Ok(captures[1].parse::<f64>()?);
+        return parse_number(&captures[1]);
     }
 
     // Pattern: {decimal}fps (e.g., "60fps", "59.94fps", "120fps")
     let fps_regex = Regex::new(r"^([0-9]*\.?[0-9]+)fps$").unwrap();
     if let Some(captures) = fps_regex.captures(rate) {
-        return Ok(captures[1].parse::<f64>()?);
+        return parse_number(&captures[1]);
     }
 
-    Err(anyhow!("Unable to parse refresh rate: {}", rate))
+    Err(Error::Parse(format!("Unable to parse refresh rate: {}", rate)).into())
+}
+
+fn parse_number<T: FromStr>(digits: &str) -> Result<T> {
+    digits
+        .parse()
+        .map_err(|_| Error::Parse(format!("Number out of range: {}", digits)).into())
 }
 
 fn calculate_width_from_height(height: u32) -> u32 {
@@ -148,6 +308,50 @@ mod tests {
         assert_eq!(parse_refresh_rate("120fps").unwrap(), 120.0);
     }
 
+    #[test]
+    fn test_parse_canonical_forms() {
+        let spec = parse_display_spec("1920x1080/16:9@60hz").unwrap();
+        assert_eq!(spec.width, Some(1920));
+        assert_eq!(spec.height, Some(1080));
+        assert_eq!(spec.aspect_ratio, Some((16, 9)));
+        assert_eq!(spec.refresh_rate, Some(60.0));
+
+        let spec = parse_display_spec("*@144hz").unwrap();
+        assert_eq!(spec.width, None);
+        assert_eq!(spec.aspect_ratio, None);
+        assert_eq!(spec.refresh_rate, Some(144.0));
+
+        let spec = parse_display_spec("2560x*").unwrap();
+        assert_eq!(spec.width, Some(2560));
+        assert_eq!(spec.height, None);
+
+        assert!(parse_display_spec("1080p@60hz@120hz").is_err());
+        assert!(parse_display_spec("*@").is_err());
+    }
+
+    #[test]
+    fn test_split_spec_list() {
+        assert_eq!(
+            split_spec_list("4k@60hz,1440p@120hz,1080p"),
+            vec!["4k@60hz", "1440p@120hz", "1080p"]
+        );
+        assert_eq!(
+            split_spec_list(" 4k@60hz | 1080p ,, "),
+            vec!["4k@60hz", "1080p"]
+        );
+        assert_eq!(split_spec_list("16:9@60fps"), vec!["16:9@60fps"]);
+        assert!(split_spec_list("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_spec_file() {
+        let content = "# Living room TV\n4k@60hz\n\n1440p@120hz # fallback\n1080p,720p\n";
+        assert_eq!(
+            parse_spec_file(content),
+            vec!["4k@60hz", "1440p@120hz", "1080p", "720p"]
+        );
+    }
+
     #[test]
     fn test_parse_display_spec() {
         let spec = parse_display_spec("1920x1080@60hz").unwrap();
@@ -163,5 +367,37 @@ mod tests {
         assert_eq!(spec.width, Some(3840));
         assert_eq!(spec.height, Some(2160));
         assert_eq!(spec.refresh_rate, None);
+        for invalid in ["1080q", "1080p@60", "99999999999p"] {
+            let error = parse_display_spec(invalid).unwrap_err();
+            assert!(matches!(Error::find(&error), Some(Error::Parse(_))));
+        }
+    }
+
+    #[test]
+    fn test_expand_variables() -> Result<()> {
+        let variables = Variables::new(HashMap::from([
+            ("DS_TEST_W".to_string(), "2560".to_string()),
+            ("DS_TEST_H".to_string(), "1440".to_string()),
+            ("DS_TEST_EMPTY".to_string(), String::new()),
+        ]));
+
+        let spec = "${DS_TEST_W}x${DS_TEST_H}@${DS_TEST_FPS:-60}hz";
+        assert_eq!(expand_variables(spec, &variables)?, "2560x1440@60hz");
+        assert_eq!(
+            expand_variables("${DS_TEST_EMPTY:-1080p}", &variables)?,
+            "1080p"
+        );
+        assert_eq!(expand_variables("4k@60hz", &variables)?, "4k@60hz");
+
+        // Every missing variable is named
+        let error = expand_variables("${DS_TEST_A}x${DS_TEST_B}", &variables)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("DS_TEST_A, DS_TEST_B"), "{error}");
+
+        assert!(check_variables("${W}x${H:-1080}").is_ok());
+        assert!(check_variables("${W").is_err());
+        assert!(check_variables("${1W}").is_err());
+        Ok(())
     }
 }
@@ -1,78 +1,85 @@
+//! X11 backend on the RandR 1.2 per-output API. Unlike the screen-wide 1.1
+//! calls (`XRRSetScreenConfigAndRate`), it can switch one named output, which
+//! `--output` and Sunshine's `output_name` need; the screen is resized around
+//! the new mode so other outputs keep their place.
+
 use anyhow::{anyhow, Result};
-use std::ptr;
-use x11::xlib::{Display, XCloseDisplay, XDefaultScreen, XOpenDisplay, XRootWindow};
+use std::fs;
+use std::os::raw::{c_int, c_uchar, c_ulong};
+use std::slice;
+use x11::xlib::{
+    AnyPropertyType, Atom, CurrentTime, Display, Success, Window, XCloseDisplay, XDefaultScreen,
+    XDisplayHeight, XDisplayHeightMM, XDisplayWidth, XDisplayWidthMM, XFree, XInternAtom,
+    XOpenDisplay, XRootWindow, XSync,
+};
 use x11::xrandr::{
-    XRRConfigCurrentConfiguration, XRRConfigRates, XRRConfigSizes, XRRFreeScreenConfigInfo,
-    XRRGetScreenInfo, XRRSetScreenConfigAndRate,
+    RRCrtc, RRMode, RROutput, RRSetConfigSuccess, RR_Connected, RR_Rotate_0, RR_Rotate_270,
+    RR_Rotate_90, Rotation, XRRFreeCrtcInfo, XRRFreeOutputInfo, XRRFreeScreenResources,
+    XRRGetCrtcInfo, XRRGetOutputInfo, XRRGetOutputPrimary, XRRGetOutputProperty,
+    XRRGetScreenResourcesCurrent, XRRModeInfo, XRRScreenResources, XRRSetCrtcConfig,
+    XRRSetScreenSize,
 };
 
-use crate::display::DisplayMode;
+use crate::display::{DisplayMode, OutputInfo};
+use crate::edid::MonitorIdentity;
+use crate::error::Error;
 
 pub struct LinuxDisplayManager {
     display: *mut Display,
 }
 
+// A connected RandR output and the modes it supports
+struct RandrOutput {
+    id: RROutput,
+    name: String,
+    crtc: RRCrtc,
+    crtcs: Vec<RRCrtc>,
+    primary: bool,
+    modes: Vec<(RRMode, DisplayMode)>,
+    current: Option<DisplayMode>,
+    identity: Option<MonitorIdentity>,
+}
+
 impl LinuxDisplayManager {
     pub fn new() -> Result<Self> {
         unsafe {
-            let display = XOpenDisplay(ptr::null());
+            let display = XOpenDisplay(std::ptr::null());
             if display.is_null() {
-                return Err(anyhow!("Failed to open X11 display"));
+                return Err(
+                    Error::BackendUnavailable("Failed to open X11 display".to_string()).into(),
+                );
             }
 
             Ok(Self { display })
         }
     }
 
-    pub async fn get_available_modes(&self) -> Result<Vec<DisplayMode>> {
-        let mut modes = Vec::new();
-
-        unsafe {
-            let screen = XDefaultScreen(self.display);
-            let root = XRootWindow(self.display, screen);
-            let screen_info = XRRGetScreenInfo(self.display, root);
-
-            if screen_info.is_null() {
-                return Err(anyhow!("Failed to get screen info"));
-            }
-
-            let mut num_sizes = 0;
-            let sizes = XRRConfigSizes(screen_info, &mut num_sizes);
-
-            if sizes.is_null() || num_sizes == 0 {
-                XRRFreeScreenConfigInfo(screen_info);
-                return Err(anyhow!("No screen sizes available"));
-            }
+    pub async fn get_outputs(&self) -> Result<Vec<OutputInfo>> {
+        Ok(self
+            .query_outputs()?
+            .into_iter()
+            .map(|output| OutputInfo {
+                name: output.name,
+                primary: output.primary,
+                current_mode: output.current,
+                identity: output.identity,
+            })
+            .collect())
+    }
 
-            for i in 0..num_sizes {
-                let size = *sizes.offset(i as isize);
-
-                let mut num_rates = 0;
-                let rates = XRRConfigRates(screen_info, i, &mut num_rates);
-
-                if !rates.is_null() && num_rates > 0 {
-                    for j in 0..num_rates {
-                        let rate = *rates.offset(j as isize);
-
-                        let mode = DisplayMode {
-                            width: size.width as u32,
-                            height: size.height as u32,
-                            refresh_rate: rate as f64,
-                        };
-
-                        // Avoid duplicates
-                        if !modes.iter().any(|m: &DisplayMode| {
-                            m.width == mode.width
-                                && m.height == mode.height
-                                && (m.refresh_rate - mode.refresh_rate).abs() < 0.1
-                        }) {
-                            modes.push(mode);
-                        }
-                    }
-                }
+    pub async fn get_available_modes(&self, output: Option<&str>) -> Result<Vec<DisplayMode>> {
+        let output = self.find_output(output)?;
+        let mut modes: Vec<DisplayMode> = Vec::new();
+
+        for (_, mode) in output.modes {
+            // Avoid duplicates
+            if !modes.iter().any(|m| {
+                m.width == mode.width
+                    && m.height == mode.height
+                    && (m.refresh_rate - mode.refresh_rate).abs() < 0.1
+            }) {
+                modes.push(mode);
             }
-
-            XRRFreeScreenConfigInfo(screen_info);
         }
 
         if modes.is_empty() {
@@ -90,139 +97,396 @@ impl LinuxDisplayManager {
         Ok(modes)
     }
 
-    pub async fn set_display_mode(&self, mode: &DisplayMode) -> Result<()> {
-        unsafe {
-            let screen = XDefaultScreen(self.display);
-            let root = XRootWindow(self.display, screen);
-            let screen_info = XRRGetScreenInfo(self.display, root);
+    pub async fn set_display_mode(&self, output: Option<&str>, mode: &DisplayMode) -> Result<()> {
+        let output = self.find_output(output)?;
 
-            if screen_info.is_null() {
-                return Err(anyhow!("Failed to get screen info"));
+        let mode_id = output
+            .modes
+            .iter()
+            .filter(|(_, m)| {
+                m.width == mode.width
+                    && m.height == mode.height
+                    && (m.refresh_rate - mode.refresh_rate).abs() < 0.1
+            })
+            .min_by(|(_, a), (_, b)| {
+                let a_diff = (a.refresh_rate - mode.refresh_rate).abs();
+                let b_diff = (b.refresh_rate - mode.refresh_rate).abs();
+                a_diff.partial_cmp(&b_diff).unwrap()
+            })
+            .map(|(id, _)| *id)
+            .ok_or_else(|| {
+                Error::NoSuitableMode(format!(
+                    "Display mode {} not available on {}",
+                    mode, output.name
+                ))
+            })?;
+
+        unsafe {
+            let resources = XRRGetScreenResourcesCurrent(self.display, self.root());
+            if resources.is_null() {
+                return Err(anyhow!("Failed to get screen resources"));
             }
 
-            let mut num_sizes = 0;
-            let sizes = XRRConfigSizes(screen_info, &mut num_sizes);
+            let result = self.apply_crtc_mode(resources, &output, mode_id, mode);
+            XRRFreeScreenResources(resources);
+            XSync(self.display, 0);
+            result
+        }
+    }
+
+    pub async fn get_current_display_mode(&self, output: Option<&str>) -> Result<DisplayMode> {
+        let output = self.find_output(output)?;
+        output
+            .current
+            .ok_or_else(|| anyhow!("Output {} is not active", output.name))
+    }
 
-            if sizes.is_null() || num_sizes == 0 {
-                XRRFreeScreenConfigInfo(screen_info);
-                return Err(anyhow!("No screen sizes available"));
-            }
+    fn root(&self) -> Window {
+        unsafe { XRootWindow(self.display, XDefaultScreen(self.display)) }
+    }
 
-            // Find matching size index
-            let mut size_index = None;
-            for i in 0..num_sizes {
-                let size = *sizes.offset(i as isize);
-                if size.width as u32 == mode.width && size.height as u32 == mode.height {
-                    size_index = Some(i);
-                    break;
-                }
+    fn query_outputs(&self) -> Result<Vec<RandrOutput>> {
+        let mut outputs = Vec::new();
+
+        unsafe {
+            let root = self.root();
+            let resources = XRRGetScreenResourcesCurrent(self.display, root);
+            if resources.is_null() {
+                return Err(anyhow!("Failed to get screen resources"));
             }
 
-            let size_index = match size_index {
-                Some(idx) => idx,
-                None => {
-                    XRRFreeScreenConfigInfo(screen_info);
-                    return Err(anyhow!(
-                        "Resolution {}x{} not available",
-                        mode.width,
-                        mode.height
-                    ));
+            let primary = XRRGetOutputPrimary(self.display, root);
+            let mode_infos = raw_slice((*resources).modes, (*resources).nmode);
+
+            for &output_id in raw_slice((*resources).outputs, (*resources).noutput) {
+                let info = XRRGetOutputInfo(self.display, resources, output_id);
+                if info.is_null() {
+                    continue;
                 }
-            };
-
-            // Find matching refresh rate
-            let mut num_rates = 0;
-            let rates = XRRConfigRates(screen_info, size_index, &mut num_rates);
-            let mut rate_index = None;
-
-            if !rates.is_null() && num_rates > 0 {
-                for j in 0..num_rates {
-                    let rate = *rates.offset(j as isize);
-                    if (rate as f64 - mode.refresh_rate).abs() < 0.1 {
-                        rate_index = Some(rate);
-                        break;
+
+                if c_int::from((*info).connection) == RR_Connected {
+                    let name = raw_slice((*info).name as *const u8, (*info).nameLen);
+                    let modes = raw_slice((*info).modes, (*info).nmode)
+                        .iter()
+                        .filter_map(|id| mode_infos.iter().find(|m| m.id == *id))
+                        .map(|m| (m.id, mode_from_info(m)))
+                        .collect();
+
+                    let mut current = None;
+                    if (*info).crtc != 0 {
+                        let crtc = XRRGetCrtcInfo(self.display, resources, (*info).crtc);
+                        if !crtc.is_null() {
+                            current = mode_infos
+                                .iter()
+                                .find(|m| m.id == (*crtc).mode)
+                                .map(mode_from_info);
+                            XRRFreeCrtcInfo(crtc);
+                        }
                     }
+
+                    let name = String::from_utf8_lossy(name).into_owned();
+                    let identity = self
+                        .output_edid(output_id)
+                        .or_else(|| sysfs_edid(&name))
+                        .and_then(|edid| MonitorIdentity::from_edid(&edid).ok());
+
+                    outputs.push(RandrOutput {
+                        id: output_id,
+                        name,
+                        crtc: (*info).crtc,
+                        crtcs: raw_slice((*info).crtcs, (*info).ncrtc).to_vec(),
+                        primary: output_id == primary,
+                        modes,
+                        current,
+                        identity,
+                    });
                 }
+
+                XRRFreeOutputInfo(info);
             }
 
-            let rate = match rate_index {
-                Some(r) => r,
-                None => {
-                    XRRFreeScreenConfigInfo(screen_info);
-                    return Err(anyhow!(
-                        "Refresh rate {}Hz not available for {}x{}",
-                        mode.refresh_rate,
-                        mode.width,
-                        mode.height
-                    ));
-                }
-            };
+            XRRFreeScreenResources(resources);
+        }
 
-            // Apply the configuration
-            let result = XRRSetScreenConfigAndRate(
-                self.display,
-                screen_info,
-                root,
-                size_index,
-                0, // rotation
-                rate,
-                0, // timestamp
-            );
+        Ok(outputs)
+    }
 
-            XRRFreeScreenConfigInfo(screen_info);
+    // Reads the EDID RandR exposes as an output property
+    fn output_edid(&self, output: RROutput) -> Option<Vec<u8>> {
+        unsafe {
+            let atom = XInternAtom(self.display, c"EDID".as_ptr(), 1);
+            if atom == 0 {
+                return None;
+            }
 
-            if result != 0 {
-                return Err(anyhow!(
-                    "Failed to set display mode. XRandR error: {}",
-                    result
-                ));
+            let mut actual_type: Atom = 0;
+            let mut actual_format: c_int = 0;
+            let mut nitems: c_ulong = 0;
+            let mut bytes_after: c_ulong = 0;
+            let mut data: *mut c_uchar = std::ptr::null_mut();
+            let status = XRRGetOutputProperty(
+                self.display,
+                output,
+                atom,
+                0,
+                // Length is in 32-bit units; enough for the base block and extensions
+                256,
+                0,
+                0,
+                AnyPropertyType as Atom,
+                &mut actual_type,
+                &mut actual_format,
+                &mut nitems,
+                &mut bytes_after,
+                &mut data,
+            );
+            if status != Success as c_int || data.is_null() {
+                return None;
             }
+
+            let edid = (actual_format == 8 && nitems > 0)
+                .then(|| slice::from_raw_parts(data, nitems as usize).to_vec());
+            XFree(data.cast());
+            edid
         }
+    }
 
-        Ok(())
+    fn find_output(&self, name: Option<&str>) -> Result<RandrOutput> {
+        let mut outputs = self.query_outputs()?;
+
+        let index = match name {
+            Some(name) => outputs.iter().position(|o| o.name == name).ok_or_else(|| {
+                Error::OutputNotFound(format!("Output '{}' not found or not connected", name))
+            })?,
+            // Default to the primary output, then the first active one
+            None => outputs
+                .iter()
+                .position(|o| o.primary && o.current.is_some())
+                .or_else(|| outputs.iter().position(|o| o.current.is_some()))
+                .or(if outputs.is_empty() { None } else { Some(0) })
+                .ok_or_else(|| Error::OutputNotFound("No connected outputs found".to_string()))?,
+        };
+
+        Ok(outputs.swap_remove(index))
     }
 
-    pub async fn get_current_display_mode(&self) -> Result<DisplayMode> {
-        unsafe {
-            let screen = XDefaultScreen(self.display);
-            let root = XRootWindow(self.display, screen);
-            let screen_info = XRRGetScreenInfo(self.display, root);
+    unsafe fn apply_crtc_mode(
+        &self,
+        resources: *mut XRRScreenResources,
+        output: &RandrOutput,
+        mode_id: RRMode,
+        mode: &DisplayMode,
+    ) -> Result<()> {
+        // Reuse the output's CRTC, or find a free one that can drive it
+        let crtc = if output.crtc != 0 {
+            output.crtc
+        } else {
+            output
+                .crtcs
+                .iter()
+                .copied()
+                .find(|&crtc| {
+                    let info = XRRGetCrtcInfo(self.display, resources, crtc);
+                    let free = !info.is_null() && (*info).noutput == 0;
+                    if !info.is_null() {
+                        XRRFreeCrtcInfo(info);
+                    }
+                    free
+                })
+                .ok_or_else(|| {
+                    Error::ApplyFailed(format!("No free CRTC available for output {}", output.name))
+                })?
+        };
+
+        let (mut x, mut y) = (0, 0);
+        let mut rotation = RR_Rotate_0 as Rotation;
+        let mut crtc_outputs = vec![output.id];
+        let mut areas = Vec::new();
+
+        // Work out the screen size needed once the new mode is in place
+        for &other in raw_slice((*resources).crtcs, (*resources).ncrtc) {
+            let info = XRRGetCrtcInfo(self.display, resources, other);
+            if info.is_null() {
+                continue;
+            }
 
-            if screen_info.is_null() {
-                return Err(anyhow!("Failed to get screen info"));
+            if other == crtc {
+                x = (*info).x;
+                y = (*info).y;
+                if (*info).mode != 0 {
+                    rotation = (*info).rotation;
+                    crtc_outputs = raw_slice((*info).outputs, (*info).noutput).to_vec();
+                }
+                let (width, height) = rotated_size(mode, rotation);
+                areas.push(CrtcArea {
+                    x,
+                    y,
+                    width,
+                    height,
+                });
+            } else if (*info).mode != 0 {
+                areas.push(CrtcArea {
+                    x: (*info).x,
+                    y: (*info).y,
+                    width: (*info).width,
+                    height: (*info).height,
+                });
             }
 
-            let current_config = XRRConfigCurrentConfiguration(screen_info, std::ptr::null_mut());
+            XRRFreeCrtcInfo(info);
+        }
+
+        let screen = XDefaultScreen(self.display);
+        let current = (
+            XDisplayWidth(self.display, screen) as u32,
+            XDisplayHeight(self.display, screen) as u32,
+        );
+        let needed = screen_size(&areas);
+        let (grow, shrink) = screen_resizes(current, needed);
 
-            let mut num_sizes = 0;
-            let sizes = XRRConfigSizes(screen_info, &mut num_sizes);
+        if let Some((width, height)) = grow {
+            self.set_screen_size(width, height);
+        }
 
-            if sizes.is_null() || i32::from(current_config) >= num_sizes {
-                XRRFreeScreenConfigInfo(screen_info);
-                return Err(anyhow!("Invalid current configuration"));
-            }
+        let status = XRRSetCrtcConfig(
+            self.display,
+            resources,
+            crtc,
+            CurrentTime,
+            x,
+            y,
+            mode_id,
+            rotation,
+            crtc_outputs.as_mut_ptr(),
+            crtc_outputs.len() as c_int,
+        );
+
+        if status != RRSetConfigSuccess {
+            return Err(Error::ApplyFailed(format!(
+                "Failed to set display mode. XRandR error: {}",
+                status
+            ))
+            .into());
+        }
 
-            let current_size = *sizes.offset(current_config as isize);
+        if let Some((width, height)) = shrink {
+            self.set_screen_size(width, height);
+        }
+
+        Ok(())
+    }
 
-            let mut num_rates = 0;
-            let rates = XRRConfigRates(screen_info, current_config.into(), &mut num_rates);
-            let current_rate = if !rates.is_null() && num_rates > 0 {
-                *rates
-            } else {
-                60 // Default fallback
-            };
+    unsafe fn set_screen_size(&self, width: u32, height: u32) {
+        let screen = XDefaultScreen(self.display);
+        let width_px = XDisplayWidth(self.display, screen).max(1);
+        let height_px = XDisplayHeight(self.display, screen).max(1);
+
+        // Keep the physical DPI constant when resizing
+        let width_mm = width as c_int * XDisplayWidthMM(self.display, screen) / width_px;
+        let height_mm = height as c_int * XDisplayHeightMM(self.display, screen) / height_px;
+
+        XRRSetScreenSize(
+            self.display,
+            self.root(),
+            width as c_int,
+            height as c_int,
+            width_mm,
+            height_mm,
+        );
+    }
+}
 
-            XRRFreeScreenConfigInfo(screen_info);
+unsafe fn raw_slice<'a, T>(ptr: *const T, len: c_int) -> &'a [T] {
+    if ptr.is_null() || len <= 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, len as usize)
+    }
+}
 
-            Ok(DisplayMode {
-                width: current_size.width as u32,
-                height: current_size.height as u32,
-                refresh_rate: current_rate as f64,
+// Falls back to the kernel's copy of the EDID for drivers that do not expose
+// the RandR property. DRM connectors are named like `card0-HDMI-A-1` where
+// RandR may say `HDMI-1` or `HDMI-A-1`.
+fn sysfs_edid(output: &str) -> Option<Vec<u8>> {
+    let normalize = |name: &str| name.replace("HDMI-A-", "HDMI-").to_ascii_uppercase();
+    let wanted = normalize(output);
+
+    fs::read_dir("/sys/class/drm")
+        .ok()?
+        .flatten()
+        .find(|entry| {
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            file_name.split_once('-').is_some_and(|(card, connector)| {
+                card.starts_with("card") && normalize(connector) == wanted
             })
-        }
+        })
+        .and_then(|entry| fs::read(entry.path().join("edid")).ok())
+        .filter(|edid| !edid.is_empty())
+}
+
+fn mode_from_info(info: &XRRModeInfo) -> DisplayMode {
+    DisplayMode {
+        width: info.width,
+        height: info.height,
+        refresh_rate: vertical_refresh(info.dotClock, info.hTotal, info.vTotal),
     }
 }
 
+// Whole Hz, rounded the way the X server reports rates to RandR 1.1 clients
+// (`RRVerticalRefresh`), so profiles written against the screen-wide API keep
+// matching the same modes
+fn vertical_refresh(dot_clock: u64, h_total: u32, v_total: u32) -> f64 {
+    let dots = u64::from(h_total) * u64::from(v_total);
+    if dots == 0 {
+        return 0.0;
+    }
+    ((dot_clock + dots / 2) / dots) as f64
+}
+
+// Where a CRTC sits on the X screen, in screen pixels
+struct CrtcArea {
+    x: c_int,
+    y: c_int,
+    width: u32,
+    height: u32,
+}
+
+// Size `mode` takes up on the screen; rotating by 90 or 270 degrees swaps its sides
+fn rotated_size(mode: &DisplayMode, rotation: Rotation) -> (u32, u32) {
+    if rotation & (RR_Rotate_90 | RR_Rotate_270) as Rotation != 0 {
+        (mode.height, mode.width)
+    } else {
+        (mode.width, mode.height)
+    }
+}
+
+// The smallest screen that contains every area
+fn screen_size(areas: &[CrtcArea]) -> (u32, u32) {
+    areas.iter().fold((0, 0), |(width, height), area| {
+        (
+            width.max(area.x.max(0) as u32 + area.width),
+            height.max(area.y.max(0) as u32 + area.height),
+        )
+    })
+}
+
+// Width and height of the X screen, in pixels
+type ScreenSize = (u32, u32);
+
+// Screen sizes to set before and after the CRTC change: the screen must be
+// large enough for the new mode while it is applied, and is trimmed to the
+// layout afterwards
+fn screen_resizes(
+    current: ScreenSize,
+    needed: ScreenSize,
+) -> (Option<ScreenSize>, Option<ScreenSize>) {
+    let grow = (needed.0 > current.0 || needed.1 > current.1)
+        .then(|| (needed.0.max(current.0), needed.1.max(current.1)));
+    let shrink = (needed != current).then_some(needed);
+    (grow, shrink)
+}
+
 impl Drop for LinuxDisplayManager {
     fn drop(&mut self) {
         unsafe {
@@ -232,3 +496,62 @@ impl Drop for LinuxDisplayManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vertical_refresh() {
+        // CEA 1080p60 (148.5 MHz) and its 59.94 Hz NTSC variant
+        assert_eq!(vertical_refresh(148_500_000, 2200, 1125), 60.0);
+        assert_eq!(vertical_refresh(148_351_648, 2200, 1125), 60.0);
+        // 2560x1440 at 143.97 Hz (CVT reduced blanking)
+        assert_eq!(vertical_refresh(586_584_000, 2720, 1498), 144.0);
+        assert_eq!(vertical_refresh(148_500_000, 0, 1125), 0.0);
+    }
+
+    #[test]
+    fn test_screen_size() {
+        let mode = DisplayMode {
+            width: 1920,
+            height: 1080,
+            refresh_rate: 60.0,
+        };
+        assert_eq!(rotated_size(&mode, RR_Rotate_0 as Rotation), (1920, 1080));
+        assert_eq!(rotated_size(&mode, RR_Rotate_90 as Rotation), (1080, 1920));
+
+        // A 4K output left of a portrait 1080p one
+        let areas = [
+            CrtcArea {
+                x: 0,
+                y: 0,
+                width: 3840,
+                height: 2160,
+            },
+            CrtcArea {
+                x: 3840,
+                y: 0,
+                width: 1080,
+                height: 1920,
+            },
+        ];
+        assert_eq!(screen_size(&areas), (4920, 2160));
+        assert_eq!(screen_size(&[]), (0, 0));
+    }
+
+    #[test]
+    fn test_screen_resizes() {
+        // Growing in one dimension and shrinking in the other
+        assert_eq!(
+            screen_resizes((3840, 2160), (4920, 1440)),
+            (Some((4920, 2160)), Some((4920, 1440)))
+        );
+        // Only shrinking happens after the change
+        assert_eq!(
+            screen_resizes((3840, 2160), (1920, 1080)),
+            (None, Some((1920, 1080)))
+        );
+        assert_eq!(screen_resizes((1920, 1080), (1920, 1080)), (None, None));
+    }
+}
@@ -5,12 +5,14 @@ use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
 use core_foundation::number::{CFNumber, CFNumberRef};
 use core_foundation::string::{CFString, CFStringRef};
 use core_graphics::display::{
-    CGDirectDisplayID, CGDisplayCopyAllDisplayModes, CGDisplayModeGetHeight,
+    CGDirectDisplayID, CGDisplay, CGDisplayCopyAllDisplayModes, CGDisplayModeGetHeight,
     CGDisplayModeGetRefreshRate, CGDisplayModeGetWidth, CGDisplayModeRef, CGDisplaySetDisplayMode,
     CGGetActiveDisplayList, CGMainDisplayID,
 };
 
-use crate::display::DisplayMode;
+use crate::display::{DisplayMode, OutputInfo};
+use crate::edid::MonitorIdentity;
+use crate::error::Error;
 
 pub struct MacOSDisplayManager {
     display_id: CGDirectDisplayID,
@@ -24,9 +26,41 @@ impl MacOSDisplayManager {
         }
     }
 
-    pub async fn get_available_modes(&self) -> Result<Vec<DisplayMode>> {
+    pub async fn get_outputs(&self) -> Result<Vec<OutputInfo>> {
+        let display_ids = CGDisplay::active_displays().map_err(|e| {
+            Error::BackendUnavailable(format!(
+                "Failed to list displays. Core Graphics error: {}",
+                e
+            ))
+        })?;
+
+        let mut outputs = Vec::new();
+        for display_id in display_ids {
+            let name = display_id.to_string();
+            let current_mode = self.get_current_display_mode(Some(&name)).await.ok();
+            // Core Graphics reports the EDID vendor, product and numeric serial directly
+            let display = CGDisplay::new(display_id);
+            let identity = MonitorIdentity::from_vendor(
+                display.vendor_number() as u16,
+                display.model_number() as u16,
+                display.serial_number(),
+            );
+            outputs.push(OutputInfo {
+                name,
+                primary: display_id == self.display_id,
+                current_mode,
+                identity: Some(identity),
+            });
+        }
+
+        Ok(outputs)
+    }
+
+    pub async fn get_available_modes(&self, output: Option<&str>) -> Result<Vec<DisplayMode>> {
+        let display_id = self.resolve_display(output)?;
+
         unsafe {
-            let modes_array = CGDisplayCopyAllDisplayModes(self.display_id, std::ptr::null());
+            let modes_array = CGDisplayCopyAllDisplayModes(display_id, std::ptr::null());
             if modes_array.is_null() {
                 return Err(anyhow!("Failed to get display modes"));
             }
@@ -83,9 +117,11 @@ impl MacOSDisplayManager {
         }
     }
 
-    pub async fn set_display_mode(&self, mode: &DisplayMode) -> Result<()> {
+    pub async fn set_display_mode(&self, output: Option<&str>, mode: &DisplayMode) -> Result<()> {
+        let display_id = self.resolve_display(output)?;
+
         unsafe {
-            let modes_array = CGDisplayCopyAllDisplayModes(self.display_id, std::ptr::null());
+            let modes_array = CGDisplayCopyAllDisplayModes(display_id, std::ptr::null());
             if modes_array.is_null() {
                 return Err(anyhow!("Failed to get display modes"));
             }
@@ -117,34 +153,36 @@ impl MacOSDisplayManager {
                 Some(mode_ref) => mode_ref,
                 None => {
                     CFRelease(modes_array as CFTypeRef);
-                    return Err(anyhow!(
+                    return Err(Error::NoSuitableMode(format!(
                         "Display mode {}x{}@{}Hz not available",
-                        mode.width,
-                        mode.height,
-                        mode.refresh_rate
-                    ));
+                        mode.width, mode.height, mode.refresh_rate
+                    ))
+                    .into());
                 }
             };
 
-            let result = CGDisplaySetDisplayMode(self.display_id, target_mode, std::ptr::null());
+            let result = CGDisplaySetDisplayMode(display_id, target_mode, std::ptr::null());
             CFRelease(modes_array as CFTypeRef);
 
             if result != 0 {
-                return Err(anyhow!(
+                return Err(Error::ApplyFailed(format!(
                     "Failed to set display mode. Core Graphics error: {}",
                     result
-                ));
+                ))
+                .into());
             }
         }
 
         Ok(())
     }
 
-    pub async fn get_current_display_mode(&self) -> Result<DisplayMode> {
+    pub async fn get_current_display_mode(&self, output: Option<&str>) -> Result<DisplayMode> {
+        let display_id = self.resolve_display(output)?;
+
         unsafe {
             use core_graphics::display::{CGDisplayCopyDisplayMode, CGDisplayModeRelease};
 
-            let current_mode = CGDisplayCopyDisplayMode(self.display_id);
+            let current_mode = CGDisplayCopyDisplayMode(display_id);
             if current_mode.is_null() {
                 return Err(anyhow!("Failed to get current display mode"));
             }
@@ -162,4 +200,14 @@ impl MacOSDisplayManager {
             })
         }
     }
+
+    // Outputs are addressed by their CGDirectDisplayID; `None` is the main display
+    fn resolve_display(&self, output: Option<&str>) -> Result<CGDirectDisplayID> {
+        match output {
+            Some(name) => name
+                .parse::<CGDirectDisplayID>()
+                .map_err(|_| Error::OutputNotFound(format!("Invalid display ID: {}", name)).into()),
+            None => Ok(self.display_id),
+        }
+    }
 }
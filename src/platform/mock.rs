@@ -0,0 +1,191 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::config;
+use crate::display::{DisplayMode, OutputInfo};
+use crate::edid::MonitorIdentity;
+use crate::error::Error;
+
+/// Environment variable naming a JSON file that persists mock outputs between runs
+#[cfg(any(test, feature = "mock-backend"))]
+pub const STATE_ENV: &str = "DISPLAY_SWITCH_MOCK_STATE";
+
+/// A simulated backend with a fixed set of outputs, used to exercise mode
+/// selection and switching without touching real hardware.
+pub struct MockDisplayManager {
+    outputs: Mutex<Vec<MockOutput>>,
+    // Written after every mode change so separate invocations see each other's changes
+    state_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockOutput {
+    pub name: String,
+    pub primary: bool,
+    pub modes: Vec<DisplayMode>,
+    pub current: DisplayMode,
+    #[serde(default)]
+    pub identity: Option<MonitorIdentity>,
+}
+
+impl MockDisplayManager {
+    #[cfg(test)]
+    pub fn new(outputs: Vec<MockOutput>) -> Self {
+        Self {
+            outputs: Mutex::new(outputs),
+            state_file: None,
+        }
+    }
+
+    /// Loads outputs from the file named by `DISPLAY_SWITCH_MOCK_STATE`, if
+    /// set and present, otherwise starts from `default_outputs()`
+    #[cfg(any(test, feature = "mock-backend"))]
+    pub fn from_env() -> Result<Self> {
+        let state_file = std::env::var_os(STATE_ENV).map(PathBuf::from);
+        let outputs = match &state_file {
+            Some(path) if path.exists() => load(path)?,
+            _ => default_outputs(),
+        };
+
+        Ok(Self {
+            outputs: Mutex::new(outputs),
+            state_file,
+        })
+    }
+
+    /// Serves the outputs recorded by `display-switch snapshot`; mode changes
+    /// are not written back
+    pub fn from_snapshot(path: &Path) -> Result<Self> {
+        Ok(Self {
+            outputs: Mutex::new(load(path)?),
+            state_file: None,
+        })
+    }
+
+    pub async fn get_outputs(&self) -> Result<Vec<OutputInfo>> {
+        let outputs = self.outputs.lock().unwrap();
+        Ok(outputs
+            .iter()
+            .map(|output| OutputInfo {
+                name: output.name.clone(),
+                primary: output.primary,
+                current_mode: Some(output.current.clone()),
+                identity: output.identity.clone(),
+            })
+            .collect())
+    }
+
+    pub async fn get_available_modes(&self, output: Option<&str>) -> Result<Vec<DisplayMode>> {
+        self.with_output(output, |output| Ok(output.modes.clone()))
+    }
+
+    pub async fn set_display_mode(&self, output: Option<&str>, mode: &DisplayMode) -> Result<()> {
+        self.with_output(output, |output| {
+            let available = output.modes.iter().find(|m| {
+                m.width == mode.width
+                    && m.height == mode.height
+                    && (m.refresh_rate - mode.refresh_rate).abs() < 0.1
+            });
+
+            match available {
+                Some(available) => {
+                    output.current = available.clone();
+                    Ok(())
+                }
+                None => Err(Error::NoSuitableMode(format!(
+                    "Display mode {} not available on {}",
+                    mode, output.name
+                ))
+                .into()),
+            }
+        })?;
+
+        if let Some(path) = &self.state_file {
+            let outputs = self.outputs.lock().unwrap();
+            config::write_atomically(path, &serde_json::to_string_pretty(&*outputs)?)?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_current_display_mode(&self, output: Option<&str>) -> Result<DisplayMode> {
+        self.with_output(output, |output| Ok(output.current.clone()))
+    }
+
+    fn with_output<T>(
+        &self,
+        name: Option<&str>,
+        f: impl FnOnce(&mut MockOutput) -> Result<T>,
+    ) -> Result<T> {
+        let mut outputs = self.outputs.lock().unwrap();
+        let output = match name {
+            Some(name) => outputs.iter_mut().find(|o| o.name == name),
+            None => {
+                let primary = outputs.iter().position(|o| o.primary).unwrap_or(0);
+                outputs.get_mut(primary)
+            }
+        };
+
+        match output {
+            Some(output) => f(output),
+            None => Err(Error::OutputNotFound(format!(
+                "Output '{}' not found or not connected",
+                name.unwrap_or("default")
+            ))
+            .into()),
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<Vec<MockOutput>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read outputs from {}", path.display()))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("Invalid mock state file {}", path.display()))
+}
+
+/// A 4K primary monitor with common high refresh modes and a 1080p secondary
+#[cfg(any(test, feature = "mock-backend"))]
+pub fn default_outputs() -> Vec<MockOutput> {
+    let mode = |width, height, refresh_rate| DisplayMode {
+        width,
+        height,
+        refresh_rate,
+    };
+    let identity = |product, serial: &str, name: &str, edid_hash: &str| MonitorIdentity {
+        manufacturer: "MCK".to_string(),
+        product,
+        serial: Some(serial.to_string()),
+        name: Some(name.to_string()),
+        edid_hash: Some(edid_hash.to_string()),
+    };
+
+    vec![
+        MockOutput {
+            name: "MOCK-1".to_string(),
+            primary: true,
+            modes: vec![
+                mode(3840, 2160, 60.0),
+                mode(3840, 2160, 30.0),
+                mode(2560, 1440, 144.0),
+                mode(2560, 1440, 120.0),
+                mode(2560, 1440, 60.0),
+                mode(1920, 1080, 144.0),
+                mode(1920, 1080, 120.0),
+                mode(1920, 1080, 60.0),
+                mode(1920, 1080, 59.5),
+            ],
+            current: mode(3840, 2160, 60.0),
+            identity: Some(identity(0x0001, "M0001", "Mock 4K", "6d6f636b00000001")),
+        },
+        MockOutput {
+            name: "MOCK-2".to_string(),
+            primary: false,
+            modes: vec![mode(1920, 1080, 60.0), mode(1280, 720, 60.0)],
+            current: mode(1920, 1080, 60.0),
+            identity: Some(identity(0x0002, "M0002", "Mock FHD", "6d6f636b00000002")),
+        },
+    ]
+}
@@ -1,10 +1,13 @@
-use crate::display::DisplayMode;
+use crate::display::{DisplayMode, OutputInfo};
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "macos")]
 mod macos;
+pub mod mock;
 #[cfg(target_os = "windows")]
 mod windows;
 
@@ -12,61 +15,98 @@ mod windows;
 #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
 mod stub;
 
+/// Which display backend to drive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackendKind {
+    /// The platform's native display API
+    #[default]
+    Native,
+    /// Simulated outputs, for trying out settings and profiles without real hardware
+    #[cfg(any(test, feature = "mock-backend"))]
+    Mock,
+}
+
 pub struct PlatformDisplayManager {
+    inner: Backend,
+}
+
+enum Backend {
     #[cfg(target_os = "windows")]
-    inner: windows::WindowsDisplayManager,
+    Windows(windows::WindowsDisplayManager),
     #[cfg(target_os = "linux")]
-    inner: linux::LinuxDisplayManager,
+    Linux(linux::LinuxDisplayManager),
     #[cfg(target_os = "macos")]
-    inner: macos::MacOSDisplayManager,
+    MacOS(macos::MacOSDisplayManager),
     #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
-    inner: stub::StubDisplayManager,
+    Stub(stub::StubDisplayManager),
+    Mock(mock::MockDisplayManager),
 }
 
-impl PlatformDisplayManager {
-    pub fn new() -> Result<Self> {
-        Ok(Self {
+// Forward a call to whichever backend is active
+macro_rules! dispatch {
+    ($self:ident, $method:ident($($arg:expr),*)) => {
+        match &$self.inner {
             #[cfg(target_os = "windows")]
-            inner: windows::WindowsDisplayManager::new()?,
+            Backend::Windows(inner) => inner.$method($($arg),*).await,
             #[cfg(target_os = "linux")]
-            inner: linux::LinuxDisplayManager::new()?,
+            Backend::Linux(inner) => inner.$method($($arg),*).await,
             #[cfg(target_os = "macos")]
-            inner: macos::MacOSDisplayManager::new()?,
+            Backend::MacOS(inner) => inner.$method($($arg),*).await,
             #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
-            inner: stub::StubDisplayManager::new()?,
+            Backend::Stub(inner) => inner.$method($($arg),*).await,
+            Backend::Mock(inner) => inner.$method($($arg),*).await,
+        }
+    };
+}
+
+impl PlatformDisplayManager {
+    pub fn new(kind: BackendKind) -> Result<Self> {
+        Ok(match kind {
+            BackendKind::Native => Self {
+                #[cfg(target_os = "windows")]
+                inner: Backend::Windows(windows::WindowsDisplayManager::new()?),
+                #[cfg(target_os = "linux")]
+                inner: Backend::Linux(linux::LinuxDisplayManager::new()?),
+                #[cfg(target_os = "macos")]
+                inner: Backend::MacOS(macos::MacOSDisplayManager::new()?),
+                #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+                inner: Backend::Stub(stub::StubDisplayManager::new()?),
+            },
+            #[cfg(any(test, feature = "mock-backend"))]
+            BackendKind::Mock => Self {
+                inner: Backend::Mock(mock::MockDisplayManager::from_env()?),
+            },
         })
     }
 
-    pub async fn get_available_modes(&self) -> Result<Vec<DisplayMode>> {
-        #[cfg(target_os = "windows")]
-        return self.inner.get_available_modes().await;
-        #[cfg(target_os = "linux")]
-        return self.inner.get_available_modes().await;
-        #[cfg(target_os = "macos")]
-        return self.inner.get_available_modes().await;
-        #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
-        return self.inner.get_available_modes().await;
+    /// A read-only mock backend serving outputs recorded in `path`
+    pub fn from_snapshot(path: &Path) -> Result<Self> {
+        Ok(Self {
+            inner: Backend::Mock(mock::MockDisplayManager::from_snapshot(path)?),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn mock(outputs: Vec<mock::MockOutput>) -> Self {
+        Self {
+            inner: Backend::Mock(mock::MockDisplayManager::new(outputs)),
+        }
+    }
+
+    pub async fn get_outputs(&self) -> Result<Vec<OutputInfo>> {
+        dispatch!(self, get_outputs())
+    }
+
+    pub async fn get_available_modes(&self, output: Option<&str>) -> Result<Vec<DisplayMode>> {
+        dispatch!(self, get_available_modes(output))
     }
 
-    pub async fn set_display_mode(&self, mode: &DisplayMode) -> Result<()> {
-        #[cfg(target_os = "windows")]
-        return self.inner.set_display_mode(mode).await;
-        #[cfg(target_os = "linux")]
-        return self.inner.set_display_mode(mode).await;
-        #[cfg(target_os = "macos")]
-        return self.inner.set_display_mode(mode).await;
-        #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
-        return self.inner.set_display_mode(mode).await;
+    pub async fn set_display_mode(&self, output: Option<&str>, mode: &DisplayMode) -> Result<()> {
+        dispatch!(self, set_display_mode(output, mode))
     }
 
-    pub async fn get_current_display_mode(&self) -> Result<DisplayMode> {
-        #[cfg(target_os = "windows")]
-        return self.inner.get_current_display_mode().await;
-        #[cfg(target_os = "linux")]
-        return self.inner.get_current_display_mode().await;
-        #[cfg(target_os = "macos")]
-        return self.inner.get_current_display_mode().await;
-        #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
-        return self.inner.get_current_display_mode().await;
+    pub async fn get_current_display_mode(&self, output: Option<&str>) -> Result<DisplayMode> {
+        dispatch!(self, get_current_display_mode(output))
     }
 }
@@ -1,5 +1,6 @@
-use crate::display::DisplayMode;
-use anyhow::{anyhow, Result};
+use crate::display::{DisplayMode, OutputInfo};
+use crate::error::Error;
+use anyhow::Result;
 
 pub struct StubDisplayManager;
 
@@ -8,7 +9,16 @@ impl StubDisplayManager {
         Ok(Self)
     }
 
-    pub async fn get_available_modes(&self) -> Result<Vec<DisplayMode>> {
+    pub async fn get_outputs(&self) -> Result<Vec<OutputInfo>> {
+        Ok(vec![OutputInfo {
+            name: "default".to_string(),
+            primary: true,
+            current_mode: Some(self.get_current_display_mode(None).await?),
+            identity: None,
+        }])
+    }
+
+    pub async fn get_available_modes(&self, _output: Option<&str>) -> Result<Vec<DisplayMode>> {
         // Return some mock display modes for testing purposes
         Ok(vec![
             DisplayMode {
@@ -39,18 +49,20 @@ impl StubDisplayManager {
         ])
     }
 
-    pub async fn set_display_mode(&self, mode: &DisplayMode) -> Result<()> {
+    pub async fn set_display_mode(&self, _output: Option<&str>, mode: &DisplayMode) -> Result<()> {
         // Stub implementation that doesn't actually change the display
         println!(
             "Stub: Would set display mode to {}x{}@{}Hz",
             mode.width, mode.height, mode.refresh_rate
         );
-        Err(anyhow!(
+        Err(Error::BackendUnavailable(
             "Display switching not supported on this platform. This is a stub implementation."
-        ))
+                .to_string(),
+        )
+        .into())
     }
 
-    pub async fn get_current_display_mode(&self) -> Result<DisplayMode> {
+    pub async fn get_current_display_mode(&self, _output: Option<&str>) -> Result<DisplayMode> {
         // Return a mock current display mode for testing
         Ok(DisplayMode {
             width: 1920,
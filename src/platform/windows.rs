@@ -1,11 +1,18 @@
 use anyhow::{anyhow, Result};
+use std::ffi::{CStr, CString};
 use std::mem;
-use winapi::um::wingdi::DEVMODEA;
+use winapi::um::wingdi::{
+    DEVMODEA, DISPLAY_DEVICEA, DISPLAY_DEVICE_ATTACHED_TO_DESKTOP, DISPLAY_DEVICE_PRIMARY_DEVICE,
+};
 use winapi::um::winuser::{
-    ChangeDisplaySettingsA, EnumDisplaySettingsA, CDS_UPDATEREGISTRY, DISP_CHANGE_SUCCESSFUL,
+    ChangeDisplaySettingsExA, EnumDisplayDevicesA, EnumDisplaySettingsA, CDS_UPDATEREGISTRY,
+    DISP_CHANGE_SUCCESSFUL,
 };
 
-use crate::display::DisplayMode;
+use crate::display::{DisplayMode, OutputInfo};
+use crate::error::Error;
+
+const ENUM_CURRENT_SETTINGS: u32 = 0xFFFFFFFF;
 
 pub struct WindowsDisplayManager;
 
@@ -14,7 +21,43 @@ impl WindowsDisplayManager {
         Ok(Self)
     }
 
-    pub async fn get_available_modes(&self) -> Result<Vec<DisplayMode>> {
+    pub async fn get_outputs(&self) -> Result<Vec<OutputInfo>> {
+        let mut outputs = Vec::new();
+        let mut device_index = 0;
+
+        unsafe {
+            loop {
+                let mut device: DISPLAY_DEVICEA = mem::zeroed();
+                device.cb = mem::size_of::<DISPLAY_DEVICEA>() as u32;
+
+                if EnumDisplayDevicesA(std::ptr::null(), device_index, &mut device, 0) == 0 {
+                    break;
+                }
+                device_index += 1;
+
+                if device.StateFlags & DISPLAY_DEVICE_ATTACHED_TO_DESKTOP == 0 {
+                    continue;
+                }
+
+                let name = CStr::from_ptr(device.DeviceName.as_ptr())
+                    .to_string_lossy()
+                    .into_owned();
+                let current_mode = self.get_current_display_mode(Some(&name)).await.ok();
+
+                outputs.push(OutputInfo {
+                    name,
+                    primary: device.StateFlags & DISPLAY_DEVICE_PRIMARY_DEVICE != 0,
+                    current_mode,
+                    identity: None,
+                });
+            }
+        }
+
+        Ok(outputs)
+    }
+
+    pub async fn get_available_modes(&self, output: Option<&str>) -> Result<Vec<DisplayMode>> {
+        let device = device_name(output)?;
         let mut modes = Vec::new();
         let mut mode_index = 0;
 
@@ -23,7 +66,7 @@ impl WindowsDisplayManager {
                 let mut dev_mode: DEVMODEA = mem::zeroed();
                 dev_mode.dmSize = mem::size_of::<DEVMODEA>() as u16;
 
-                let result = EnumDisplaySettingsA(std::ptr::null(), mode_index, &mut dev_mode);
+                let result = EnumDisplaySettingsA(device_ptr(&device), mode_index, &mut dev_mode);
 
                 if result == 0 {
                     break;
@@ -70,7 +113,9 @@ impl WindowsDisplayManager {
         Ok(modes)
     }
 
-    pub async fn set_display_mode(&self, mode: &DisplayMode) -> Result<()> {
+    pub async fn set_display_mode(&self, output: Option<&str>, mode: &DisplayMode) -> Result<()> {
+        let device = device_name(output)?;
+
         unsafe {
             // Find the exact mode from available modes to get all parameters
             let mut found_mode: Option<DEVMODEA> = None;
@@ -80,7 +125,7 @@ impl WindowsDisplayManager {
                 let mut dev_mode: DEVMODEA = mem::zeroed();
                 dev_mode.dmSize = mem::size_of::<DEVMODEA>() as u16;
 
-                let result = EnumDisplaySettingsA(std::ptr::null(), mode_index, &mut dev_mode);
+                let result = EnumDisplaySettingsA(device_ptr(&device), mode_index, &mut dev_mode);
 
                 if result == 0 {
                     break;
@@ -102,38 +147,43 @@ impl WindowsDisplayManager {
             let mut target_mode = match found_mode {
                 Some(mode) => mode,
                 None => {
-                    return Err(anyhow!(
+                    return Err(Error::NoSuitableMode(format!(
                         "Display mode {}x{}@{}Hz not found in available modes",
-                        mode.width,
-                        mode.height,
-                        mode.refresh_rate
-                    ));
+                        mode.width, mode.height, mode.refresh_rate
+                    ))
+                    .into());
                 }
             };
 
-            let result = ChangeDisplaySettingsA(&mut target_mode, CDS_UPDATEREGISTRY);
+            let result = ChangeDisplaySettingsExA(
+                device_ptr(&device),
+                &mut target_mode,
+                std::ptr::null_mut(),
+                CDS_UPDATEREGISTRY,
+                std::ptr::null_mut(),
+            );
 
             if result != DISP_CHANGE_SUCCESSFUL {
-                return Err(anyhow!(
+                return Err(Error::ApplyFailed(format!(
                     "Failed to change display settings. Error code: {}",
                     result
-                ));
+                ))
+                .into());
             }
         }
 
         Ok(())
     }
 
-    pub async fn get_current_display_mode(&self) -> Result<DisplayMode> {
+    pub async fn get_current_display_mode(&self, output: Option<&str>) -> Result<DisplayMode> {
+        let device = device_name(output)?;
+
         unsafe {
             let mut dev_mode: DEVMODEA = mem::zeroed();
             dev_mode.dmSize = mem::size_of::<DEVMODEA>() as u16;
 
-            let result = EnumDisplaySettingsA(
-                std::ptr::null(),
-                0xFFFFFFFF, // ENUM_CURRENT_SETTINGS
-                &mut dev_mode,
-            );
+            let result =
+                EnumDisplaySettingsA(device_ptr(&device), ENUM_CURRENT_SETTINGS, &mut dev_mode);
 
             if result == 0 {
                 return Err(anyhow!("Failed to get current display settings"));
@@ -147,3 +197,19 @@ impl WindowsDisplayManager {
         }
     }
 }
+
+// Outputs are addressed by GDI device name (e.g. `\\.\DISPLAY1`); `None` is the primary display
+fn device_name(output: Option<&str>) -> Result<Option<CString>> {
+    output
+        .map(|name| {
+            CString::new(name)
+                .map_err(|_| Error::OutputNotFound(format!("Invalid output name: {}", name)).into())
+        })
+        .transpose()
+}
+
+fn device_ptr(device: &Option<CString>) -> *const i8 {
+    device
+        .as_ref()
+        .map_or(std::ptr::null(), |name| name.as_ptr())
+}
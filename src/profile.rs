@@ -1,111 +1,1202 @@
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::display::DisplaySpec;
+use crate::conditions::{Environment, When};
+use crate::config::{self, Format, CURRENT_VERSION};
+use crate::display::{DisplaySpec, OutputInfo};
+use crate::error::Error;
+use crate::hooks::Hooks;
+use crate::parser::{self, Variables};
+use crate::platform::BackendKind;
+use crate::settings::Settings;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct ProfilesData {
-    profiles: HashMap<String, Vec<DisplaySpec>>,
+    #[serde(default = "default_version")]
+    version: u32,
+    #[serde(default, skip_serializing_if = "Settings::is_empty")]
+    settings: Settings,
+    profiles: BTreeMap<String, Profile>,
 }
 
-pub struct ProfileManager {
-    config_file: PathBuf,
-    data: ProfilesData,
+fn default_version() -> u32 {
+    CURRENT_VERSION
 }
 
-impl ProfileManager {
-    pub fn new() -> Result<Self> {
-        let config_dir = dirs::config_dir()
-            .ok_or_else(|| anyhow!("Unable to determine config directory"))?
-            .join("display-switch");
-
-        if !config_dir.exists() {
-            fs::create_dir_all(&config_dir)?;
-        }
-
-        let config_file = config_dir.join("profiles.json");
-
-        let data = if config_file.exists() {
-            let content = fs::read_to_string(&config_file)?;
-            match serde_json::from_str(&content) {
-                Ok(data) => data,
-                Err(e) => {
-                    eprintln!(
-                        "Warning: Failed to parse profiles file: {e}. Starting with empty profiles."
-                    );
-                    ProfilesData {
-                        profiles: HashMap::new(),
-                    }
-                }
-            }
+impl Default for ProfilesData {
+    fn default() -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            settings: Settings::default(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+/// A display specification stored in a profile, kept exactly as the user typed it
+/// so keywords such as `4k` are resolved at activation time rather than creation time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredSpecRepr", into = "String")]
+pub struct StoredSpec(String);
+
+// Profiles written by older versions stored each spec as a `DisplaySpec` struct
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredSpecRepr {
+    Text(String),
+    Legacy(DisplaySpec),
+}
+
+impl StoredSpec {
+    /// Validates `spec`; specs with placeholders can only be checked for
+    /// syntax until their variables are known
+    pub fn new(spec: &str) -> Result<Self> {
+        if parser::has_variables(spec) {
+            parser::check_variables(spec)?;
         } else {
-            ProfilesData {
-                profiles: HashMap::new(),
-            }
-        };
+            parser::parse_display_spec(spec)?;
+        }
+        Ok(Self(spec.trim().to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn has_variables(&self) -> bool {
+        parser::has_variables(&self.0)
+    }
+
+    /// The spec with its placeholders filled in
+    pub fn expand(&self, variables: &Variables) -> Result<String> {
+        parser::expand_variables(&self.0, variables)
+    }
+
+    pub fn parse(&self, variables: &Variables) -> Result<DisplaySpec> {
+        parser::parse_display_spec(&self.expand(variables)?)
+    }
+}
+
+impl From<StoredSpecRepr> for StoredSpec {
+    fn from(repr: StoredSpecRepr) -> Self {
+        match repr {
+            StoredSpecRepr::Text(spec) => Self(spec),
+            StoredSpecRepr::Legacy(spec) => Self(spec.to_string()),
+        }
+    }
+}
+
+impl From<StoredSpec> for String {
+    fn from(spec: StoredSpec) -> Self {
+        spec.0
+    }
+}
 
-        Ok(Self { config_file, data })
+impl fmt::Display for StoredSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
     }
+}
+
+/// The specifications a profile applies to one output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputEntry {
+    /// Output name; `None` means the default output. With `monitor` set it is
+    /// only used when no connected output reports an identity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Monitor identity (`MFG:PRODUCT[:SERIAL]` or `edid:<hash>`), matched
+    /// whichever connector the monitor is plugged into
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitor: Option<String>,
+    /// Specifications to try, in order of preference
+    pub specs: Vec<StoredSpec>,
+    /// Skip this entry instead of failing when the output is not connected
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+    /// Only use this entry when these conditions hold
+    #[serde(default, skip_serializing_if = "When::is_empty")]
+    pub when: When,
+}
 
-    pub fn create_profile(&mut self, name: String, specs: Vec<DisplaySpec>) -> Result<()> {
+impl OutputEntry {
+    pub fn new(output: Option<String>, specs: &[String], optional: bool) -> Result<Self> {
         if specs.is_empty() {
             return Err(anyhow!(
                 "Profile must have at least one display specification"
             ));
         }
 
-        self.data.profiles.insert(name, specs);
-        self.save()?;
+        Ok(Self {
+            output,
+            monitor: None,
+            specs: specs
+                .iter()
+                .map(|s| StoredSpec::new(s))
+                .collect::<Result<_>>()?,
+            optional,
+            when: When::default(),
+        })
+    }
+
+    /// Whether this entry targets the default output
+    pub fn is_default_output(&self) -> bool {
+        self.output.is_none() && self.monitor.is_none()
+    }
+
+    // Entries for one output under different conditions may coexist
+    fn same_target(&self, other: &OutputEntry) -> bool {
+        self.output == other.output && self.monitor == other.monitor && self.when == other.when
+    }
+
+    /// Finds the connected output this entry targets: the one whose monitor
+    /// matches `monitor`, otherwise the one named `output`
+    pub fn find_output<'a>(&self, connected: &'a [OutputInfo]) -> Option<&'a OutputInfo> {
+        let by_monitor = self.monitor.as_deref().and_then(|monitor| {
+            connected.iter().find(|o| {
+                o.identity
+                    .as_ref()
+                    .is_some_and(|identity| identity.matches(monitor))
+            })
+        });
+        by_monitor.or_else(|| {
+            let output = self.output.as_deref()?;
+            connected.iter().find(|o| o.name == output)
+        })
+    }
+}
+
+/// `monitor (output)`, `monitor`, `output` or `default`, for messages
+impl fmt::Display for OutputEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.monitor, &self.output) {
+            (Some(monitor), Some(output)) => write!(f, "{monitor} ({output})"),
+            (Some(monitor), None) => write!(f, "{monitor}"),
+            (None, Some(output)) => write!(f, "{output}"),
+            (None, None) => write!(f, "default"),
+        }
+    }
+}
+
+/// How the outputs listed in a `match` block must relate to the connected ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchMode {
+    /// At least one listed output is connected
+    AnyOf,
+    /// Every listed output is connected, possibly alongside others
+    Subset,
+    /// Exactly the listed outputs are connected
+    #[default]
+    Exact,
+}
+
+impl fmt::Display for MatchMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchMode::AnyOf => write!(f, "any-of"),
+            MatchMode::Subset => write!(f, "subset"),
+            MatchMode::Exact => write!(f, "exact"),
+        }
+    }
+}
+
+/// Describes the set of connected outputs a profile is meant for, so that
+/// `profile auto` can pick it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchRule {
+    /// Monitor identities (`MFG:PRODUCT[:SERIAL]`, `edid:<hash>`) or connector names
+    pub outputs: Vec<String>,
+    #[serde(default)]
+    pub mode: MatchMode,
+}
+
+impl MatchRule {
+    /// A rule matching exactly the given outputs, identified by monitor where known
+    pub fn fingerprint(connected: &[OutputInfo]) -> Self {
+        Self {
+            outputs: connected.iter().map(fingerprint_of).collect(),
+            mode: MatchMode::Exact,
+        }
+    }
+
+    /// How many connected outputs this rule matched, or `None` if it does not fit
+    pub fn score(&self, connected: &[OutputInfo]) -> Option<usize> {
+        // Each listed output claims a distinct connected one
+        let mut used = vec![false; connected.len()];
+        let mut matched = 0;
+        for pattern in &self.outputs {
+            let found =
+                (0..connected.len()).find(|&i| !used[i] && output_matches(pattern, &connected[i]));
+            if let Some(i) = found {
+                used[i] = true;
+                matched += 1;
+            }
+        }
+
+        let fits = match self.mode {
+            MatchMode::AnyOf => matched > 0,
+            MatchMode::Subset => matched == self.outputs.len(),
+            MatchMode::Exact => matched == self.outputs.len() && matched == connected.len(),
+        };
+        fits.then_some(matched)
+    }
+}
+
+/// The value `--match-current` records for an output: its monitor identity
+/// if the backend can read one, otherwise the connector name
+pub fn fingerprint_of(output: &OutputInfo) -> String {
+    match &output.identity {
+        Some(identity) => identity.to_string(),
+        None => output.name.clone(),
+    }
+}
+
+// Patterns with a ':' are monitor identities, anything else a connector name
+fn output_matches(pattern: &str, output: &OutputInfo) -> bool {
+    if pattern.contains(':') {
+        output
+            .identity
+            .as_ref()
+            .is_some_and(|identity| identity.matches(pattern))
+    } else {
+        output.name == pattern
+    }
+}
+
+/// A named profile: one or more outputs, each with its own fallback list
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "ProfileRepr", into = "ProfileRepr")]
+pub struct Profile {
+    pub outputs: Vec<OutputEntry>,
+    /// Connected outputs this profile is meant for, used by `profile auto`
+    pub match_rule: Option<MatchRule>,
+    /// Profile whose outputs and match block this one inherits and overrides
+    pub extends: Option<String>,
+    /// Profiles whose output entries are merged in, later ones winning
+    pub includes: Vec<String>,
+    /// Commands run around applying this profile, after the global ones
+    pub hooks: Hooks,
+    /// Machines and sessions this profile is for; elsewhere it is hidden
+    pub when: When,
+}
+
+// Profiles for just the default output are stored as a plain list of specs,
+// the only form older versions understand
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
+enum ProfileRepr {
+    Specs(Vec<StoredSpec>),
+    Outputs {
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        outputs: Vec<OutputEntry>,
+        #[serde(rename = "match", default, skip_serializing_if = "Option::is_none")]
+        match_rule: Option<MatchRule>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        extends: Option<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        includes: Vec<String>,
+        #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+        hooks: Hooks,
+        #[serde(default, skip_serializing_if = "When::is_empty")]
+        when: When,
+    },
+}
+
+impl Profile {
+    /// A profile for the default output
+    pub fn single(specs: Vec<StoredSpec>) -> Self {
+        Self {
+            outputs: vec![OutputEntry {
+                output: None,
+                monitor: None,
+                specs,
+                optional: false,
+                when: When::default(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// Whether the profile is only complete once `extends`/`includes` are resolved
+    pub fn inherits(&self) -> bool {
+        self.extends.is_some() || !self.includes.is_empty()
+    }
+
+    /// Adds `other`'s output entries, replacing entries for the same output
+    /// under the same conditions
+    fn merge_outputs(&mut self, other: Vec<OutputEntry>) {
+        for entry in other {
+            let existing = self.outputs.iter_mut().find(|e| e.same_target(&entry));
+            match existing {
+                Some(existing) => *existing = entry,
+                None => self.outputs.push(entry),
+            }
+        }
+    }
+
+    /// The specs of a profile that only targets the default output
+    pub fn default_output_specs(&self) -> Option<&[StoredSpec]> {
+        match self.outputs.as_slice() {
+            [entry] if entry.is_default_output() && !entry.optional && entry.when.is_empty() => {
+                Some(&entry.specs)
+            }
+            _ => None,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.outputs.is_empty() && !self.inherits() {
+            return Err(anyhow!("Profile must have at least one output entry"));
+        }
+        if self
+            .match_rule
+            .as_ref()
+            .is_some_and(|rule| rule.outputs.is_empty())
+        {
+            return Err(anyhow!(
+                "A profile's match block must list at least one output"
+            ));
+        }
+
+        for (i, entry) in self.outputs.iter().enumerate() {
+            if entry.specs.is_empty() {
+                return Err(anyhow!(
+                    "Profile must have at least one display specification"
+                ));
+            }
+            if self.outputs[..i].iter().any(|e| e.same_target(entry)) {
+                return Err(anyhow!(
+                    "Output '{}' appears more than once in the profile",
+                    entry
+                ));
+            }
+        }
+
         Ok(())
     }
+}
+
+impl From<ProfileRepr> for Profile {
+    fn from(repr: ProfileRepr) -> Self {
+        match repr {
+            ProfileRepr::Specs(specs) => Profile::single(specs),
+            ProfileRepr::Outputs {
+                outputs,
+                match_rule,
+                extends,
+                includes,
+                hooks,
+                when,
+            } => Profile {
+                outputs,
+                match_rule,
+                extends,
+                includes,
+                hooks,
+                when,
+            },
+        }
+    }
+}
+
+impl From<Profile> for ProfileRepr {
+    fn from(profile: Profile) -> Self {
+        match profile.default_output_specs() {
+            Some(specs)
+                if profile.match_rule.is_none()
+                    && !profile.inherits()
+                    && profile.hooks.is_empty()
+                    && profile.when.is_empty() =>
+            {
+                ProfileRepr::Specs(specs.to_vec())
+            }
+            _ => ProfileRepr::Outputs {
+                outputs: profile.outputs,
+                match_rule: profile.match_rule,
+                extends: profile.extends,
+                includes: profile.includes,
+                hooks: profile.hooks,
+                when: profile.when,
+            },
+        }
+    }
+}
+
+/// Configuration layer a profile was loaded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    System,
+    User,
+}
 
-    pub fn get_profile(&self, name: &str) -> Result<Vec<DisplaySpec>> {
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layer::System => write!(f, "system"),
+            Layer::User => write!(f, "user"),
+        }
+    }
+}
+
+/// A profile as seen through the merged system and user layers
+#[derive(Debug, Clone)]
+pub struct ProfileEntry {
+    pub name: String,
+    pub profile: Profile,
+    pub layer: Layer,
+}
+
+/// What `import_profiles` does with a profile whose name is already taken
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictMode {
+    /// Import nothing and report the conflicts
+    #[default]
+    Fail,
+    /// Import under the first free name of the form `name-2`, `name-3`, ...
+    Rename,
+    /// Replace the existing profile
+    Overwrite,
+    /// Keep the existing profile
+    Skip,
+}
+
+/// What happened to one profile of an import
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportOutcome {
+    Added(String),
+    Renamed { from: String, to: String },
+    Overwritten(String),
+    Skipped(String),
+}
+
+/// How a name typed on the command line relates to the defined profiles
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileLookup {
+    /// A profile has exactly this name
+    Exact(String),
+    /// A single profile matches ignoring case, or starts with the name
+    Fuzzy(String),
+    /// Several profiles match ignoring case or start with the name
+    Ambiguous(Vec<String>),
+    /// No profile matches; holds similar names, closest first
+    NotFound(Vec<String>),
+}
+
+pub struct ProfileManager {
+    config_file: PathBuf,
+    data: ProfilesData,
+    // Why the user profiles file could not be parsed, if it could not; its
+    // profiles cannot be looked up until it is fixed
+    parse_error: Option<String>,
+    // Read-only profiles shared by every user; entries in `data` take precedence
+    system: Option<(PathBuf, ProfilesData)>,
+    // What `when` conditions are evaluated against
+    environment: Environment,
+}
+
+impl ProfileManager {
+    /// Opens the user profiles file (see `config::user_profiles_path`) layered
+    /// over the system profiles file, if there is one
+    pub fn new(config_file: Option<PathBuf>) -> Result<Self> {
+        let mut manager = Self::open(config::user_profiles_path(config_file)?)?;
+        if let Some(system_file) = config::system_profiles_path() {
+            manager.system = load_system_profiles(&system_file).map(|data| (system_file, data));
+        }
+        Ok(manager)
+    }
+
+    fn open(config_file: PathBuf) -> Result<Self> {
+        let loaded = {
+            let _lock = config::lock(&config_file)?;
+            load_profiles(&config_file)?
+        };
+
+        // Reading never touches a broken or outdated file; only `update`
+        // moves it aside or writes the upgrade
+        let (data, parse_error) = match loaded {
+            Ok((data, _)) => (data, None),
+            Err(e) => {
+                eprintln!(
+                    "Warning: Failed to parse profiles file {}: {}. Its settings are ignored \
+                     and its profiles cannot be used until it is fixed.",
+                    config_file.display(),
+                    e
+                );
+                (ProfilesData::default(), Some(e.to_string()))
+            }
+        };
+
+        Ok(Self {
+            config_file,
+            data,
+            parse_error,
+            system: None,
+            environment: Environment::detect(Default::default()),
+        })
+    }
+
+    /// Sets the backend `when.backend` conditions are checked against, once
+    /// the effective settings are known
+    pub fn set_backend(&mut self, backend: BackendKind) {
+        self.environment.backend = backend;
+    }
+
+    /// Whether a profile's own `when` conditions hold here
+    pub fn applies(&self, profile: &Profile) -> bool {
+        profile.when.matches(&self.environment)
+    }
+
+    /// Path of the user profiles file that changes are written to
+    pub fn config_file(&self) -> &Path {
+        &self.config_file
+    }
+
+    /// Creates a profile for the default output
+    pub fn create_profile(&mut self, name: String, specs: &[String], force: bool) -> Result<()> {
+        let entry = OutputEntry::new(None, specs, false)?;
+        self.save_profile(
+            name,
+            Profile {
+                outputs: vec![entry],
+                ..Default::default()
+            },
+            force,
+        )
+    }
+
+    pub fn save_profile(&mut self, name: String, profile: Profile, force: bool) -> Result<()> {
+        profile.validate()?;
+
+        self.update(|data| {
+            if !force && data.profiles.contains_key(&name) {
+                return Err(anyhow!(
+                    "Profile '{}' already exists (use --force to overwrite)",
+                    name
+                ));
+            }
+
+            data.profiles.insert(name, profile);
+            Ok(())
+        })
+    }
+
+    /// Returns a profile as stored, without resolving `extends`/`includes`
+    pub fn profile(&self, name: &str) -> Result<Profile> {
+        self.ensure_parsed()?;
         self.data
             .profiles
             .get(name)
+            .or_else(|| self.system_profile(name))
             .cloned()
-            .ok_or_else(|| anyhow!("Profile '{}' not found", name))
+            .ok_or_else(|| Error::ProfileNotFound(format!("Profile '{}' not found", name)).into())
     }
 
-    #[allow(dead_code)]
-    pub fn delete_profile(&mut self, name: &str) -> Result<()> {
-        if self.data.profiles.remove(name).is_some() {
-            self.save()?;
-            Ok(())
+    /// Returns a profile with `extends` and `includes` applied. Entries from
+    /// the profile itself override included ones, which override inherited ones.
+    /// Entries whose `when` conditions fail here are dropped; of the rest, the
+    /// last one for each output wins.
+    pub fn resolved_profile(&self, name: &str) -> Result<Profile> {
+        let mut resolved = self.resolve(name, &mut Vec::new())?;
+        if !self.applies(&resolved) {
+            return Err(anyhow!(
+                "Profile '{}' does not apply here (when: {})",
+                name,
+                resolved.when
+            ));
+        }
+        if resolved.outputs.is_empty() {
+            return Err(anyhow!(
+                "Profile '{}' has no output entries, even after resolving extends and includes",
+                name
+            ));
+        }
+
+        let mut outputs: Vec<OutputEntry> = Vec::new();
+        for mut entry in resolved.outputs {
+            if !entry.when.matches(&self.environment) {
+                continue;
+            }
+            entry.when = When::default();
+            outputs.retain(|e| !e.same_target(&entry));
+            outputs.push(entry);
+        }
+        if outputs.is_empty() {
+            return Err(anyhow!(
+                "None of the output entries of profile '{}' apply here",
+                name
+            ));
+        }
+        resolved.outputs = outputs;
+        Ok(resolved)
+    }
+
+    /// Returns the specifications of a resolved profile for the default
+    /// output, with placeholders still to be expanded
+    pub fn get_profile(&self, name: &str) -> Result<Vec<StoredSpec>> {
+        self.resolved_profile(name)?
+            .default_output_specs()
+            .map(<[StoredSpec]>::to_vec)
+            .ok_or_else(|| per_output_error(name))
+    }
+
+    /// Returns a profile's specifications as they were typed; fails for
+    /// profiles with per-output entries
+    #[cfg(test)]
+    pub fn get_stored_specs(&self, name: &str) -> Result<Vec<StoredSpec>> {
+        self.profile(name)?
+            .default_output_specs()
+            .map(<[StoredSpec]>::to_vec)
+            .ok_or_else(|| per_output_error(name))
+    }
+
+    // `stack` holds the profiles being resolved, so a name that appears in
+    // it again is a cycle while one reached twice through different paths
+    // (diamond inheritance) is fine
+    fn resolve(&self, name: &str, stack: &mut Vec<String>) -> Result<Profile> {
+        if let Some(start) = stack.iter().position(|n| n == name) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(name.to_string());
+            return Err(Error::InvalidConfig(format!(
+                "Profile cycle detected: {}",
+                cycle.join(" -> ")
+            ))
+            .into());
+        }
+
+        let profile = self.profile(name)?;
+        let parent = |other: &str, relation: &str| -> Result<()> {
+            if self.profile_exists(other) {
+                Ok(())
+            } else {
+                Err(Error::InvalidConfig(format!(
+                    "Profile '{}' {} unknown profile '{}'",
+                    name, relation, other
+                ))
+                .into())
+            }
+        };
+
+        stack.push(name.to_string());
+        let mut resolved = match &profile.extends {
+            Some(base) => {
+                parent(base, "extends")?;
+                self.resolve(base, stack)?
+            }
+            None => Profile::default(),
+        };
+        for include in &profile.includes {
+            parent(include, "includes")?;
+            let included = self.resolve(include, stack)?;
+            resolved.merge_outputs(included.outputs);
+        }
+        stack.pop();
+
+        resolved.merge_outputs(profile.outputs);
+        if profile.match_rule.is_some() {
+            resolved.match_rule = profile.match_rule;
+        }
+        resolved.hooks = resolved.hooks.overlay(&profile.hooks);
+        // Conditions are not inherited: they say where the profile itself is used
+        resolved.when = profile.when;
+        Ok(resolved)
+    }
+
+    /// Returns the layer that currently provides `name`
+    pub fn profile_layer(&self, name: &str) -> Option<Layer> {
+        if self.data.profiles.contains_key(name) {
+            Some(Layer::User)
         } else {
-            Err(anyhow!("Profile '{}' not found", name))
+            self.system_profile(name).map(|_| Layer::System)
         }
     }
 
-    pub fn list_profiles(&self) -> Result<Vec<(String, Vec<DisplaySpec>)>> {
+    pub fn delete_profile(&mut self, name: &str) -> Result<()> {
+        self.ensure_user_profile(name)?;
+        self.update(|data| match data.profiles.remove(name) {
+            Some(_) => Ok(()),
+            None => Err(Error::ProfileNotFound(format!("Profile '{}' not found", name)).into()),
+        })
+    }
+
+    pub fn rename_profile(&mut self, from: &str, to: &str, force: bool) -> Result<()> {
+        self.ensure_user_profile(from)?;
+        self.update(|data| {
+            copy_profile_data(data, None, from, to, force)?;
+            if from != to {
+                data.profiles.remove(from);
+            }
+            Ok(())
+        })
+    }
+
+    /// Copies a profile into the user layer; the source may be a system profile
+    pub fn copy_profile(&mut self, from: &str, to: &str, force: bool) -> Result<()> {
+        let system_profile = self.system_profile(from).cloned();
+        self.update(|data| copy_profile_data(data, system_profile, from, to, force))
+    }
+
+    /// Profiles from both layers in name order. Those whose `when` conditions
+    /// fail here are left out unless `all` is set.
+    pub fn list_profiles(&self, all: bool) -> Result<Vec<ProfileEntry>> {
+        self.ensure_parsed()?;
         let mut profiles: Vec<_> = self
             .data
             .profiles
             .iter()
-            .map(|(name, specs)| (name.clone(), specs.clone()))
+            .map(|(name, profile)| (name, profile, Layer::User))
+            .chain(
+                self.system
+                    .iter()
+                    .flat_map(|(_, system)| &system.profiles)
+                    .filter(|(name, _)| !self.data.profiles.contains_key(*name))
+                    .map(|(name, profile)| (name, profile, Layer::System)),
+            )
+            .filter(|(_, profile, _)| all || self.applies(profile))
+            .map(|(name, profile, layer)| ProfileEntry {
+                name: name.clone(),
+                profile: profile.clone(),
+                layer,
+            })
             .collect();
 
-        profiles.sort_by(|a, b| a.0.cmp(&b.0));
+        profiles.sort_by(|a, b| a.name.cmp(&b.name));
         Ok(profiles)
     }
 
+    /// The profile whose `match` block fits the connected outputs best: exact
+    /// beats subset beats any-of, then more matched outputs win, then name order
+    pub fn select_profile(
+        &self,
+        connected: &[OutputInfo],
+    ) -> Result<Option<(ProfileEntry, MatchMode)>> {
+        let mut best: Option<(ProfileEntry, MatchMode, usize)> = None;
+        for mut entry in self.list_profiles(false)? {
+            // The match block may be inherited
+            if entry.profile.inherits() {
+                match self.resolved_profile(&entry.name) {
+                    Ok(profile) => entry.profile = profile,
+                    Err(e) => {
+                        eprintln!("Warning: skipping profile '{}': {e}", entry.name);
+                        continue;
+                    }
+                }
+            }
+            let Some(rule) = &entry.profile.match_rule else {
+                continue;
+            };
+            let Some(matched) = rule.score(connected) else {
+                continue;
+            };
+            let mode = rule.mode;
+            if best.as_ref().is_none_or(|(_, best_mode, best_matched)| {
+                (mode, matched) > (*best_mode, *best_matched)
+            }) {
+                best = Some((entry, mode, matched));
+            }
+        }
+        Ok(best.map(|(entry, mode, _)| (entry, mode)))
+    }
+
+    /// Returns the named profiles as stored, along with every profile they
+    /// reference through `extends` or `includes`. The second list names the
+    /// profiles that were added as references.
+    pub fn export_profiles(
+        &self,
+        names: &[String],
+    ) -> Result<(BTreeMap<String, Profile>, Vec<String>)> {
+        let mut profiles = BTreeMap::new();
+        let mut referenced = Vec::new();
+        let mut pending: Vec<String> = names.iter().rev().cloned().collect();
+        while let Some(name) = pending.pop() {
+            if profiles.contains_key(&name) {
+                continue;
+            }
+            let profile = self.profile(&name)?;
+            if !names.contains(&name) {
+                referenced.push(name.clone());
+            }
+            pending.extend(profile.includes.iter().rev().cloned());
+            pending.extend(profile.extends.clone());
+            profiles.insert(name, profile);
+        }
+        Ok((profiles, referenced))
+    }
+
+    /// Adds `profiles` to the user layer. Every profile and specification is
+    /// validated and conflicts are settled per `mode` before anything is
+    /// written; with `ConflictMode::Fail` any conflict aborts the import.
+    pub fn import_profiles(
+        &mut self,
+        profiles: BTreeMap<String, Profile>,
+        mode: ConflictMode,
+    ) -> Result<Vec<ImportOutcome>> {
+        let mut problems = Vec::new();
+        for (name, profile) in &profiles {
+            if let Err(e) = profile.validate() {
+                problems.push(format!("'{name}': {e}"));
+            }
+            for entry in &profile.outputs {
+                for spec in &entry.specs {
+                    if let Err(e) = StoredSpec::new(spec.as_str()) {
+                        problems.push(format!("'{name}' ({entry}): '{spec}': {e}"));
+                    }
+                }
+            }
+        }
+        if !problems.is_empty() {
+            return Err(anyhow!(
+                "Nothing was imported; the bundle has invalid profiles:\n  {}",
+                problems.join("\n  ")
+            ));
+        }
+
+        let system_names: HashSet<String> = self
+            .system
+            .iter()
+            .flat_map(|(_, system)| system.profiles.keys().cloned())
+            .collect();
+        self.update(|data| {
+            let exists = |name: &str| data.profiles.contains_key(name) || system_names.contains(name);
+            let conflicts: Vec<&String> = profiles.keys().filter(|name| exists(name)).collect();
+            if mode == ConflictMode::Fail && !conflicts.is_empty() {
+                return Err(anyhow!(
+                    "Nothing was imported; these profiles already exist: {} \
+                     (use --rename-on-conflict, --overwrite or --skip)",
+                    conflicts
+                        .iter()
+                        .map(|name| format!("'{name}'"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+
+            let mut renames = HashMap::new();
+            let mut outcomes = Vec::new();
+            let mut imported = BTreeMap::new();
+            for (name, profile) in &profiles {
+                let target = if !exists(name) {
+                    outcomes.push(ImportOutcome::Added(name.clone()));
+                    name.clone()
+                } else {
+                    match mode {
+                        ConflictMode::Fail => unreachable!("conflicts were rejected above"),
+                        ConflictMode::Skip => {
+                            outcomes.push(ImportOutcome::Skipped(name.clone()));
+                            continue;
+                        }
+                        ConflictMode::Overwrite => {
+                            outcomes.push(ImportOutcome::Overwritten(name.clone()));
+                            name.clone()
+                        }
+                        ConflictMode::Rename => {
+                            let free = (2..)
+                                .map(|n| format!("{name}-{n}"))
+                                .find(|candidate| {
+                                    !exists(candidate)
+                                        && !profiles.contains_key(candidate)
+                                        && !renames.values().any(|to| to == candidate)
+                                })
+                                .expect("unbounded range");
+                            renames.insert(name.clone(), free.clone());
+                            outcomes.push(ImportOutcome::Renamed {
+                                from: name.clone(),
+                                to: free.clone(),
+                            });
+                            free
+                        }
+                    }
+                };
+                imported.insert(target, profile.clone());
+            }
+
+            // References between bundled profiles follow their new names
+            let renamed = |name: &String| renames.get(name).unwrap_or(name).clone();
+            for profile in imported.values_mut() {
+                profile.extends = profile.extends.as_ref().map(renamed);
+                profile.includes = profile.includes.iter().map(renamed).collect();
+            }
+            for (name, profile) in &imported {
+                let unknown = profile
+                    .extends
+                    .iter()
+                    .chain(&profile.includes)
+                    .find(|parent| !imported.contains_key(*parent) && !exists(parent));
+                if let Some(parent) = unknown {
+                    return Err(anyhow!(
+                        "Nothing was imported; profile '{}' refers to '{}', which is neither in the bundle nor defined here",
+                        name,
+                        parent
+                    ));
+                }
+            }
+
+            data.profiles.extend(imported);
+            Ok(outcomes)
+        })
+    }
+
     pub fn profile_exists(&self, name: &str) -> bool {
-        self.data.profiles.contains_key(name)
+        self.profile_layer(name).is_some()
     }
 
-    fn save(&self) -> Result<()> {
-        let content = serde_json::to_string_pretty(&self.data)?;
-        fs::write(&self.config_file, content)?;
-        Ok(())
+    /// Finds the profile a user most likely means by `name`: an exact match,
+    /// then a case-insensitive one, then a unique prefix. Profiles that do not
+    /// apply here are included so the error names the reason.
+    pub fn lookup_profile(&self, name: &str) -> Result<ProfileLookup> {
+        self.ensure_parsed()?;
+        if self.profile_exists(name) {
+            return Ok(ProfileLookup::Exact(name.to_string()));
+        }
+
+        let names: Vec<String> = self
+            .list_profiles(true)?
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        let lowercase = name.to_lowercase();
+        let candidates = |matches: &dyn Fn(&str) -> bool| -> Vec<String> {
+            names
+                .iter()
+                .filter(|candidate| matches(&candidate.to_lowercase()))
+                .cloned()
+                .collect()
+        };
+
+        for mut found in [
+            candidates(&|candidate| candidate == lowercase),
+            candidates(&|candidate| candidate.starts_with(&lowercase)),
+        ] {
+            match found.len() {
+                0 => {}
+                1 => return Ok(ProfileLookup::Fuzzy(found.remove(0))),
+                _ => return Ok(ProfileLookup::Ambiguous(found)),
+            }
+        }
+
+        // Allow roughly one typo per three characters
+        let max_distance = (lowercase.chars().count() / 3).max(1);
+        let mut similar: Vec<(usize, String)> = names
+            .into_iter()
+            .map(|candidate| {
+                (
+                    edit_distance(&lowercase, &candidate.to_lowercase()),
+                    candidate,
+                )
+            })
+            .filter(|(distance, _)| *distance <= max_distance)
+            .collect();
+        similar.sort();
+        Ok(ProfileLookup::NotFound(
+            similar.into_iter().take(3).map(|(_, name)| name).collect(),
+        ))
+    }
+
+    /// Effective settings: built-in defaults, then the system file, then the user file
+    pub fn settings(&self) -> Settings {
+        Settings::defaults()
+            .overlay(&self.layer_settings(Layer::System))
+            .overlay(&self.layer_settings(Layer::User))
+    }
+
+    /// Settings defined in a single layer
+    pub fn layer_settings(&self, layer: Layer) -> Settings {
+        match layer {
+            Layer::User => self.data.settings.clone(),
+            Layer::System => self
+                .system
+                .as_ref()
+                .map(|(_, system)| system.settings.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Changes settings in the user file
+    pub fn update_settings(&mut self, f: impl FnOnce(&mut Settings) -> Result<()>) -> Result<()> {
+        self.update(|data| f(&mut data.settings))
+    }
+
+    fn system_profile(&self, name: &str) -> Option<&Profile> {
+        self.system
+            .as_ref()
+            .and_then(|(_, system)| system.profiles.get(name))
+    }
+
+    // While the user file is broken, a profile missing from it may only be
+    // unreadable, so lookups fail instead of reporting it as not found
+    fn ensure_parsed(&self) -> Result<()> {
+        match &self.parse_error {
+            Some(e) => Err(Error::InvalidConfig(format!(
+                "Profiles file {} could not be parsed: {}",
+                self.config_file.display(),
+                e
+            ))
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    // System profiles can be shadowed by a user profile of the same name, but
+    // never modified in place
+    fn ensure_user_profile(&self, name: &str) -> Result<()> {
+        match (self.profile_layer(name), &self.system) {
+            (Some(Layer::System), Some((system_file, _))) => Err(anyhow!(
+                "Profile '{}' is defined in the system configuration {} and cannot be modified; \
+                 copy it to create a user profile instead",
+                name,
+                system_file.display()
+            )),
+            _ => Ok(()),
+        }
     }
+
+    /// Applies `f` to the latest on-disk profiles under an exclusive lock and
+    /// atomically writes the result, so concurrent invocations never lose or
+    /// interleave each other's changes.
+    /// A file that cannot be parsed is moved aside and the change refused, so
+    /// that running the command again starts from empty profiles. A file
+    /// written by an older version is kept as `<file>.v<version>.bak` before
+    /// the upgraded profiles replace it.
+    fn update<T>(&mut self, f: impl FnOnce(&mut ProfilesData) -> Result<T>) -> Result<T> {
+        let _lock = config::lock(&self.config_file)?;
+        let (mut data, migrated_from) = match load_profiles(&self.config_file)? {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                let backup =
+                    config::sibling_path(&self.config_file, &format!(".corrupt-{timestamp}"));
+                fs::rename(&self.config_file, &backup)?;
+                return Err(Error::InvalidConfig(format!(
+                    "Refusing to modify profiles: {} could not be parsed ({}) and was moved to {}. \
+                     Fix it and move it back, or run the command again to start with empty profiles.",
+                    self.config_file.display(),
+                    e,
+                    backup.display()
+                ))
+                .into());
+            }
+        };
+
+        let result = f(&mut data)?;
+        let content = Format::from_path(&self.config_file).serialize(&data)?;
+        if let Some(version) = migrated_from {
+            fs::copy(
+                &self.config_file,
+                config::sibling_path(&self.config_file, &format!(".v{version}.bak")),
+            )?;
+        }
+        config::write_atomically(&self.config_file, &content)?;
+        self.data = data;
+        self.parse_error = None;
+        Ok(result)
+    }
+}
+
+fn per_output_error(name: &str) -> anyhow::Error {
+    anyhow!(
+        "Profile '{}' has per-output entries and cannot be used as a single list of specifications",
+        name
+    )
+}
+
+fn copy_profile_data(
+    data: &mut ProfilesData,
+    fallback: Option<Profile>,
+    from: &str,
+    to: &str,
+    force: bool,
+) -> Result<()> {
+    let profile = data
+        .profiles
+        .get(from)
+        .cloned()
+        .or(fallback)
+        .ok_or_else(|| Error::ProfileNotFound(format!("Profile '{}' not found", from)))?;
+
+    if !force && from != to && data.profiles.contains_key(to) {
+        return Err(anyhow!(
+            "Profile '{}' already exists (use --force to overwrite)",
+            to
+        ));
+    }
+
+    data.profiles.insert(to.to_string(), profile);
+    Ok(())
+}
+
+// The system layer is usually not writable, so a broken or outdated file is
+// only interpreted in memory and skipped with a warning if unusable
+fn load_system_profiles(system_file: &Path) -> Option<ProfilesData> {
+    let loaded = fs::read_to_string(system_file)
+        .map_err(Into::into)
+        .and_then(|content| Format::from_path(system_file).parse(&content))
+        .and_then(config::migrate)
+        .and_then(|(value, _)| Ok(serde_json::from_value::<ProfilesData>(value)?));
+
+    match loaded {
+        Ok(data) => Some(data),
+        Err(e) => {
+            eprintln!(
+                "Warning: Ignoring system profiles file {}: {}",
+                system_file.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+// Loads the profiles file, upgrading one written by an older version in
+// memory only; the version it was upgraded from is returned alongside.
+// The inner error is a file that is not valid JSON or TOML, or not shaped
+// like a profiles file
+fn load_profiles(config_file: &Path) -> Result<Result<(ProfilesData, Option<u32>)>> {
+    if !config_file.exists() {
+        return Ok(Ok((ProfilesData::default(), None)));
+    }
+
+    let content = fs::read_to_string(config_file)?;
+    let format = Format::from_path(config_file);
+    // A file this version cannot migrate, e.g. one written by a newer
+    // display-switch, is an error but is left alone
+    Ok(match format.parse(&content) {
+        Ok(value) => {
+            let (value, migrated_from) = config::migrate(value)?;
+            serde_json::from_value::<ProfilesData>(value)
+                .map(|data| (data, migrated_from))
+                .map_err(anyhow::Error::from)
+        }
+        Err(e) => Err(e),
+    })
+}
+
+// Levenshtein distance over characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::display::MatchPolicy;
+    use crate::edid::MonitorIdentity;
     use std::env;
 
     fn create_test_profile_manager() -> Result<ProfileManager> {
@@ -115,12 +1206,20 @@ mod tests {
 
         Ok(ProfileManager {
             config_file: temp_path,
-            data: ProfilesData {
-                profiles: HashMap::new(),
-            },
+            data: ProfilesData::default(),
+            parse_error: None,
+            system: None,
+            environment: Environment::default(),
         })
     }
 
+    // For tests that exercise the on-disk read-modify-write path
+    fn create_file_profile_manager() -> Result<(tempfile::TempDir, ProfileManager)> {
+        let dir = tempfile::tempdir()?;
+        let manager = ProfileManager::open(dir.path().join("profiles.json"))?;
+        Ok((dir, manager))
+    }
+
     #[test]
     fn test_create_and_get_profile() -> Result<()> {
         let mut manager = create_test_profile_manager()?;
@@ -133,11 +1232,15 @@ mod tests {
         }];
 
         // Only test the in-memory operations, not file I/O
-        manager
-            .data
-            .profiles
-            .insert("test".to_string(), specs.clone());
-        let retrieved_specs = manager.get_profile("test")?;
+        manager.data.profiles.insert(
+            "test".to_string(),
+            Profile::single(vec![StoredSpec::new("1080p@60hz")?]),
+        );
+        let retrieved_specs: Vec<DisplaySpec> = manager
+            .get_profile("test")?
+            .iter()
+            .map(|spec| spec.parse(&Variables::default()))
+            .collect::<Result<_>>()?;
 
         assert_eq!(specs, retrieved_specs);
         Ok(())
@@ -147,36 +1250,25 @@ mod tests {
     fn test_list_profiles() -> Result<()> {
         let mut manager = create_test_profile_manager()?;
 
-        let specs1 = vec![DisplaySpec {
-            width: Some(1920),
-            height: Some(1080),
-            refresh_rate: Some(60.0),
-            aspect_ratio: None,
-        }];
-
-        let specs2 = vec![DisplaySpec {
-            width: Some(2560),
-            height: Some(1440),
-            refresh_rate: Some(144.0),
-            aspect_ratio: None,
-        }];
+        let specs1 = vec![StoredSpec::new("1920x1080@60hz")?];
+        let specs2 = vec![StoredSpec::new("2560x1440@144hz")?];
 
         // Only test the in-memory operations, not file I/O
         manager
             .data
             .profiles
-            .insert("profile1".to_string(), specs1.clone());
+            .insert("profile1".to_string(), Profile::single(specs1));
         manager
             .data
             .profiles
-            .insert("profile2".to_string(), specs2.clone());
+            .insert("profile2".to_string(), Profile::single(specs2));
 
-        let profiles = manager.list_profiles()?;
+        let profiles = manager.list_profiles(false)?;
         assert_eq!(profiles.len(), 2);
 
         // Should be sorted alphabetically
-        assert_eq!(profiles[0].0, "profile1");
-        assert_eq!(profiles[1].0, "profile2");
+        assert_eq!(profiles[0].name, "profile1");
+        assert_eq!(profiles[1].name, "profile2");
 
         Ok(())
     }
@@ -185,15 +1277,13 @@ mod tests {
     fn test_delete_profile() -> Result<()> {
         let mut manager = create_test_profile_manager()?;
 
-        let specs = vec![DisplaySpec {
-            width: Some(1920),
-            height: Some(1080),
-            refresh_rate: Some(60.0),
-            aspect_ratio: None,
-        }];
+        let specs = vec![StoredSpec::new("1920x1080@60hz")?];
 
         // Only test the in-memory operations, not file I/O
-        manager.data.profiles.insert("test".to_string(), specs);
+        manager
+            .data
+            .profiles
+            .insert("test".to_string(), Profile::single(specs));
         assert!(manager.profile_exists("test"));
 
         manager.data.profiles.remove("test");
@@ -201,4 +1291,646 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_create_profile_requires_force_to_overwrite() -> Result<()> {
+        let (_dir, mut manager) = create_file_profile_manager()?;
+        manager.create_profile("tv".to_string(), &["4k@60hz".to_string()], false)?;
+
+        let specs = vec!["1080p@120hz".to_string()];
+        assert!(manager
+            .create_profile("tv".to_string(), &specs, false)
+            .is_err());
+        assert_eq!(manager.get_stored_specs("tv")?[0].as_str(), "4k@60hz");
+
+        manager.create_profile("tv".to_string(), &specs, true)?;
+        assert_eq!(manager.get_stored_specs("tv")?[0].as_str(), "1080p@120hz");
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_and_copy_profile() -> Result<()> {
+        let (_dir, mut manager) = create_file_profile_manager()?;
+        manager.create_profile("tv".to_string(), &["4k@60hz".to_string()], false)?;
+        manager.create_profile("desk".to_string(), &["1440p@144hz".to_string()], false)?;
+
+        manager.copy_profile("tv", "tv-backup", false)?;
+        assert!(manager.profile_exists("tv"));
+        assert_eq!(
+            manager.get_stored_specs("tv-backup")?[0].as_str(),
+            "4k@60hz"
+        );
+
+        assert!(manager.rename_profile("tv", "desk", false).is_err());
+        manager.rename_profile("tv", "living-room", false)?;
+        assert!(!manager.profile_exists("tv"));
+        assert!(manager.profile_exists("living-room"));
+
+        assert!(manager.rename_profile("missing", "other", false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_profiles_are_written_in_name_order() -> Result<()> {
+        let (_dir, mut manager) = create_file_profile_manager()?;
+        for name in ["tv", "desk", "couch", "beamer"] {
+            manager.create_profile(name.to_string(), &["1080p".to_string()], false)?;
+        }
+
+        let written = fs::read_to_string(&manager.config_file)?;
+        let positions: Vec<usize> = ["beamer", "couch", "desk", "tv"]
+            .iter()
+            .map(|name| written.find(&format!("\"{name}\"")).unwrap())
+            .collect();
+        assert!(positions.is_sorted(), "{written}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_updates_merge_with_concurrent_writers() -> Result<()> {
+        let (_dir, mut first) = create_file_profile_manager()?;
+        let mut second = ProfileManager::open(first.config_file.clone())?;
+
+        first.create_profile("tv".to_string(), &["4k@60hz".to_string()], false)?;
+        second.create_profile("desk".to_string(), &["1440p".to_string()], false)?;
+
+        let reloaded = ProfileManager::open(first.config_file.clone())?;
+        assert!(reloaded.profile_exists("tv"));
+        assert!(reloaded.profile_exists("desk"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_profiles_are_migrated_with_backup() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_file = dir.path().join("profiles.json");
+        let legacy = r#"{"profiles":{"tv":[{"width":3840,"height":2160,"refresh_rate":60.0,"aspect_ratio":null}]}}"#;
+        fs::write(&config_file, legacy)?;
+
+        // Reading upgrades the profiles in memory only
+        let mut manager = ProfileManager::open(config_file.clone())?;
+        assert_eq!(
+            manager.get_stored_specs("tv")?[0].as_str(),
+            "3840x2160@60hz"
+        );
+        let backup = dir.path().join("profiles.json.v1.bak");
+        assert_eq!(fs::read_to_string(&config_file)?, legacy);
+        assert!(!backup.exists());
+
+        // The first change writes the upgrade, keeping the original
+        manager.create_profile("desk".to_string(), &["1440p".to_string()], false)?;
+        assert_eq!(fs::read_to_string(&backup)?, legacy);
+
+        let migrated: serde_json::Value = serde_json::from_str(&fs::read_to_string(&config_file)?)?;
+        assert_eq!(migrated["version"], CURRENT_VERSION);
+        assert_eq!(migrated["profiles"]["tv"][0], "3840x2160@60hz");
+        Ok(())
+    }
+
+    #[test]
+    fn test_profiles_from_a_newer_version_are_left_alone() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_file = dir.path().join("profiles.json");
+        let content = r#"{"version":3,"profiles":{"tv":["4k@60hz"]}}"#;
+        fs::write(&config_file, content)?;
+
+        let error = ProfileManager::open(config_file.clone()).err().unwrap();
+        assert!(matches!(
+            Error::find(&error),
+            Some(Error::InvalidConfig(message)) if message.contains("please upgrade")
+        ));
+        assert_eq!(fs::read_to_string(&config_file)?, content);
+        for entry in fs::read_dir(dir.path())? {
+            assert!(!entry?.file_name().to_string_lossy().contains("corrupt"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_profiles() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_file = dir.path().join("profiles.toml");
+        fs::write(
+            &config_file,
+            "version = 2\n\n[profiles]\ntv = [\"4k@60hz\"]\n",
+        )?;
+
+        let mut manager = ProfileManager::open(config_file.clone())?;
+        assert_eq!(manager.get_stored_specs("tv")?[0].as_str(), "4k@60hz");
+
+        manager.create_profile("desk".to_string(), &["1440p@144hz".to_string()], false)?;
+        let written = fs::read_to_string(&config_file)?;
+        assert!(written.contains("desk = [\"1440p@144hz\"]"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_profiles_are_moved_aside() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_file = dir.path().join("profiles.json");
+        fs::write(&config_file, "{ not json")?;
+
+        // Reading leaves the broken file where it is, and profiles in it are
+        // unreadable rather than missing
+        let mut manager = ProfileManager::open(config_file.clone())?;
+        for error in [
+            manager.lookup_profile("tv").err().unwrap(),
+            manager.resolved_profile("tv").err().unwrap(),
+            manager.list_profiles(false).err().unwrap(),
+        ] {
+            assert!(matches!(
+                Error::find(&error),
+                Some(Error::InvalidConfig(message))
+                    if message.contains(&config_file.display().to_string())
+            ));
+        }
+        assert_eq!(fs::read_to_string(&config_file)?, "{ not json");
+
+        // Mutations must fail rather than silently replace the broken file
+        assert!(manager
+            .create_profile("tv".to_string(), &["4k".to_string()], false)
+            .is_err());
+        assert!(!config_file.exists());
+        let backup = fs::read_dir(dir.path())?
+            .map(|entry| entry.unwrap().path())
+            .find(|path| {
+                path.file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .starts_with("profiles.json.corrupt-")
+            })
+            .unwrap();
+        assert_eq!(fs::read_to_string(&backup)?, "{ not json");
+
+        manager.create_profile("tv".to_string(), &["4k".to_string()], false)?;
+        assert!(config_file.exists());
+        assert!(manager.profile_exists("tv"));
+        assert_eq!(manager.list_profiles(false)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_user_profiles_override_system_profiles() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let system_file = dir.path().join("system.toml");
+        fs::write(
+            &system_file,
+            "version = 2\n\n[profiles]\ntv = [\"4k@60hz\"]\ndesk = [\"1440p@144hz\"]\n",
+        )?;
+
+        let mut manager = ProfileManager::open(dir.path().join("user.json"))?;
+        manager.system = load_system_profiles(&system_file).map(|data| (system_file, data));
+        manager.create_profile("tv".to_string(), &["1080p@120hz".to_string()], false)?;
+
+        let profiles = manager.list_profiles(false)?;
+        let layers: Vec<_> = profiles
+            .iter()
+            .map(|p| (p.name.as_str(), p.layer))
+            .collect();
+        assert_eq!(layers, [("desk", Layer::System), ("tv", Layer::User)]);
+        assert_eq!(manager.get_stored_specs("tv")?[0].as_str(), "1080p@120hz");
+        assert_eq!(manager.get_stored_specs("desk")?[0].as_str(), "1440p@144hz");
+
+        // System profiles are read-only but can be copied into the user layer
+        assert!(manager.delete_profile("desk").is_err());
+        assert!(manager.rename_profile("desk", "office", false).is_err());
+        manager.copy_profile("desk", "office", false)?;
+        assert_eq!(manager.profile_layer("office"), Some(Layer::User));
+
+        // Deleting the user override reveals the system profile again
+        manager.delete_profile("tv")?;
+        assert_eq!(manager.profile_layer("tv"), Some(Layer::System));
+        assert_eq!(manager.get_stored_specs("tv")?[0].as_str(), "4k@60hz");
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings_are_layered_and_persisted() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let system_file = dir.path().join("system.toml");
+        fs::write(
+            &system_file,
+            "version = 2\n\n[settings]\npolicy = \"nearest\"\nexact = true\n\n[profiles]\n",
+        )?;
+
+        let config_file = dir.path().join("user.toml");
+        let mut manager = ProfileManager::open(config_file.clone())?;
+        manager.system = load_system_profiles(&system_file).map(|data| (system_file, data));
+        manager.update_settings(|settings| settings.set("exact", "false"))?;
+
+        let settings = manager.settings();
+        assert_eq!(settings.match_options().policy, MatchPolicy::Nearest);
+        assert!(!settings.match_options().exact);
+        assert_eq!(settings.match_options().refresh_tolerance, 0.1);
+
+        let written = fs::read_to_string(&config_file)?;
+        assert!(written.contains("[settings]\nexact = false"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_per_output_profiles() -> Result<()> {
+        let (_dir, mut manager) = create_file_profile_manager()?;
+        let profile = Profile {
+            outputs: vec![
+                OutputEntry::new(
+                    Some("HDMI-1".to_string()),
+                    &["4k@60hz".to_string(), "1080p@60hz".to_string()],
+                    false,
+                )?,
+                OutputEntry::new(Some("DP-1".to_string()), &["1440p@144hz".to_string()], true)?,
+            ],
+            ..Default::default()
+        };
+        manager.save_profile("both".to_string(), profile.clone(), false)?;
+        manager.create_profile("tv".to_string(), &["4k@60hz".to_string()], false)?;
+
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&manager.config_file)?)?;
+        assert_eq!(written["profiles"]["tv"], serde_json::json!(["4k@60hz"]));
+        assert_eq!(
+            written["profiles"]["both"]["outputs"][1],
+            serde_json::json!({"output": "DP-1", "specs": ["1440p@144hz"], "optional": true})
+        );
+
+        let reloaded = ProfileManager::open(manager.config_file.clone())?;
+        assert_eq!(reloaded.profile("both")?, profile);
+        assert!(reloaded.get_stored_specs("both").is_err());
+
+        let duplicate = Profile {
+            outputs: vec![profile.outputs[0].clone(), profile.outputs[0].clone()],
+            ..Default::default()
+        };
+        assert!(manager
+            .save_profile("dup".to_string(), duplicate, false)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_entry_matches_monitor_before_connector() -> Result<()> {
+        let outputs = crate::platform::mock::default_outputs();
+        let connected: Vec<OutputInfo> = outputs
+            .into_iter()
+            .map(|o| OutputInfo {
+                name: o.name,
+                primary: o.primary,
+                current_mode: Some(o.current),
+                identity: o.identity,
+            })
+            .collect();
+        let entry = |monitor: Option<&str>, output: Option<&str>| OutputEntry {
+            monitor: monitor.map(String::from),
+            output: output.map(String::from),
+            ..OutputEntry::new(None, &["1080p".to_string()], false).unwrap()
+        };
+
+        let found = |e: OutputEntry| e.find_output(&connected).map(|o| o.name.clone());
+        assert_eq!(
+            found(entry(Some("mck:0002"), None)).as_deref(),
+            Some("MOCK-2")
+        );
+        assert_eq!(
+            found(entry(Some("MCK:0002:M0002"), Some("MOCK-1"))).as_deref(),
+            Some("MOCK-2")
+        );
+        assert_eq!(
+            found(entry(Some("DEL:A0B2"), Some("MOCK-1"))).as_deref(),
+            Some("MOCK-1")
+        );
+        assert_eq!(found(entry(Some("DEL:A0B2"), None)), None);
+
+        let json = r#"{"outputs":[{"monitor":"MCK:0001","specs":["4k"]}]}"#;
+        let profile: Profile = serde_json::from_str(json)?;
+        assert!(profile.default_output_specs().is_none());
+        assert_eq!(serde_json::to_string(&profile)?, json);
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_profile_by_connected_outputs() -> Result<()> {
+        // Recorded from a laptop panel (no EDID) docked to two desk monitors
+        let output = |name: &str, identity: Option<&str>| OutputInfo {
+            name: name.to_string(),
+            primary: false,
+            current_mode: None,
+            identity: identity.map(|id| {
+                let (manufacturer, rest) = id.split_once(':').unwrap();
+                let (product, serial) = rest.split_once(':').unwrap();
+                MonitorIdentity {
+                    manufacturer: manufacturer.to_string(),
+                    product: u16::from_str_radix(product, 16).unwrap(),
+                    serial: Some(serial.to_string()),
+                    name: None,
+                    edid_hash: None,
+                }
+            }),
+        };
+        let laptop = vec![output("eDP-1", None)];
+        let docked = vec![
+            output("eDP-1", None),
+            output("DP-1", Some("DEL:A0B2:CFV9N13")),
+            output("DP-2", Some("DEL:A0B2:7XK2L01")),
+        ];
+        let office = vec![output("eDP-1", None), output("HDMI-1", Some("SAM:0F35:1"))];
+
+        let (_dir, mut manager) = create_file_profile_manager()?;
+        let mut save = |name: &str, outputs: &[&str], mode| {
+            let profile = Profile {
+                match_rule: Some(MatchRule {
+                    outputs: outputs.iter().map(|o| o.to_string()).collect(),
+                    mode,
+                }),
+                ..Profile::single(vec![StoredSpec::new("1080p").unwrap()])
+            };
+            manager.save_profile(name.to_string(), profile, false)
+        };
+        save("mobile", &["eDP-1"], MatchMode::Exact)?;
+        save(
+            "desk",
+            &["eDP-1", "DEL:A0B2:CFV9N13", "DEL:A0B2"],
+            MatchMode::Exact,
+        )?;
+        save("any-dell", &["DEL:A0B2"], MatchMode::Subset)?;
+        save("external", &["DP-1", "HDMI-1"], MatchMode::AnyOf)?;
+
+        let selected = |connected: &[OutputInfo]| {
+            manager
+                .select_profile(connected)
+                .unwrap()
+                .map(|(entry, mode)| (entry.name, mode))
+        };
+        assert_eq!(selected(&laptop), Some(("mobile".into(), MatchMode::Exact)));
+        assert_eq!(selected(&docked), Some(("desk".into(), MatchMode::Exact)));
+        assert_eq!(
+            selected(&office),
+            Some(("external".into(), MatchMode::AnyOf))
+        );
+        assert_eq!(
+            selected(&docked[1..2]),
+            Some(("any-dell".into(), MatchMode::Subset))
+        );
+        assert_eq!(selected(&[output("DP-3", None)]), None);
+
+        // Recorded fingerprints use identities where known
+        let fingerprint = MatchRule::fingerprint(&docked);
+        assert_eq!(
+            fingerprint.outputs,
+            ["eDP-1", "DEL:A0B2:CFV9N13", "DEL:A0B2:7XK2L01"]
+        );
+        assert_eq!(fingerprint.score(&docked), Some(3));
+        assert_eq!(fingerprint.score(&docked[..2]), None);
+
+        let written = fs::read_to_string(&manager.config_file)?;
+        assert!(written.contains(r#""match": {"#));
+        assert_eq!(
+            ProfileManager::open(manager.config_file.clone())?
+                .profile("desk")?
+                .match_rule
+                .unwrap()
+                .mode,
+            MatchMode::Exact
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_inheritance() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_file = dir.path().join("profiles.json");
+        fs::write(
+            &config_file,
+            r#"{"version": 2, "profiles": {
+                "base": {"outputs": [{"specs": ["4k@60hz"]}], "match": {"outputs": ["DP-1"]}},
+                "left": {"extends": "base", "outputs": [{"output": "HDMI-1", "specs": ["1080p@60hz"]}]},
+                "right": {"extends": "base", "outputs": [{"specs": ["4k@120hz"]}]},
+                "diamond": {"includes": ["left", "right"]},
+                "child": {"extends": "diamond", "outputs": [{"output": "HDMI-1", "specs": ["720p"]}]},
+                "a": {"extends": "b"},
+                "b": {"includes": ["c"]},
+                "c": {"extends": "a"},
+                "orphan": {"extends": "missing"}
+            }}"#,
+        )?;
+        let manager = ProfileManager::open(config_file.clone())?;
+        let specs = |profile: &Profile| -> Vec<(Option<String>, String)> {
+            profile
+                .outputs
+                .iter()
+                .map(|e| (e.output.clone(), e.specs[0].to_string()))
+                .collect()
+        };
+
+        // Own entries override inherited ones; the match block is inherited
+        let left = manager.resolved_profile("left")?;
+        assert_eq!(
+            specs(&left),
+            [
+                (None, "4k@60hz".to_string()),
+                (Some("HDMI-1".to_string()), "1080p@60hz".to_string())
+            ]
+        );
+        assert_eq!(left.match_rule.unwrap().outputs, ["DP-1"]);
+        assert_eq!(manager.get_profile("right")?[0].as_str(), "4k@120hz");
+
+        // Both sides of the diamond reach "base"; the later include wins
+        let diamond = manager.resolved_profile("diamond")?;
+        assert_eq!(
+            specs(&diamond),
+            [
+                (None, "4k@120hz".to_string()),
+                (Some("HDMI-1".to_string()), "1080p@60hz".to_string())
+            ]
+        );
+        assert!(diamond.match_rule.is_none());
+
+        let child = manager.resolved_profile("child")?;
+        assert_eq!(
+            specs(&child)[1],
+            (Some("HDMI-1".to_string()), "720p".to_string())
+        );
+        assert!(!child.inherits());
+
+        let cycle = manager.resolved_profile("a").unwrap_err().to_string();
+        assert!(cycle.contains("a -> b -> c -> a"), "{cycle}");
+        let orphan = manager.resolved_profile("orphan").unwrap_err().to_string();
+        assert!(orphan.contains("unknown profile 'missing'"), "{orphan}");
+
+        // Stored profiles keep their references
+        assert_eq!(
+            manager.profile("child")?.extends.as_deref(),
+            Some("diamond")
+        );
+        let written = serde_json::to_value(manager.profile("diamond")?)?;
+        assert_eq!(written, serde_json::json!({"includes": ["left", "right"]}));
+        Ok(())
+    }
+
+    #[test]
+    fn test_when_conditions() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_file = dir.path().join("profiles.json");
+        fs::write(
+            &config_file,
+            r#"{"version": 2, "profiles": {
+                "htpc": {"when": {"hostname": "htpc"}, "outputs": [{"specs": ["4k@60hz"]}]},
+                "laptop": {"when": {"hostname": "laptop"}, "outputs": [{"specs": ["1080p"]}]},
+                "docked": {"outputs": [
+                    {"specs": ["1080p"]},
+                    {"specs": ["1440p"], "when": {"env": {"DOCKED": "1"}}},
+                    {"output": "HDMI-1", "specs": ["720p"], "when": {"session_type": "wayland"}}
+                ]}
+            }}"#,
+        )?;
+        let mut manager = ProfileManager::open(config_file)?;
+        manager.environment = Environment {
+            hostname: Some("htpc".to_string()),
+            session_type: Some("x11".to_string()),
+            vars: HashMap::from([("DOCKED".to_string(), "1".to_string())]),
+            ..Default::default()
+        };
+
+        let names = |all| -> Result<Vec<String>> {
+            Ok(manager
+                .list_profiles(all)?
+                .into_iter()
+                .map(|e| e.name)
+                .collect())
+        };
+        assert_eq!(names(false)?, ["docked", "htpc"]);
+        assert_eq!(names(true)?, ["docked", "htpc", "laptop"]);
+
+        let error = manager.resolved_profile("laptop").unwrap_err().to_string();
+        assert!(error.contains("does not apply here"), "{error}");
+
+        // The later, matching entry replaces the unconditional one; the
+        // Wayland-only entry is dropped
+        assert_eq!(manager.get_profile("docked")?[0].as_str(), "1440p");
+        assert_eq!(manager.resolved_profile("docked")?.outputs.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_profile() -> Result<()> {
+        let mut manager = create_test_profile_manager()?;
+        for name in ["Living room", "Streaming", "Streaming 720p", "tv"] {
+            manager.data.profiles.insert(
+                name.to_string(),
+                Profile::single(vec![StoredSpec::new("1080p")?]),
+            );
+        }
+
+        assert_eq!(
+            manager.lookup_profile("tv")?,
+            ProfileLookup::Exact("tv".into())
+        );
+        assert_eq!(
+            manager.lookup_profile("TV")?,
+            ProfileLookup::Fuzzy("tv".into())
+        );
+        assert_eq!(
+            manager.lookup_profile("living")?,
+            ProfileLookup::Fuzzy("Living room".into())
+        );
+        // A case-insensitive match beats a longer name with the same prefix
+        assert_eq!(
+            manager.lookup_profile("streaming")?,
+            ProfileLookup::Fuzzy("Streaming".into())
+        );
+        assert_eq!(
+            manager.lookup_profile("str")?,
+            ProfileLookup::Ambiguous(vec!["Streaming".into(), "Streaming 720p".into()])
+        );
+        assert_eq!(
+            manager.lookup_profile("stremaing")?,
+            ProfileLookup::NotFound(vec!["Streaming".into()])
+        );
+        assert_eq!(
+            manager.lookup_profile("tvv")?,
+            ProfileLookup::NotFound(vec!["tv".into()])
+        );
+        assert_eq!(
+            manager.lookup_profile("desk")?,
+            ProfileLookup::NotFound(Vec::new())
+        );
+
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "tv"), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_and_import_profiles() -> Result<()> {
+        let (_dir, mut source) = create_file_profile_manager()?;
+        source.create_profile("base".to_string(), &["4k@60hz".to_string()], false)?;
+        let child = Profile {
+            extends: Some("base".to_string()),
+            ..Default::default()
+        };
+        source.save_profile("child".to_string(), child, false)?;
+
+        let (profiles, referenced) = source.export_profiles(&["child".to_string()])?;
+        assert_eq!(profiles.keys().collect::<Vec<_>>(), ["base", "child"]);
+        assert_eq!(referenced, ["base"]);
+
+        let (_dir, mut target) = create_file_profile_manager()?;
+        target.create_profile("base".to_string(), &["720p".to_string()], false)?;
+
+        // Conflicts abort the whole import
+        let error = target
+            .import_profiles(profiles.clone(), ConflictMode::Fail)
+            .unwrap_err();
+        assert!(error.to_string().contains("'base'"), "{error}");
+        assert!(!target.profile_exists("child"));
+
+        // Renamed profiles keep their references within the bundle
+        let outcomes = target.import_profiles(profiles.clone(), ConflictMode::Rename)?;
+        assert_eq!(
+            outcomes,
+            [
+                ImportOutcome::Renamed {
+                    from: "base".to_string(),
+                    to: "base-2".to_string()
+                },
+                ImportOutcome::Added("child".to_string()),
+            ]
+        );
+        assert_eq!(target.profile("child")?.extends.as_deref(), Some("base-2"));
+        assert_eq!(target.get_profile("child")?[0].as_str(), "4k@60hz");
+
+        let outcomes = target.import_profiles(profiles.clone(), ConflictMode::Skip)?;
+        assert_eq!(outcomes[0], ImportOutcome::Skipped("base".to_string()));
+        assert_eq!(target.get_stored_specs("base")?[0].as_str(), "720p");
+        target.import_profiles(profiles, ConflictMode::Overwrite)?;
+        assert_eq!(target.get_stored_specs("base")?[0].as_str(), "4k@60hz");
+
+        // Specs are validated even though the bundle parsed
+        let invalid: BTreeMap<String, Profile> =
+            serde_json::from_str(r#"{"new": ["4k@60hz"], "bad": ["not-a-spec"]}"#)?;
+        let error = target
+            .import_profiles(invalid, ConflictMode::Fail)
+            .unwrap_err();
+        assert!(error.to_string().contains("not-a-spec"), "{error}");
+        assert!(!target.profile_exists("new"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stored_spec_serialization() -> Result<()> {
+        let json = r#"{"profiles":{"tv":["4k@60hz",{"width":1920,"height":1080,"refresh_rate":60.0,"aspect_ratio":null}]}}"#;
+        let data: ProfilesData = serde_json::from_str(json)?;
+
+        let specs = data.profiles["tv"].default_output_specs().unwrap();
+        assert_eq!(specs[0].as_str(), "4k@60hz");
+        assert_eq!(specs[1].as_str(), "1920x1080@60hz");
+
+        let written = serde_json::to_string(&data)?;
+        assert_eq!(
+            written,
+            r#"{"version":2,"profiles":{"tv":["4k@60hz","1920x1080@60hz"]}}"#
+        );
+
+        assert!(StoredSpec::new("not-a-spec").is_err());
+        Ok(())
+    }
 }
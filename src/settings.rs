@@ -0,0 +1,350 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::audit::{AuditLog, DEFAULT_AUDIT_MAX_SIZE};
+use crate::display::{MatchOptions, MatchPolicy, DEFAULT_REFRESH_TOLERANCE};
+use crate::hooks::{Hooks, DEFAULT_HOOK_TIMEOUT};
+use crate::platform::BackendKind;
+
+/// Keys accepted by `config get/set/unset`, in the order `config list` shows them
+pub const KEYS: [&str; 13] = [
+    "exact",
+    "policy",
+    "refresh_tolerance",
+    "output",
+    "format",
+    "confirm_timeout",
+    "backend",
+    "pre_switch",
+    "post_switch",
+    "on_failure",
+    "hook_timeout",
+    "audit_log",
+    "audit_max_size",
+];
+
+/// How results are printed by commands that support JSON
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Defaults for behaviour that can also be chosen per invocation. Every field
+/// is optional so layers (system file, user file, command line) can be
+/// stacked with `overlay`, the last layer to set a field winning.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    /// Require an exact mode match instead of the closest one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exact: Option<bool>,
+    /// How to pick a refresh rate when the requested one is unavailable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<MatchPolicy>,
+    /// Refresh rates closer than this many Hz are treated as equal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_tolerance: Option<f64>,
+    /// Output to act on instead of the primary one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Default output format for `--list`, `--current` and `profile show`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<OutputFormat>,
+    /// Seconds to wait for confirmation before reverting a switch (0 disables)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm_timeout: Option<u64>,
+    /// Display backend to drive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<BackendKind>,
+    /// Command run before every switch; a failure aborts the switch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_switch: Option<String>,
+    /// Command run after every switch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_switch: Option<String>,
+    /// Command run when a switch fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_failure: Option<String>,
+    /// Seconds a hook may run before it is killed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hook_timeout: Option<u64>,
+    /// Audit log file instead of `audit.jsonl` next to the profiles file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_log: Option<PathBuf>,
+    /// Size in bytes at which the audit log is rotated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_max_size: Option<u64>,
+}
+
+impl Settings {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns `self` with every field set in `other` replaced by `other`'s value
+    pub fn overlay(mut self, other: &Settings) -> Self {
+        macro_rules! overlay {
+            ($($field:ident),*) => {
+                $(if other.$field.is_some() {
+                    self.$field = other.$field.clone();
+                })*
+            };
+        }
+        overlay!(
+            exact,
+            policy,
+            refresh_tolerance,
+            output,
+            format,
+            confirm_timeout,
+            backend,
+            pre_switch,
+            post_switch,
+            on_failure,
+            hook_timeout,
+            audit_log,
+            audit_max_size
+        );
+        self
+    }
+
+    pub fn match_options(&self) -> MatchOptions {
+        MatchOptions {
+            exact: self.exact.unwrap_or(false),
+            policy: self.policy.unwrap_or_default(),
+            refresh_tolerance: self.refresh_tolerance.unwrap_or(DEFAULT_REFRESH_TOLERANCE),
+        }
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format.unwrap_or_default()
+    }
+
+    pub fn confirm_timeout(&self) -> u64 {
+        self.confirm_timeout.unwrap_or(0)
+    }
+
+    pub fn backend(&self) -> BackendKind {
+        self.backend.unwrap_or_default()
+    }
+
+    /// The global hooks, run before any hooks of the profile being applied
+    pub fn hooks(&self) -> Hooks {
+        Hooks {
+            pre_switch: self.pre_switch.clone(),
+            post_switch: self.post_switch.clone(),
+            on_failure: self.on_failure.clone(),
+            timeout: self.hook_timeout,
+        }
+    }
+
+    /// The audit log, `audit.jsonl` next to `config_file` unless set otherwise
+    pub fn audit_log(&self, config_file: &Path) -> AuditLog {
+        AuditLog::new(
+            self.audit_log
+                .clone()
+                .unwrap_or_else(|| AuditLog::default_path(config_file)),
+            self.audit_max_size.unwrap_or(DEFAULT_AUDIT_MAX_SIZE),
+        )
+    }
+
+    /// The value of `key` as `config get` prints it, or `None` if unset
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(match check_key(key)? {
+            "exact" => self.exact.map(|v| v.to_string()),
+            "policy" => self.policy.map(value_name),
+            "refresh_tolerance" => self.refresh_tolerance.map(|v| v.to_string()),
+            "output" => self.output.clone(),
+            "format" => self.format.map(value_name),
+            "confirm_timeout" => self.confirm_timeout.map(|v| v.to_string()),
+            "backend" => self.backend.map(value_name),
+            "pre_switch" => self.pre_switch.clone(),
+            "post_switch" => self.post_switch.clone(),
+            "on_failure" => self.on_failure.clone(),
+            "hook_timeout" => self.hook_timeout.map(|v| v.to_string()),
+            "audit_log" => self.audit_log.as_ref().map(|v| v.display().to_string()),
+            "audit_max_size" => self.audit_max_size.map(|v| v.to_string()),
+            _ => unreachable!(),
+        })
+    }
+
+    /// Parses `value` for `key` and stores it
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let invalid = |expected: &str| {
+            anyhow!(
+                "Invalid value '{}' for {}: expected {}",
+                value,
+                key,
+                expected
+            )
+        };
+
+        match check_key(key)? {
+            "exact" => self.exact = Some(value.parse().map_err(|_| invalid("true or false"))?),
+            "policy" => self.policy = Some(parse_enum(value).map_err(|e| invalid(&e))?),
+            "refresh_tolerance" => {
+                let tolerance: f64 = value
+                    .parse()
+                    .ok()
+                    .filter(|t: &f64| t.is_finite() && *t >= 0.0)
+                    .ok_or_else(|| invalid("a non-negative number of Hz"))?;
+                self.refresh_tolerance = Some(tolerance);
+            }
+            "output" => self.output = Some(value.to_string()),
+            "format" => self.format = Some(parse_enum(value).map_err(|e| invalid(&e))?),
+            "confirm_timeout" => {
+                self.confirm_timeout =
+                    Some(value.parse().map_err(|_| invalid("a number of seconds"))?)
+            }
+            "backend" => self.backend = Some(parse_backend(value).map_err(|e| invalid(&e))?),
+            "pre_switch" => self.pre_switch = Some(value.to_string()),
+            "post_switch" => self.post_switch = Some(value.to_string()),
+            "on_failure" => self.on_failure = Some(value.to_string()),
+            "hook_timeout" => {
+                self.hook_timeout = Some(value.parse().map_err(|_| invalid("a number of seconds"))?)
+            }
+            "audit_log" => self.audit_log = Some(PathBuf::from(value)),
+            "audit_max_size" => {
+                let size: u64 = value
+                    .parse()
+                    .ok()
+                    .filter(|size| *size > 0)
+                    .ok_or_else(|| invalid("a positive number of bytes"))?;
+                self.audit_max_size = Some(size);
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    pub fn unset(&mut self, key: &str) -> Result<()> {
+        match check_key(key)? {
+            "exact" => self.exact = None,
+            "policy" => self.policy = None,
+            "refresh_tolerance" => self.refresh_tolerance = None,
+            "output" => self.output = None,
+            "format" => self.format = None,
+            "confirm_timeout" => self.confirm_timeout = None,
+            "backend" => self.backend = None,
+            "pre_switch" => self.pre_switch = None,
+            "post_switch" => self.post_switch = None,
+            "on_failure" => self.on_failure = None,
+            "hook_timeout" => self.hook_timeout = None,
+            "audit_log" => self.audit_log = None,
+            "audit_max_size" => self.audit_max_size = None,
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Built-in values for every key that has one
+    pub fn defaults() -> Self {
+        Self {
+            exact: Some(false),
+            policy: Some(MatchPolicy::default()),
+            refresh_tolerance: Some(DEFAULT_REFRESH_TOLERANCE),
+            output: None,
+            format: Some(OutputFormat::default()),
+            confirm_timeout: Some(0),
+            backend: Some(BackendKind::default()),
+            pre_switch: None,
+            post_switch: None,
+            on_failure: None,
+            hook_timeout: Some(DEFAULT_HOOK_TIMEOUT),
+            audit_log: None,
+            audit_max_size: Some(DEFAULT_AUDIT_MAX_SIZE),
+        }
+    }
+}
+
+fn check_key(key: &str) -> Result<&'static str> {
+    KEYS.iter().find(|k| **k == key).copied().ok_or_else(|| {
+        anyhow!(
+            "Unknown setting '{}'; valid settings are: {}",
+            key,
+            KEYS.join(", ")
+        )
+    })
+}
+
+fn parse_enum<T: ValueEnum>(value: &str) -> Result<T, String> {
+    T::from_str(value, true).map_err(|_| {
+        let names: Vec<_> = T::value_variants()
+            .iter()
+            .cloned()
+            .map(value_name)
+            .collect();
+        format!("one of {}", names.join(", "))
+    })
+}
+
+// Like `parse_enum`, but explains why `mock` is refused by builds without it
+fn parse_backend(value: &str) -> Result<BackendKind, String> {
+    if !cfg!(any(test, feature = "mock-backend")) && value.eq_ignore_ascii_case("mock") {
+        return Err(
+            "native; the mock backend is only available in builds with `--features mock-backend`"
+                .to_string(),
+        );
+    }
+    parse_enum(value)
+}
+
+fn value_name<T: ValueEnum>(value: T) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_get_and_unset() -> Result<()> {
+        let mut settings = Settings::default();
+        settings.set("exact", "true")?;
+        settings.set("policy", "nearest")?;
+        settings.set("refresh_tolerance", "0.5")?;
+        settings.set("format", "JSON")?;
+
+        assert_eq!(settings.get("exact")?.as_deref(), Some("true"));
+        assert_eq!(settings.get("policy")?.as_deref(), Some("nearest"));
+        assert_eq!(settings.get("format")?.as_deref(), Some("json"));
+        assert_eq!(settings.get("output")?, None);
+
+        let options = settings.match_options();
+        assert!(options.exact);
+        assert_eq!(options.policy, MatchPolicy::Nearest);
+        assert_eq!(options.refresh_tolerance, 0.5);
+
+        settings.unset("exact")?;
+        assert_eq!(settings.get("exact")?, None);
+
+        assert!(settings.set("exact", "maybe").is_err());
+        assert!(settings.set("refresh_tolerance", "-1").is_err());
+        assert!(settings.set("backend", "wayland").is_err());
+        assert!(settings.get("colour").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_overlay() -> Result<()> {
+        let mut system = Settings::default();
+        system.set("policy", "prefer-lower")?;
+        system.set("output", "HDMI-1")?;
+
+        let mut user = Settings::default();
+        user.set("output", "DP-1")?;
+
+        let merged = Settings::defaults().overlay(&system).overlay(&user);
+        assert_eq!(merged.policy, Some(MatchPolicy::PreferLower));
+        assert_eq!(merged.output.as_deref(), Some("DP-1"));
+        assert_eq!(merged.refresh_tolerance, Some(DEFAULT_REFRESH_TOLERANCE));
+        Ok(())
+    }
+}
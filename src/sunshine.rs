@@ -0,0 +1,632 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::display::{DisplayManager, DisplayMode, DisplaySpec, MatchOptions};
+
+/// Display parameters Sunshine exports to prep commands for the connecting client
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientEnv {
+    pub width: u32,
+    pub height: u32,
+    pub fps: Option<f64>,
+    pub hdr: bool,
+}
+
+/// The mode an output was in before `sunshine do` changed it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedState {
+    pub output: Option<String>,
+    pub mode: DisplayMode,
+}
+
+impl ClientEnv {
+    pub fn from_env() -> Result<Self> {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let required = |key: &str| -> Result<u32> {
+            let value = lookup(key).ok_or_else(|| {
+                anyhow!("{key} is not set; run this from a Sunshine prep command")
+            })?;
+            value
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid {key}: {value}"))
+        };
+
+        let width = required("SUNSHINE_CLIENT_WIDTH")?;
+        let height = required("SUNSHINE_CLIENT_HEIGHT")?;
+
+        let fps = match lookup("SUNSHINE_CLIENT_FPS") {
+            Some(value) if !value.trim().is_empty() => Some(
+                value
+                    .trim()
+                    .parse::<f64>()
+                    .with_context(|| format!("Invalid SUNSHINE_CLIENT_FPS: {value}"))?,
+            ),
+            _ => None,
+        };
+
+        let hdr = lookup("SUNSHINE_CLIENT_HDR").is_some_and(|value| {
+            matches!(value.trim().to_lowercase().as_str(), "true" | "1" | "yes")
+        });
+
+        Ok(Self {
+            width,
+            height,
+            fps,
+            hdr,
+        })
+    }
+
+    pub fn to_spec(&self) -> DisplaySpec {
+        DisplaySpec {
+            width: Some(self.width),
+            height: Some(self.height),
+            refresh_rate: self.fps,
+            aspect_ratio: None,
+        }
+    }
+}
+
+/// Which `apps.json` entries `install_prep_commands` should touch
+pub enum AppSelection {
+    All,
+    Named(Vec<String>),
+}
+
+/// The `do`/`undo` pair display-switch adds to an app's `prep-cmd` list
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrepCommand {
+    pub do_cmd: String,
+    pub undo_cmd: String,
+}
+
+/// Sunshine's configuration directory on this platform
+fn default_config_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        let program_files = std::env::var_os("ProgramFiles")?;
+        Some(PathBuf::from(program_files).join("Sunshine").join("config"))
+    } else {
+        Some(dirs::config_dir()?.join("sunshine"))
+    }
+}
+
+/// Location of `sunshine.conf` for the current user on this platform
+pub fn default_config_path() -> Option<PathBuf> {
+    Some(default_config_dir()?.join("sunshine.conf"))
+}
+
+/// Location of Sunshine's `apps.json` for the current user on this platform
+pub fn default_apps_path() -> Option<PathBuf> {
+    Some(default_config_dir()?.join("apps.json"))
+}
+
+/// Reads `output_name` from a `sunshine.conf` file, if it is set
+pub fn read_output_name(path: &Path) -> Result<Option<String>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(parse_output_name(&content))
+}
+
+fn parse_output_name(content: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let (key, value) = line.split_once('=')?;
+        let value = value.trim();
+        (key.trim() == "output_name" && !value.is_empty()).then(|| value.to_string())
+    })
+}
+
+/// Maps Sunshine's `output_name` onto a backend output. Sunshine uses connector
+/// or device names on some platforms and monitor indices on others, so a
+/// numeric name that matches no output is treated as an index.
+pub async fn resolve_output(display_manager: &DisplayManager, name: &str) -> Result<String> {
+    let outputs = display_manager.list_outputs().await?;
+
+    if outputs.iter().any(|output| output.name == name) {
+        return Ok(name.to_string());
+    }
+
+    name.parse::<usize>()
+        .ok()
+        .and_then(|index| outputs.get(index))
+        .map(|output| output.name.clone())
+        .ok_or_else(|| anyhow!("Output '{}' from sunshine.conf not found", name))
+}
+
+/// Where `apply` saves the mode to restore: `sunshine-state.json` next to the
+/// profiles file
+pub fn state_file_beside(config_file: &Path) -> PathBuf {
+    config_file
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join("sunshine-state.json")
+}
+
+/// Saves the current mode of `output` (unless a previous `do` already did) and
+/// applies the first spec in `specs` that can be satisfied.
+pub async fn apply(
+    display_manager: &DisplayManager,
+    output: Option<&str>,
+    specs: &[DisplaySpec],
+    options: &MatchOptions,
+    state_file: &Path,
+) -> Result<(DisplaySpec, DisplayMode)> {
+    // Keep the original state if Sunshine runs `do` again without an `undo`
+    let saved_state = !state_file.exists();
+    if saved_state {
+        let state = SavedState {
+            output: output.map(str::to_string),
+            mode: display_manager.get_current_display_mode(output).await?,
+        };
+        config::write_atomically(state_file, &serde_json::to_string_pretty(&state)?)?;
+    }
+
+    for spec in specs {
+        match display_manager.switch_display(output, spec, options).await {
+            Ok(actual_mode) => return Ok((spec.clone(), actual_mode)),
+            Err(e) => {
+                eprintln!("Failed to switch to {spec}: {e}");
+                continue;
+            }
+        }
+    }
+
+    if saved_state {
+        fs::remove_file(state_file)?;
+    }
+
+    Err(anyhow!(
+        "No suitable display specification could be applied"
+    ))
+}
+
+/// The state `apply` saved, if there is one to restore
+pub fn saved_state(state_file: &Path) -> Result<Option<SavedState>> {
+    if !state_file.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(state_file)?;
+    let state = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", state_file.display()))?;
+    Ok(Some(state))
+}
+
+/// Restores the mode saved by `apply`, returning it, or `None` if nothing was saved.
+pub async fn restore(
+    display_manager: &DisplayManager,
+    state_file: &Path,
+) -> Result<Option<SavedState>> {
+    let Some(state) = saved_state(state_file)? else {
+        return Ok(None);
+    };
+
+    display_manager
+        .set_display_mode(state.output.as_deref(), &state.mode)
+        .await?;
+    fs::remove_file(state_file)?;
+
+    Ok(Some(state))
+}
+
+/// Joins command-line arguments into a single command string, quoting any
+/// argument that contains whitespace or quotes.
+pub fn join_command(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '"') {
+                format!("\"{}\"", arg.replace('"', "\\\""))
+            } else {
+                arg.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// A prep command belongs to display-switch if its program is the display-switch binary
+fn is_display_switch_command(command: &str) -> bool {
+    let command = command.trim_start();
+    let program = match command.strip_prefix('"') {
+        Some(rest) => rest.split('"').next().unwrap_or(""),
+        None => command.split_whitespace().next().unwrap_or(""),
+    };
+
+    let file_name = program.rsplit(['/', '\\']).next().unwrap_or(program);
+    let file_name = file_name.to_lowercase();
+    file_name == "display-switch" || file_name == "display-switch.exe"
+}
+
+fn is_display_switch_entry(entry: &Value) -> bool {
+    ["do", "undo"].iter().any(|key| {
+        entry
+            .get(key)
+            .and_then(Value::as_str)
+            .is_some_and(is_display_switch_command)
+    })
+}
+
+/// Adds, updates (or with `None`, removes) display-switch prep commands in the
+/// selected apps of a parsed `apps.json`. Existing display-switch entries are
+/// updated in place so repeated installs are idempotent. Returns the names of
+/// the apps whose entries changed.
+pub fn install_prep_commands(
+    apps_json: &mut Value,
+    selection: &AppSelection,
+    command: Option<&PrepCommand>,
+) -> Result<Vec<String>> {
+    let apps = apps_json
+        .get_mut("apps")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| anyhow!("apps.json does not contain an \"apps\" list"))?;
+
+    if let AppSelection::Named(names) = selection {
+        let missing: Vec<_> = names
+            .iter()
+            .filter(|name| {
+                !apps
+                    .iter()
+                    .any(|app| app.get("name").and_then(Value::as_str) == Some(name.as_str()))
+            })
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "App(s) not found in apps.json: {}",
+                missing.join(", ")
+            ));
+        }
+    }
+
+    let mut changed = Vec::new();
+
+    for app in apps.iter_mut() {
+        let name = app
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        if let AppSelection::Named(names) = selection {
+            if !names.contains(&name) {
+                continue;
+            }
+        }
+
+        let app = app
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("App '{}' in apps.json is not an object", name))?;
+        let before = app.get("prep-cmd").cloned();
+
+        match command {
+            Some(command) => {
+                let prep_cmds = app
+                    .entry("prep-cmd")
+                    .or_insert_with(|| Value::Array(Vec::new()))
+                    .as_array_mut()
+                    .ok_or_else(|| anyhow!("prep-cmd of app '{}' is not a list", name))?;
+
+                let existing = prep_cmds.iter_mut().find(|e| is_display_switch_entry(e));
+                match existing.and_then(Value::as_object_mut) {
+                    Some(entry) => {
+                        entry.insert("do".to_string(), json!(command.do_cmd));
+                        entry.insert("undo".to_string(), json!(command.undo_cmd));
+                    }
+                    None => prep_cmds.push(json!({
+                        "do": command.do_cmd,
+                        "undo": command.undo_cmd,
+                        "elevated": false,
+                    })),
+                }
+            }
+            None => {
+                if let Some(prep_cmds) = app.get_mut("prep-cmd").and_then(Value::as_array_mut) {
+                    prep_cmds.retain(|e| !is_display_switch_entry(e));
+                }
+            }
+        }
+
+        if app.get("prep-cmd").cloned() != before {
+            changed.push(name);
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Where `update_apps_file` keeps the previous contents of `path`
+pub fn apps_backup_path(path: &Path) -> PathBuf {
+    config::sibling_path(path, ".bak")
+}
+
+/// Applies `install_prep_commands` to an `apps.json` file. The whole document
+/// is written again with its key order, indentation and line endings; string
+/// escapes and arrays or objects written on one line come out in serde_json's
+/// form. The previous file is kept at `apps_backup_path(path)` and both are
+/// replaced atomically.
+pub fn update_apps_file(
+    path: &Path,
+    selection: &AppSelection,
+    command: Option<&PrepCommand>,
+) -> Result<Vec<String>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut apps_json: Value = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    let changed = install_prep_commands(&mut apps_json, selection, command)?;
+    if !changed.is_empty() {
+        config::write_atomically(&apps_backup_path(path), &content)?;
+        config::write_atomically(path, &format_like(&apps_json, &content)?)?;
+    }
+
+    Ok(changed)
+}
+
+// Serializes `value` using the indentation and line endings of `original`
+fn format_like(value: &Value, original: &str) -> Result<String> {
+    let indent = original
+        .lines()
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .find(|ws| !ws.is_empty())
+        .unwrap_or("    ");
+
+    let mut output = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+    let mut serializer = serde_json::Serializer::with_formatter(&mut output, formatter);
+    value.serialize(&mut serializer)?;
+
+    let mut output = String::from_utf8(output)?;
+    if original.contains("\r\n") {
+        output = output.replace('\n', "\r\n");
+    }
+    if original.ends_with('\n') {
+        output.push_str(if original.ends_with("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        });
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::mock::MockOutput;
+    use crate::platform::PlatformDisplayManager;
+    use std::collections::HashMap;
+
+    fn mode(width: u32, height: u32, refresh_rate: f64) -> DisplayMode {
+        DisplayMode {
+            width,
+            height,
+            refresh_rate,
+        }
+    }
+
+    fn mock_display_manager() -> DisplayManager {
+        let modes = vec![
+            mode(1920, 1080, 60.0),
+            mode(1920, 1080, 120.0),
+            mode(2560, 1440, 60.0),
+            mode(3840, 2160, 60.0),
+        ];
+
+        DisplayManager::with_platform(PlatformDisplayManager::mock(vec![
+            MockOutput {
+                name: "DP-1".to_string(),
+                primary: true,
+                modes: modes.clone(),
+                current: mode(2560, 1440, 60.0),
+                identity: None,
+            },
+            MockOutput {
+                name: "HDMI-1".to_string(),
+                primary: false,
+                modes,
+                current: mode(3840, 2160, 60.0),
+                identity: None,
+            },
+        ]))
+    }
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_client_env() -> Result<()> {
+        let env = ClientEnv::from_lookup(lookup(&[
+            ("SUNSHINE_CLIENT_WIDTH", "1920"),
+            ("SUNSHINE_CLIENT_HEIGHT", "1080"),
+            ("SUNSHINE_CLIENT_FPS", "120"),
+            ("SUNSHINE_CLIENT_HDR", "true"),
+        ]))?;
+
+        assert!(env.hdr);
+        assert_eq!(env.to_spec().to_string(), "1920x1080@120hz");
+
+        let env = ClientEnv::from_lookup(lookup(&[
+            ("SUNSHINE_CLIENT_WIDTH", "1280"),
+            ("SUNSHINE_CLIENT_HEIGHT", "800"),
+        ]))?;
+        assert!(!env.hdr);
+        assert_eq!(env.to_spec().to_string(), "1280x800");
+
+        assert!(ClientEnv::from_lookup(lookup(&[("SUNSHINE_CLIENT_WIDTH", "1920")])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_output_name() {
+        let conf = "# Sunshine config\nsunshine_name = living-room\noutput_name = HDMI-1 # TV\n";
+        assert_eq!(parse_output_name(conf), Some("HDMI-1".to_string()));
+        assert_eq!(parse_output_name("output_name =\n"), None);
+        assert_eq!(parse_output_name(""), None);
+    }
+
+    #[test]
+    fn test_install_prep_commands() -> Result<()> {
+        let original = r#"{
+  "env": {
+    "PATH": "$(PATH):$(HOME)/.local/bin"
+  },
+  "apps": [
+    {
+      "name": "Desktop",
+      "image-path": "desktop.png"
+    },
+    {
+      "name": "Steam Big Picture",
+      "prep-cmd": [
+        {
+          "do": "setsid steam steam://open/bigpicture",
+          "undo": "setsid steam steam://close/bigpicture"
+        }
+      ]
+    }
+  ]
+}
+"#;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("apps.json");
+        fs::write(&path, original)?;
+
+        let command = PrepCommand {
+            do_cmd: join_command(&[
+                "/usr/bin/display-switch".to_string(),
+                "sunshine".to_string(),
+                "do".to_string(),
+            ]),
+            undo_cmd: "/usr/bin/display-switch sunshine undo".to_string(),
+        };
+
+        let changed = update_apps_file(&path, &AppSelection::All, Some(&command))?;
+        assert_eq!(changed, vec!["Desktop", "Steam Big Picture"]);
+        assert_eq!(fs::read_to_string(apps_backup_path(&path))?, original);
+
+        let installed = fs::read_to_string(&path)?;
+        assert!(installed.starts_with("{\n  \"env\": {\n    \"PATH\""));
+        assert!(installed.ends_with("}\n"));
+
+        let apps_json: Value = serde_json::from_str(&installed)?;
+        let steam = &apps_json["apps"][1]["prep-cmd"];
+        assert_eq!(steam.as_array().unwrap().len(), 2);
+        assert_eq!(steam[0]["do"], "setsid steam steam://open/bigpicture");
+        assert_eq!(steam[1]["do"], "/usr/bin/display-switch sunshine do");
+
+        // Installing again is a no-op; changing the command updates in place
+        assert!(update_apps_file(&path, &AppSelection::All, Some(&command))?.is_empty());
+        let profile_command = PrepCommand {
+            do_cmd: "/usr/bin/display-switch sunshine do --profile \"Living Room\"".to_string(),
+            ..command.clone()
+        };
+        let selection = AppSelection::Named(vec!["Desktop".to_string()]);
+        assert_eq!(
+            update_apps_file(&path, &selection, Some(&profile_command))?,
+            vec!["Desktop"]
+        );
+        let apps_json: Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+        assert_eq!(
+            apps_json["apps"][0]["prep-cmd"].as_array().unwrap().len(),
+            1
+        );
+
+        let missing = AppSelection::Named(vec!["Missing".to_string()]);
+        assert!(update_apps_file(&path, &missing, Some(&command)).is_err());
+
+        update_apps_file(&path, &AppSelection::All, None)?;
+        let apps_json: Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+        assert_eq!(apps_json["apps"][0]["prep-cmd"], json!([]));
+        assert_eq!(
+            apps_json["apps"][1]["prep-cmd"].as_array().unwrap().len(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_display_switch_command() {
+        assert!(is_display_switch_command("display-switch sunshine do"));
+        assert!(is_display_switch_command(
+            r#""C:\Program Files\display-switch\display-switch.exe" sunshine undo"#
+        ));
+        assert!(!is_display_switch_command(
+            "setsid steam steam://open/bigpicture"
+        ));
+        assert!(!is_display_switch_command("echo display-switch"));
+    }
+
+    #[tokio::test]
+    async fn test_do_and_undo() -> Result<()> {
+        let display_manager = mock_display_manager();
+        let state_dir = tempfile::tempdir()?;
+        let state_file = state_dir.path().join("sunshine-state.json");
+
+        let output = resolve_output(&display_manager, "1").await?;
+        assert_eq!(output, "HDMI-1");
+
+        let env = ClientEnv::from_lookup(lookup(&[
+            ("SUNSHINE_CLIENT_WIDTH", "1920"),
+            ("SUNSHINE_CLIENT_HEIGHT", "1080"),
+            ("SUNSHINE_CLIENT_FPS", "120"),
+        ]))?;
+
+        let (_, applied) = apply(
+            &display_manager,
+            Some(&output),
+            &[env.to_spec()],
+            &MatchOptions::default(),
+            &state_file,
+        )
+        .await?;
+        assert_eq!(applied, mode(1920, 1080, 120.0));
+        assert_eq!(
+            display_manager
+                .get_current_display_mode(Some("DP-1"))
+                .await?,
+            mode(2560, 1440, 60.0)
+        );
+
+        // A second `do` must not overwrite the originally saved state
+        let fallback: DisplaySpec = "1440p@60hz".parse()?;
+        let exact = MatchOptions {
+            exact: true,
+            ..Default::default()
+        };
+        let (spec, _) = apply(
+            &display_manager,
+            Some(&output),
+            &["1280x800@60hz".parse()?, fallback.clone()],
+            &exact,
+            &state_file,
+        )
+        .await?;
+        assert_eq!(spec, fallback);
+
+        let restored = restore(&display_manager, &state_file).await?.unwrap();
+        assert_eq!(restored.mode, mode(3840, 2160, 60.0));
+        assert_eq!(
+            display_manager
+                .get_current_display_mode(Some("HDMI-1"))
+                .await?,
+            mode(3840, 2160, 60.0)
+        );
+        assert!(restore(&display_manager, &state_file).await?.is_none());
+
+        Ok(())
+    }
+}
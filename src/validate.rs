@@ -0,0 +1,336 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::display::{DisplayManager, DisplayMode, MatchOptions};
+use crate::parser::Variables;
+use crate::profile::ProfileManager;
+
+/// How one specification fares against the modes of its output
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum SpecStatus {
+    /// A mode matches the specification as written
+    Exact { mode: DisplayMode },
+    /// Only the closest-match rules find a mode
+    Approximate { mode: DisplayMode },
+    /// No mode of the output fits
+    Unsatisfiable,
+    /// The specification cannot be parsed or expanded
+    Invalid { error: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpecReport {
+    pub spec: String,
+    #[serde(flatten)]
+    pub status: SpecStatus,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryReport {
+    /// The entry as written in the profile, e.g. `HDMI-1` or `default`
+    pub entry: String,
+    /// The connected output it resolved to, if any
+    pub output: Option<String>,
+    pub optional: bool,
+    pub specs: Vec<SpecReport>,
+    /// Whether a switch would succeed for this entry
+    pub ok: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileReport {
+    pub name: String,
+    /// Why the profile could not be checked at all
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub outputs: Vec<EntryReport>,
+    pub ok: bool,
+}
+
+/// Checks every specification of a resolved profile against the modes the
+/// display manager reports, without switching anything
+pub async fn validate_profile(
+    display_manager: &DisplayManager,
+    profile_manager: &ProfileManager,
+    name: &str,
+    variables: &Variables,
+    options: &MatchOptions,
+) -> Result<ProfileReport> {
+    let profile = match profile_manager.resolved_profile(name) {
+        Ok(profile) => profile,
+        Err(e) => {
+            return Ok(ProfileReport {
+                name: name.to_string(),
+                error: Some(format!("{e:#}")),
+                outputs: Vec::new(),
+                ok: false,
+            })
+        }
+    };
+    let connected = display_manager.list_outputs().await?;
+
+    let mut outputs = Vec::new();
+    for entry in &profile.outputs {
+        let output = if entry.is_default_output() {
+            connected.iter().find(|o| o.primary)
+        } else {
+            entry.find_output(&connected)
+        };
+
+        let mut specs = Vec::new();
+        if let Some(output) = output {
+            for stored in &entry.specs {
+                let status = match stored.parse(variables) {
+                    Ok(spec) => {
+                        let exact = MatchOptions {
+                            exact: true,
+                            ..*options
+                        };
+                        let closest = MatchOptions {
+                            exact: false,
+                            ..*options
+                        };
+                        let name = Some(output.name.as_str());
+                        if let Ok(mode) = display_manager.resolve_mode(name, &spec, &exact).await {
+                            SpecStatus::Exact { mode }
+                        } else if let Ok(mode) =
+                            display_manager.resolve_mode(name, &spec, &closest).await
+                        {
+                            SpecStatus::Approximate { mode }
+                        } else {
+                            SpecStatus::Unsatisfiable
+                        }
+                    }
+                    Err(e) => SpecStatus::Invalid {
+                        error: format!("{e:#}"),
+                    },
+                };
+                specs.push(SpecReport {
+                    spec: stored.to_string(),
+                    status,
+                });
+            }
+        }
+
+        // With exact matching on, an approximate match would not be applied
+        let satisfiable = specs.iter().any(|report| match report.status {
+            SpecStatus::Exact { .. } => true,
+            SpecStatus::Approximate { .. } => !options.exact,
+            _ => false,
+        });
+        outputs.push(EntryReport {
+            entry: entry.to_string(),
+            output: output.map(|o| o.name.clone()),
+            optional: entry.optional,
+            ok: satisfiable || (output.is_none() && entry.optional),
+            specs,
+        });
+    }
+
+    Ok(ProfileReport {
+        name: name.to_string(),
+        error: None,
+        ok: outputs.iter().all(|entry| entry.ok),
+        outputs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{mock, PlatformDisplayManager};
+
+    #[tokio::test]
+    async fn test_validate_profile() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_file = dir.path().join("profiles.json");
+        std::fs::write(
+            &config_file,
+            r#"{"version": 2, "profiles": {
+                "tv": {"outputs": [
+                    {"specs": ["4k@60hz", "1080p@75hz", "21:9"]},
+                    {"output": "MOCK-2", "specs": ["${SIZE}"]},
+                    {"output": "HDMI-9", "specs": ["1080p"], "optional": true}
+                ]},
+                "ultrawide": ["21:9", "32:9"],
+                "orphan": {"extends": "missing"}
+            }}"#,
+        )?;
+        let profile_manager = ProfileManager::new(Some(config_file))?;
+        let display_manager =
+            DisplayManager::with_platform(PlatformDisplayManager::mock(mock::default_outputs()));
+        let validate = |name: &'static str, options: MatchOptions| {
+            let display_manager = &display_manager;
+            let profile_manager = &profile_manager;
+            async move {
+                validate_profile(
+                    display_manager,
+                    profile_manager,
+                    name,
+                    &Variables::default(),
+                    &options,
+                )
+                .await
+            }
+        };
+
+        let tv = validate("tv", MatchOptions::default()).await?;
+        let statuses: Vec<_> = tv.outputs[0].specs.iter().map(|s| &s.status).collect();
+        assert!(matches!(statuses[0], SpecStatus::Exact { mode } if mode.refresh_rate == 60.0));
+        assert!(matches!(statuses[1], SpecStatus::Approximate { mode } if mode.width == 1920));
+        assert_eq!(statuses[2], &SpecStatus::Unsatisfiable);
+        assert!(matches!(
+            tv.outputs[1].specs[0].status,
+            SpecStatus::Invalid { .. }
+        ));
+        assert!(!tv.outputs[1].ok);
+        assert!(tv.outputs[2].ok && tv.outputs[2].output.is_none());
+        assert!(!tv.ok);
+
+        assert!(!validate("ultrawide", MatchOptions::default()).await?.ok);
+        let orphan = validate("orphan", MatchOptions::default()).await?;
+        assert!(orphan.error.unwrap().contains("unknown profile"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_against_snapshot() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let snapshot = dir.path().join("snapshot.json");
+        std::fs::write(
+            &snapshot,
+            r#"[
+                {"name": "DP-1", "primary": true,
+                 "modes": [
+                    {"width": 2560, "height": 1440, "refresh_rate": 144.0},
+                    {"width": 2560, "height": 1440, "refresh_rate": 60.0},
+                    {"width": 1920, "height": 1080, "refresh_rate": 60.0}
+                 ],
+                 "current": {"width": 2560, "height": 1440, "refresh_rate": 144.0}},
+                {"name": "HDMI-1", "primary": false,
+                 "modes": [
+                    {"width": 1920, "height": 1080, "refresh_rate": 60.0},
+                    {"width": 1280, "height": 720, "refresh_rate": 60.0}
+                 ],
+                 "current": {"width": 1920, "height": 1080, "refresh_rate": 60.0}}
+            ]"#,
+        )?;
+        let config_file = dir.path().join("profiles.json");
+        std::fs::write(
+            &config_file,
+            r#"{"version": 2, "profiles": {
+                "desk": {"outputs": [
+                    {"specs": ["2560x1440@144hz", "2560x1440@100hz", "32:9"]},
+                    {"output": "HDMI-1", "specs": ["720p"]},
+                    {"output": "DP-9", "specs": ["1080p"], "optional": true}
+                ]},
+                "tv": {"outputs": [{"output": "HDMI-1", "specs": ["1080p@75hz"]}]},
+                "projector": {"outputs": [
+                    {"output": "HDMI-1", "specs": ["1080p"]},
+                    {"output": "DP-9", "specs": ["1080p"]}
+                ]}
+            }}"#,
+        )?;
+        let profile_manager = ProfileManager::new(Some(config_file))?;
+        let display_manager = DisplayManager::from_snapshot(&snapshot)?;
+        let exact = MatchOptions {
+            exact: true,
+            ..MatchOptions::default()
+        };
+        let validate = |name: &'static str, options: MatchOptions| {
+            let display_manager = &display_manager;
+            let profile_manager = &profile_manager;
+            async move {
+                validate_profile(
+                    display_manager,
+                    profile_manager,
+                    name,
+                    &Variables::default(),
+                    &options,
+                )
+                .await
+            }
+        };
+        let mode = |width, height, refresh_rate| DisplayMode {
+            width,
+            height,
+            refresh_rate,
+        };
+
+        let desk = validate("desk", MatchOptions::default()).await?;
+        // The default entry is the snapshot's primary output
+        assert_eq!(desk.outputs[0].output.as_deref(), Some("DP-1"));
+        let statuses: Vec<_> = desk.outputs[0].specs.iter().map(|s| &s.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                &SpecStatus::Exact {
+                    mode: mode(2560, 1440, 144.0)
+                },
+                &SpecStatus::Approximate {
+                    mode: mode(2560, 1440, 144.0)
+                },
+                &SpecStatus::Unsatisfiable,
+            ]
+        );
+        assert_eq!(
+            desk.outputs[1].specs[0].status,
+            SpecStatus::Exact {
+                mode: mode(1280, 720, 60.0)
+            }
+        );
+        // A missing optional output is skipped rather than checked
+        let missing = &desk.outputs[2];
+        assert!(missing.ok && missing.optional);
+        assert!(missing.output.is_none() && missing.specs.is_empty());
+        assert!(desk.ok);
+        assert!(validate("desk", exact).await?.ok);
+
+        // An approximate match only counts when it would be applied
+        let tv = validate("tv", MatchOptions::default()).await?;
+        assert_eq!(
+            tv.outputs[0].specs[0].status,
+            SpecStatus::Approximate {
+                mode: mode(1920, 1080, 60.0)
+            }
+        );
+        assert!(tv.ok);
+        assert!(!validate("tv", exact).await?.ok);
+
+        // A missing output that is not optional fails the profile
+        let projector = validate("projector", MatchOptions::default()).await?;
+        assert!(projector.outputs[0].ok);
+        assert!(!projector.outputs[1].ok && projector.outputs[1].output.is_none());
+        assert!(!projector.ok);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("snapshot.json");
+        let live =
+            DisplayManager::with_platform(PlatformDisplayManager::mock(mock::default_outputs()));
+        let snapshot = live.snapshot().await?;
+        std::fs::write(&path, serde_json::to_string_pretty(&snapshot)?)?;
+
+        let loaded = DisplayManager::from_snapshot(&path)?;
+        let names = |outputs: Vec<crate::display::OutputInfo>| -> Vec<_> {
+            outputs.into_iter().map(|o| (o.name, o.primary)).collect()
+        };
+        assert_eq!(
+            names(loaded.list_outputs().await?),
+            names(live.list_outputs().await?)
+        );
+        assert_eq!(
+            loaded.list_available_modes(Some("MOCK-1")).await?,
+            live.list_available_modes(Some("MOCK-1")).await?
+        );
+
+        assert!(DisplayManager::from_snapshot(&dir.path().join("missing.json")).is_err());
+        std::fs::write(&path, "not json")?;
+        assert!(DisplayManager::from_snapshot(&path).is_err());
+        Ok(())
+    }
+}